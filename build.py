@@ -63,13 +63,11 @@ def main():
             os.system('mkdir -p tmpdeb/usr/share/rustdesk/files/systemd/')
             os.system(
                 'cp rustdesk.service tmpdeb/usr/share/rustdesk/files/systemd/')
-            os.system('cp pynput_service.py tmpdeb/usr/share/rustdesk/files/')
             os.system('cp DEBIAN/* tmpdeb/DEBIAN/')
             os.system('strip tmpdeb/usr/bin/rustdesk')
             os.system('mkdir -p tmpdeb/usr/lib/rustdesk')
             os.system('cp libsciter-gtk.so tmpdeb/usr/lib/rustdesk/')
             md5_file('usr/share/rustdesk/files/systemd/rustdesk.service')
-            md5_file('usr/share/rustdesk/files/pynput_service.py')
             md5_file('usr/lib/rustdesk/libsciter-gtk.so')
             os.system('dpkg-deb -b tmpdeb rustdesk.deb; /bin/rm -rf tmpdeb/')
             os.rename('rustdesk.deb', 'rustdesk-%s.deb'%version)
@@ -1,21 +1,27 @@
+#[cfg(target_os = "windows")]
 use cliprdr::*;
-use hbb_common::{
-    log,
-    tokio::sync::{
-        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-        Mutex as TokioMutex,
-    },
-    ResultType,
+use hbb_common::tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Mutex as TokioMutex,
 };
+#[cfg(target_os = "windows")]
+use hbb_common::{log, ResultType};
 use serde_derive::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
 use std::{
     boxed::Box,
-    collections::HashMap,
     ffi::{CStr, CString},
-    sync::Mutex,
 };
+use std::{collections::HashMap, sync::Mutex};
 
 pub mod cliprdr;
+#[cfg(target_os = "linux")]
+mod x11;
+
+#[cfg(target_os = "windows")]
+pub use cliprdr::CliprdrClientContext;
+#[cfg(target_os = "linux")]
+pub use x11::{create_cliprdr_context, empty_clipboard, server_clip_file, CliprdrClientContext};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "t", content = "c")]
@@ -82,10 +88,12 @@ pub fn set_conn_enabled(conn_id: i32, enabled: bool) {
     }
 }
 
+#[cfg(target_os = "windows")]
 pub fn empty_clipboard(context: &mut Box<CliprdrClientContext>, conn_id: i32) -> bool {
     unsafe { TRUE == cliprdr::empty_cliprdr(&mut (**context), conn_id as u32) }
 }
 
+#[cfg(target_os = "windows")]
 pub fn server_clip_file(
     context: &mut Box<CliprdrClientContext>,
     s_conn_id: i32,
@@ -194,6 +202,7 @@ pub fn server_clip_file(
     }
 }
 
+#[cfg(target_os = "windows")]
 pub fn server_format_list(
     context: &mut Box<CliprdrClientContext>,
     conn_id: i32,
@@ -243,6 +252,7 @@ pub fn server_format_list(
         ret as u32
     }
 }
+#[cfg(target_os = "windows")]
 pub fn server_format_list_response(
     context: &mut Box<CliprdrClientContext>,
     conn_id: i32,
@@ -262,6 +272,7 @@ pub fn server_format_list_response(
         ret as u32
     }
 }
+#[cfg(target_os = "windows")]
 pub fn server_format_data_request(
     context: &mut Box<CliprdrClientContext>,
     conn_id: i32,
@@ -280,6 +291,7 @@ pub fn server_format_data_request(
         ret as u32
     }
 }
+#[cfg(target_os = "windows")]
 pub fn server_format_data_response(
     context: &mut Box<CliprdrClientContext>,
     conn_id: i32,
@@ -301,6 +313,7 @@ pub fn server_format_data_response(
         ret as u32
     }
 }
+#[cfg(target_os = "windows")]
 pub fn server_file_contents_request(
     context: &mut Box<CliprdrClientContext>,
     conn_id: i32,
@@ -335,6 +348,7 @@ pub fn server_file_contents_request(
         ret as u32
     }
 }
+#[cfg(target_os = "windows")]
 pub fn server_file_contents_response(
     context: &mut Box<CliprdrClientContext>,
     conn_id: i32,
@@ -360,6 +374,7 @@ pub fn server_file_contents_response(
     }
 }
 
+#[cfg(target_os = "windows")]
 pub fn create_cliprdr_context(
     enable_files: bool,
     enable_others: bool,
@@ -377,6 +392,7 @@ pub fn create_cliprdr_context(
     )?)
 }
 
+#[cfg(target_os = "windows")]
 extern "C" fn check_enabled(conn_id: UINT32) -> BOOL {
     let lock = CLIP_CONN_ENABLED.lock().unwrap();
 
@@ -392,6 +408,7 @@ extern "C" fn check_enabled(conn_id: UINT32) -> BOOL {
     return if connd_enabled { TRUE } else { FALSE };
 }
 
+#[cfg(target_os = "windows")]
 extern "C" fn client_format_list(
     _context: *mut CliprdrClientContext,
     clip_format_list: *const CLIPRDR_FORMAT_LIST,
@@ -432,6 +449,7 @@ extern "C" fn client_format_list(
     0
 }
 
+#[cfg(target_os = "windows")]
 extern "C" fn client_format_list_response(
     _context: *mut CliprdrClientContext,
     format_list_response: *const CLIPRDR_FORMAT_LIST_RESPONSE,
@@ -451,6 +469,7 @@ extern "C" fn client_format_list_response(
     0
 }
 
+#[cfg(target_os = "windows")]
 extern "C" fn client_format_data_request(
     _context: *mut CliprdrClientContext,
     format_data_request: *const CLIPRDR_FORMAT_DATA_REQUEST,
@@ -473,6 +492,7 @@ extern "C" fn client_format_data_request(
     0
 }
 
+#[cfg(target_os = "windows")]
 extern "C" fn client_format_data_response(
     _context: *mut CliprdrClientContext,
     format_data_response: *const CLIPRDR_FORMAT_DATA_RESPONSE,
@@ -506,6 +526,7 @@ extern "C" fn client_format_data_response(
     0
 }
 
+#[cfg(target_os = "windows")]
 extern "C" fn client_file_contents_request(
     _context: *mut CliprdrClientContext,
     file_contents_request: *const CLIPRDR_FILE_CONTENTS_REQUEST,
@@ -560,6 +581,7 @@ extern "C" fn client_file_contents_request(
     0
 }
 
+#[cfg(target_os = "windows")]
 extern "C" fn client_file_contents_response(
     _context: *mut CliprdrClientContext,
     file_contents_response: *const CLIPRDR_FILE_CONTENTS_RESPONSE,
@@ -0,0 +1,1078 @@
+// File copy and paste through the X11 clipboard, speaking the same cliprdr messages as
+// the Windows implementation. The file list of the clipboard is announced as
+// FileGroupDescriptorW. The file list of the peer is put on the clipboard as soon as it is
+// announced, the files themselves are downloaded into a private directory when pasted.
+use super::{ClipbaordFile, CLIP_CONN_ENABLED, MSG_CHANNEL_CLIENT};
+use hbb_common::{
+    anyhow::anyhow,
+    bail,
+    config::{Config, APP_NAME},
+    log, ResultType,
+};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    os::{
+        raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_void},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+const FORMAT_FILE_DESCRIPTOR: &str = "FileGroupDescriptorW";
+const FORMAT_FILE_CONTENTS: &str = "FileContents";
+// the ids of the registered formats are local, the peer echoes them back
+const FILE_DESCRIPTOR_ID: i32 = 0xC0A0;
+const FILE_CONTENTS_ID: i32 = 0xC0A1;
+
+const CB_RESPONSE_OK: i32 = 0x0001;
+const CB_RESPONSE_FAIL: i32 = 0x0002;
+const FILECONTENTS_SIZE: i32 = 0x0001;
+const FILECONTENTS_RANGE: i32 = 0x0002;
+
+// FILEDESCRIPTORW
+const FILE_DESCRIPTOR_SIZE: usize = 592;
+const FD_ATTRIBUTES: u32 = 0x0004;
+const FD_WRITESTIME: u32 = 0x0020;
+const FD_FILESIZE: u32 = 0x0040;
+const FD_UNICODE: u32 = 0x8000_0000;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x0010;
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x0080;
+const MAX_PATH: usize = 260;
+// between 1601-01-01 and 1970-01-01
+const FILETIME_UNIX_EPOCH: u64 = 11_644_473_600;
+
+const CHUNK_SIZE: u64 = 512 * 1024;
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+const SELECTION_TIMEOUT: Duration = Duration::from_millis(100);
+
+const SELECTION_CLEAR: c_int = 29;
+const SELECTION_REQUEST: c_int = 30;
+const SELECTION_NOTIFY: c_int = 31;
+const XA_ATOM: c_ulong = 4;
+const PROP_MODE_REPLACE: c_int = 0;
+
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+struct XSelectionRequestEvent {
+    type_: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut c_void,
+    owner: c_ulong,
+    requestor: c_ulong,
+    selection: c_ulong,
+    target: c_ulong,
+    property: c_ulong,
+    time: c_ulong,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+struct XSelectionEvent {
+    type_: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut c_void,
+    requestor: c_ulong,
+    selection: c_ulong,
+    target: c_ulong,
+    property: c_ulong,
+    time: c_ulong,
+}
+
+#[repr(C)]
+union XEvent {
+    type_: c_int,
+    selection_request: XSelectionRequestEvent,
+    selection: XSelectionEvent,
+    pad: [c_long; 24],
+}
+
+extern "C" {
+    fn getuid() -> c_uint;
+}
+
+#[link(name = "X11")]
+extern "C" {
+    fn XOpenDisplay(display_name: *const c_char) -> *mut c_void;
+    fn XCloseDisplay(display: *mut c_void) -> c_int;
+    fn XDefaultRootWindow(display: *mut c_void) -> c_ulong;
+    fn XCreateSimpleWindow(
+        display: *mut c_void,
+        parent: c_ulong,
+        x: c_int,
+        y: c_int,
+        width: u32,
+        height: u32,
+        border_width: u32,
+        border: c_ulong,
+        background: c_ulong,
+    ) -> c_ulong;
+    fn XDestroyWindow(display: *mut c_void, w: c_ulong) -> c_int;
+    fn XInternAtom(display: *mut c_void, name: *const c_char, only_if_exists: c_int) -> c_ulong;
+    fn XGetSelectionOwner(display: *mut c_void, selection: c_ulong) -> c_ulong;
+    fn XSetSelectionOwner(
+        display: *mut c_void,
+        selection: c_ulong,
+        owner: c_ulong,
+        time: c_ulong,
+    ) -> c_int;
+    fn XConvertSelection(
+        display: *mut c_void,
+        selection: c_ulong,
+        target: c_ulong,
+        property: c_ulong,
+        requestor: c_ulong,
+        time: c_ulong,
+    ) -> c_int;
+    fn XCheckTypedWindowEvent(
+        display: *mut c_void,
+        w: c_ulong,
+        event_type: c_int,
+        event: *mut XEvent,
+    ) -> c_int;
+    fn XPending(display: *mut c_void) -> c_int;
+    fn XNextEvent(display: *mut c_void, event: *mut XEvent) -> c_int;
+    fn XSendEvent(
+        display: *mut c_void,
+        w: c_ulong,
+        propagate: c_int,
+        event_mask: c_long,
+        event: *mut XEvent,
+    ) -> c_int;
+    fn XGetWindowProperty(
+        display: *mut c_void,
+        w: c_ulong,
+        property: c_ulong,
+        long_offset: c_long,
+        long_length: c_long,
+        delete: c_int,
+        req_type: c_ulong,
+        actual_type: *mut c_ulong,
+        actual_format: *mut c_int,
+        nitems: *mut c_ulong,
+        bytes_after: *mut c_ulong,
+        prop: *mut *mut c_uchar,
+    ) -> c_int;
+    fn XChangeProperty(
+        display: *mut c_void,
+        w: c_ulong,
+        property: c_ulong,
+        property_type: c_ulong,
+        format: c_int,
+        mode: c_int,
+        data: *const c_uchar,
+        nelements: c_int,
+    ) -> c_int;
+    fn XFlush(display: *mut c_void) -> c_int;
+    fn XFree(data: *mut c_void) -> c_int;
+}
+
+#[derive(Clone)]
+struct LocalFile {
+    path: PathBuf,
+    // relative, separated by backslashes
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: u64,
+}
+
+struct RemoteFile {
+    path: PathBuf,
+    is_dir: bool,
+    size: Option<u64>,
+}
+
+struct Download {
+    // the connection id to echo to the peer
+    conn_id: i32,
+    key: i32,
+    dir: PathBuf,
+    // the contents are requested on the first paste
+    requested: bool,
+    files: Vec<RemoteFile>,
+    index: usize,
+    file: Option<File>,
+    offset: u64,
+    stream_id: i32,
+}
+
+#[derive(Default)]
+struct State {
+    // the files on the local clipboard which were announced to the peers
+    files: Vec<LocalFile>,
+    downloads: HashMap<i32, Download>,
+    // the downloaded files we own the clipboard with, and the connection they are from
+    owner: Option<(i32, Vec<PathBuf>)>,
+    owner_changed: bool,
+    // the pastes waiting for the files to be downloaded
+    pending: Vec<XSelectionRequestEvent>,
+}
+
+pub struct CliprdrClientContext {
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for CliprdrClientContext {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+        let dir = get_process_dir();
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).ok();
+        }
+    }
+}
+
+pub fn create_cliprdr_context(
+    _enable_files: bool,
+    _enable_others: bool,
+) -> ResultType<Box<CliprdrClientContext>> {
+    let state = Arc::new(Mutex::new(State::default()));
+    let running = Arc::new(AtomicBool::new(true));
+    let (state_cloned, running_cloned) = (state.clone(), running.clone());
+    let thread = std::thread::spawn(move || unsafe { run(state_cloned, running_cloned) });
+    Ok(Box::new(CliprdrClientContext {
+        state,
+        running,
+        thread: Some(thread),
+    }))
+}
+
+pub fn empty_clipboard(context: &mut Box<CliprdrClientContext>, conn_id: i32) -> bool {
+    let mut state = context.state.lock().unwrap();
+    state.downloads.remove(&conn_id);
+    if state.owner.as_ref().map(|(id, _)| *id) == Some(conn_id) {
+        state.owner = None;
+        state.owner_changed = true;
+    }
+    remove_download_dir(conn_id);
+    true
+}
+
+pub fn server_clip_file(
+    context: &mut Box<CliprdrClientContext>,
+    s_conn_id: i32,
+    msg: ClipbaordFile,
+) -> u32 {
+    if s_conn_id != 0 && !is_enabled(s_conn_id) {
+        return 1;
+    }
+    match handle_msg(&context.state, s_conn_id, msg) {
+        Ok(()) => 0,
+        Err(err) => {
+            log::error!("Failed to handle the clipboard file message: {}", err);
+            1
+        }
+    }
+}
+
+fn handle_msg(state: &Mutex<State>, s_conn_id: i32, msg: ClipbaordFile) -> ResultType<()> {
+    // the downloads are keyed by `s_conn_id`, which is 0 on the client side
+    let get_conn_id = |conn_id: i32| if s_conn_id != 0 { s_conn_id } else { conn_id };
+    match msg {
+        ClipbaordFile::ServerFormatList {
+            conn_id,
+            format_list,
+        } => {
+            let conn_id = get_conn_id(conn_id);
+            send(
+                s_conn_id,
+                ClipbaordFile::ServerFormatListResponse {
+                    conn_id,
+                    msg_flags: CB_RESPONSE_OK,
+                },
+            );
+            let mut state = state.lock().unwrap();
+            state.fail(s_conn_id);
+            if let Some((id, _)) = format_list
+                .iter()
+                .find(|(_, name)| name == FORMAT_FILE_DESCRIPTOR)
+            {
+                state
+                    .downloads
+                    .insert(s_conn_id, Download::new(conn_id, s_conn_id));
+                send(
+                    s_conn_id,
+                    ClipbaordFile::ServerFormatDataRequest {
+                        conn_id,
+                        requested_format_id: *id,
+                    },
+                );
+            }
+        }
+        ClipbaordFile::ServerFormatListResponse { .. } => {}
+        ClipbaordFile::ServerFormatDataRequest {
+            conn_id,
+            requested_format_id,
+        } => {
+            let files = state.lock().unwrap().files.clone();
+            let (msg_flags, format_data) =
+                if requested_format_id == FILE_DESCRIPTOR_ID && !files.is_empty() {
+                    (CB_RESPONSE_OK, get_file_descriptors(&files))
+                } else {
+                    (CB_RESPONSE_FAIL, Vec::new())
+                };
+            send(
+                s_conn_id,
+                ClipbaordFile::ServerFormatDataResponse {
+                    conn_id: get_conn_id(conn_id),
+                    msg_flags,
+                    format_data,
+                },
+            );
+        }
+        ClipbaordFile::ServerFormatDataResponse {
+            msg_flags,
+            format_data,
+            ..
+        } => {
+            let mut state = state.lock().unwrap();
+            if let Some(download) = state.downloads.get_mut(&s_conn_id) {
+                if !download.files.is_empty() {
+                    return Ok(());
+                }
+                let res = if msg_flags == CB_RESPONSE_OK {
+                    download.start(&format_data)
+                } else {
+                    Err(anyhow!("The peer failed to provide the file list"))
+                };
+                if let Err(err) = res {
+                    state.fail(s_conn_id);
+                    return Err(err);
+                }
+                let paths = download.get_top_paths();
+                if let Some((old, _)) = state.owner.replace((s_conn_id, paths)) {
+                    if old != s_conn_id {
+                        state.downloads.remove(&old);
+                        remove_download_dir(old);
+                    }
+                }
+                state.owner_changed = true;
+            }
+        }
+        ClipbaordFile::FileContentsRequest {
+            conn_id,
+            stream_id,
+            list_index,
+            dw_flags,
+            n_position_low,
+            n_position_high,
+            cb_requested,
+            ..
+        } => {
+            let file = state
+                .lock()
+                .unwrap()
+                .files
+                .get(list_index as usize)
+                .cloned();
+            let position = (n_position_high as u32 as u64) << 32 | n_position_low as u32 as u64;
+            let res = match file {
+                Some(file) => read_file_contents(&file, dw_flags, position, cb_requested as u32),
+                None => Err(anyhow!("No file {} on the clipboard", list_index)),
+            };
+            let (msg_flags, requested_data) = match res {
+                Ok(data) => (CB_RESPONSE_OK, data),
+                Err(err) => {
+                    log::error!("Failed to read the clipboard file: {}", err);
+                    (CB_RESPONSE_FAIL, Vec::new())
+                }
+            };
+            send(
+                s_conn_id,
+                ClipbaordFile::FileContentsResponse {
+                    conn_id: get_conn_id(conn_id),
+                    msg_flags,
+                    stream_id,
+                    requested_data,
+                },
+            );
+        }
+        ClipbaordFile::FileContentsResponse {
+            msg_flags,
+            stream_id,
+            requested_data,
+            ..
+        } => {
+            let mut state = state.lock().unwrap();
+            if let Some(download) = state.downloads.get_mut(&s_conn_id) {
+                if download.stream_id != stream_id {
+                    return Ok(());
+                }
+                let res = if msg_flags == CB_RESPONSE_OK {
+                    download.write(requested_data)
+                } else {
+                    Err(anyhow!("The peer failed to provide the file contents"))
+                };
+                if let Err(err) = res {
+                    state.fail(s_conn_id);
+                    return Err(err);
+                }
+                state.advance(s_conn_id)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_enabled(conn_id: i32) -> bool {
+    let lock = CLIP_CONN_ENABLED.lock().unwrap();
+    lock.conn_enabled.get(&conn_id) == Some(&true)
+}
+
+#[inline]
+fn send(conn_id: i32, data: ClipbaordFile) {
+    // no need to handle result here
+    MSG_CHANNEL_CLIENT.0.send((conn_id, data)).ok();
+}
+
+impl State {
+    // requests the next part of the download, the waiting pastes are answered when done
+    fn advance(&mut self, key: i32) -> ResultType<()> {
+        let download = match self.downloads.get_mut(&key) {
+            Some(download) => download,
+            None => return Ok(()),
+        };
+        match download.next() {
+            Ok(Some(request)) => send(key, request),
+            Ok(None) => {
+                if let Some(download) = self.downloads.remove(&key) {
+                    log::info!("{} clipboard files received", download.files.len());
+                }
+            }
+            Err(err) => {
+                self.fail(key);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    // drops the download, and the clipboard if the files on it are not complete
+    fn fail(&mut self, key: i32) {
+        if self.downloads.remove(&key).is_some() {
+            if self.owner.as_ref().map(|(id, _)| *id) == Some(key) {
+                self.owner = None;
+                self.owner_changed = true;
+            }
+            remove_download_dir(key);
+        }
+    }
+
+    // starts the download of the files on the clipboard, false if they are already there
+    fn request_files(&mut self) -> bool {
+        let key = match self.owner.as_ref() {
+            Some((key, _)) => *key,
+            None => return false,
+        };
+        match self.downloads.get_mut(&key) {
+            Some(download) => {
+                if !download.requested {
+                    download.requested = true;
+                    if let Err(err) = self.advance(key) {
+                        log::error!("Failed to download the clipboard files: {}", err);
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Download {
+    fn new(conn_id: i32, key: i32) -> Self {
+        Self {
+            conn_id,
+            key,
+            dir: PathBuf::new(),
+            requested: false,
+            files: Vec::new(),
+            index: 0,
+            file: None,
+            offset: 0,
+            stream_id: 0,
+        }
+    }
+
+    fn start(&mut self, format_data: &[u8]) -> ResultType<()> {
+        self.files = parse_file_descriptors(format_data)?;
+        self.dir = get_download_dir(self.key)?;
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        Ok(())
+    }
+
+    // the request for the next part, None if all the files are received
+    fn next(&mut self) -> ResultType<Option<ClipbaordFile>> {
+        while let Some(f) = self.files.get(self.index) {
+            let path = self.dir.join(&f.path);
+            if f.is_dir {
+                std::fs::create_dir_all(&path)?;
+                self.index += 1;
+                continue;
+            }
+            let size = match f.size {
+                Some(size) => size,
+                None => return Ok(Some(self.request(FILECONTENTS_SIZE, 0, 8))),
+            };
+            if self.file.is_none() {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                self.file = Some(File::create(&path)?);
+                self.offset = 0;
+            }
+            if self.offset < size {
+                let n = (size - self.offset).min(CHUNK_SIZE);
+                return Ok(Some(self.request(FILECONTENTS_RANGE, self.offset, n)));
+            }
+            self.file = None;
+            self.index += 1;
+        }
+        Ok(None)
+    }
+
+    fn request(&mut self, dw_flags: i32, position: u64, cb_requested: u64) -> ClipbaordFile {
+        self.stream_id = self.stream_id.wrapping_add(1);
+        ClipbaordFile::FileContentsRequest {
+            conn_id: self.conn_id,
+            stream_id: self.stream_id,
+            list_index: self.index as _,
+            dw_flags,
+            n_position_low: position as u32 as _,
+            n_position_high: (position >> 32) as u32 as _,
+            cb_requested: cb_requested as _,
+            have_clip_data_id: false,
+            clip_data_id: 0,
+        }
+    }
+
+    fn write(&mut self, data: Vec<u8>) -> ResultType<()> {
+        let f = match self.files.get_mut(self.index) {
+            Some(f) => f,
+            None => bail!("Unexpected file contents"),
+        };
+        if f.size.is_none() {
+            if data.len() < 8 {
+                bail!("Invalid size of {}", f.path.display());
+            }
+            let mut size = [0u8; 8];
+            size.copy_from_slice(&data[..8]);
+            f.size = Some(u64::from_le_bytes(size));
+            return Ok(());
+        }
+        if data.is_empty() {
+            bail!("Unexpected end of {}", f.path.display());
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(&data)?;
+        }
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn get_top_paths(&self) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .filter(|f| f.path.components().count() == 1)
+            .map(|f| self.dir.join(&f.path))
+            .collect()
+    }
+}
+
+// in the cache directory of the user, not in the runtime one which is usually in memory
+fn get_download_base() -> PathBuf {
+    let cache = match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => Config::get_home().join(".cache"),
+    };
+    cache.join(format!("{}_clipboard", APP_NAME.to_lowercase()))
+}
+
+// the server and the client processes share the base
+#[inline]
+fn get_process_dir() -> PathBuf {
+    get_download_base().join(std::process::id().to_string())
+}
+
+fn get_download_dir(key: i32) -> ResultType<PathBuf> {
+    let base = get_download_base();
+    if let Some(parent) = base.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    create_private_dir(&base)?;
+    Ok(get_process_dir().join(key.to_string()))
+}
+
+// the files of the connection, when its clipboard is replaced or dropped
+fn remove_download_dir(key: i32) {
+    let dir = get_process_dir().join(key.to_string());
+    if dir.exists() {
+        if let Err(err) = std::fs::remove_dir_all(&dir) {
+            log::error!("Failed to remove {}: {}", dir.display(), err);
+        }
+    }
+}
+
+// refuses a directory prepared by someone else, a symlink could redirect the files
+fn create_private_dir(dir: &Path) -> ResultType<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    if let Err(err) = std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        if err.kind() != std::io::ErrorKind::AlreadyExists {
+            return Err(err.into());
+        }
+    }
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != unsafe { getuid() } || meta.mode() & 0o077 != 0 {
+        bail!("{} is not a private directory", dir.display());
+    }
+    Ok(())
+}
+
+fn read_file_contents(
+    file: &LocalFile,
+    dw_flags: i32,
+    position: u64,
+    cb_requested: u32,
+) -> ResultType<Vec<u8>> {
+    if dw_flags & FILECONTENTS_SIZE != 0 {
+        return Ok(file.size.to_le_bytes().to_vec());
+    }
+    if file.is_dir {
+        bail!("{} is a directory", file.path.display());
+    }
+    let mut f = File::open(&file.path)?;
+    f.seek(SeekFrom::Start(position))?;
+    let mut data = Vec::new();
+    f.take(cb_requested as _).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn get_file_descriptors(files: &[LocalFile]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 + files.len() * FILE_DESCRIPTOR_SIZE);
+    data.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    for f in files {
+        let mut fd = [0u8; FILE_DESCRIPTOR_SIZE];
+        let flags = FD_ATTRIBUTES | FD_WRITESTIME | FD_FILESIZE | FD_UNICODE;
+        let attributes = if f.is_dir {
+            FILE_ATTRIBUTE_DIRECTORY
+        } else {
+            FILE_ATTRIBUTE_NORMAL
+        };
+        fd[0..4].copy_from_slice(&flags.to_le_bytes());
+        fd[36..40].copy_from_slice(&attributes.to_le_bytes());
+        fd[56..64].copy_from_slice(&f.modified.to_le_bytes());
+        fd[64..68].copy_from_slice(&((f.size >> 32) as u32).to_le_bytes());
+        fd[68..72].copy_from_slice(&(f.size as u32).to_le_bytes());
+        for (i, c) in f.name.encode_utf16().enumerate() {
+            fd[72 + i * 2..74 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        data.extend_from_slice(&fd);
+    }
+    data
+}
+
+fn parse_file_descriptors(data: &[u8]) -> ResultType<Vec<RemoteFile>> {
+    let get_u32 = |b: &[u8], i: usize| u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+    if data.len() < 4 {
+        bail!("Invalid file list");
+    }
+    let count = get_u32(data, 0) as usize;
+    if data.len() < 4 + count * FILE_DESCRIPTOR_SIZE {
+        bail!("Invalid file list");
+    }
+    let mut files = Vec::new();
+    for fd in data[4..].chunks_exact(FILE_DESCRIPTOR_SIZE).take(count) {
+        let flags = get_u32(fd, 0);
+        let name: Vec<u16> = fd[72..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|c| *c != 0)
+            .collect();
+        let name = String::from_utf16_lossy(&name);
+        let path = match get_relative_path(&name) {
+            Some(path) => path,
+            None => bail!("Invalid file name: {}", name),
+        };
+        let size = if flags & FD_FILESIZE != 0 {
+            Some((get_u32(fd, 64) as u64) << 32 | get_u32(fd, 68) as u64)
+        } else {
+            None
+        };
+        files.push(RemoteFile {
+            path,
+            is_dir: get_u32(fd, 36) & FILE_ATTRIBUTE_DIRECTORY != 0,
+            size,
+        });
+    }
+    Ok(files)
+}
+
+// no absolute paths or parent directories
+fn get_relative_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in name.split(|c| c == '\\' || c == '/') {
+        if part.is_empty() || part == "." || part == ".." {
+            return None;
+        }
+        path.push(part);
+    }
+    Some(path)
+}
+
+fn get_local_files(paths: &[PathBuf]) -> Vec<LocalFile> {
+    let mut files = Vec::new();
+    for path in paths {
+        if let Some(name) = path.file_name() {
+            add_local_file(&mut files, path, name.to_string_lossy().into_owned());
+        }
+    }
+    files
+}
+
+fn add_local_file(files: &mut Vec<LocalFile>, path: &Path, name: String) {
+    if name.encode_utf16().count() >= MAX_PATH {
+        log::warn!("Skip the clipboard file with a too long name: {}", name);
+        return;
+    }
+    let meta = match std::fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return,
+    };
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| (d.as_secs() + FILETIME_UNIX_EPOCH) * 10_000_000 + d.subsec_nanos() as u64 / 100)
+        .unwrap_or(0);
+    files.push(LocalFile {
+        path: path.to_owned(),
+        name: name.clone(),
+        is_dir: meta.is_dir(),
+        size: if meta.is_dir() { 0 } else { meta.len() },
+        modified,
+    });
+    // the links to directories are not followed
+    let is_link = std::fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(true);
+    if meta.is_dir() && !is_link {
+        if let Ok(entries) = std::fs::read_dir(path) {
+            let mut entries: Vec<PathBuf> =
+                entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+            entries.sort();
+            for entry in entries {
+                if let Some(sub) = entry.file_name() {
+                    let sub = format!("{}\\{}", name, sub.to_string_lossy());
+                    add_local_file(files, &entry, sub);
+                }
+            }
+        }
+    }
+}
+
+// x-special/gnome-copied-files is "copy" or "cut" followed by the uris, one per line
+fn parse_uri_list(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix("file://"))
+        .filter_map(|uri| uri.find('/').map(|i| &uri[i..]))
+        .map(|path| PathBuf::from(OsString::from_vec(percent_decode(path))))
+        .collect()
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                res.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    res
+}
+
+fn get_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for b in path.as_os_str().as_bytes() {
+        match *b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                uri.push(*b as char)
+            }
+            b => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+struct Atoms {
+    clipboard: c_ulong,
+    property: c_ulong,
+    targets: c_ulong,
+    uri_list: c_ulong,
+    gnome_files: c_ulong,
+}
+
+impl Atoms {
+    unsafe fn new(d: *mut c_void) -> Self {
+        let atom = |name: &[u8]| XInternAtom(d, name.as_ptr() as _, 0);
+        Self {
+            clipboard: atom(b"CLIPBOARD\0"),
+            property: atom(b"RUSTDESK_CLIPBOARD_FILES\0"),
+            targets: atom(b"TARGETS\0"),
+            uri_list: atom(b"text/uri-list\0"),
+            gnome_files: atom(b"x-special/gnome-copied-files\0"),
+        }
+    }
+}
+
+unsafe fn run(state: Arc<Mutex<State>>, running: Arc<AtomicBool>) {
+    let d = XOpenDisplay(std::ptr::null());
+    if d.is_null() {
+        log::error!("Failed to open the display for the clipboard files");
+        return;
+    }
+    let window = XCreateSimpleWindow(d, XDefaultRootWindow(d), 0, 0, 1, 1, 0, 0, 0);
+    let atoms = Atoms::new(d);
+    let mut last_paths: Option<Vec<PathBuf>> = None;
+    let mut last_check: Option<Instant> = None;
+    while running.load(Ordering::SeqCst) {
+        {
+            let mut state = state.lock().unwrap();
+            if state.owner_changed {
+                state.owner_changed = false;
+                if state.owner.is_some() {
+                    XSetSelectionOwner(d, atoms.clipboard, window, 0);
+                } else if XGetSelectionOwner(d, atoms.clipboard) == window {
+                    XSetSelectionOwner(d, atoms.clipboard, 0, 0);
+                }
+                XFlush(d);
+            }
+        }
+        while XPending(d) > 0 {
+            let mut event = XEvent { pad: [0; 24] };
+            XNextEvent(d, &mut event);
+            match event.type_ {
+                SELECTION_REQUEST => {
+                    let request = event.selection_request;
+                    let mut state = state.lock().unwrap();
+                    // the files are downloaded on the first paste, which waits for them,
+                    // the other targets are answered at once
+                    let is_files =
+                        request.target == atoms.gnome_files || request.target == atoms.uri_list;
+                    if is_files && state.request_files() {
+                        state.pending.push(request);
+                        continue;
+                    }
+                    let paths = state.owner.clone().map(|(_, paths)| paths);
+                    reply_selection(d, &atoms, request, paths);
+                }
+                SELECTION_CLEAR => {
+                    let mut state = state.lock().unwrap();
+                    if let Some((key, _)) = state.owner.take() {
+                        state.downloads.remove(&key);
+                        remove_download_dir(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+        {
+            let mut state = state.lock().unwrap();
+            let downloading = state
+                .owner
+                .as_ref()
+                .map_or(false, |(key, _)| state.downloads.contains_key(key));
+            if !downloading && !state.pending.is_empty() {
+                let paths = state.owner.clone().map(|(_, paths)| paths);
+                for request in std::mem::take(&mut state.pending) {
+                    reply_selection(d, &atoms, request, paths.clone());
+                }
+            }
+        }
+        if last_check.map(|t| t.elapsed() >= CHECK_INTERVAL) != Some(false)
+            && XGetSelectionOwner(d, atoms.clipboard) != window
+        {
+            last_check = Some(Instant::now());
+            let paths = read_selection(d, window, &atoms, atoms.gnome_files)
+                .or_else(|| read_selection(d, window, &atoms, atoms.uri_list))
+                .map(|text| parse_uri_list(&text))
+                .filter(|paths| !paths.is_empty());
+            if paths != last_paths {
+                let files = paths
+                    .as_ref()
+                    .map(|paths| get_local_files(paths))
+                    .unwrap_or_default();
+                let announce = !files.is_empty();
+                state.lock().unwrap().files = files;
+                if announce {
+                    send(
+                        0,
+                        ClipbaordFile::ServerFormatList {
+                            conn_id: 0,
+                            format_list: vec![
+                                (FILE_DESCRIPTOR_ID, FORMAT_FILE_DESCRIPTOR.to_owned()),
+                                (FILE_CONTENTS_ID, FORMAT_FILE_CONTENTS.to_owned()),
+                            ],
+                        },
+                    );
+                }
+                last_paths = paths;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    if XGetSelectionOwner(d, atoms.clipboard) == window {
+        XSetSelectionOwner(d, atoms.clipboard, 0, 0);
+    }
+    XDestroyWindow(d, window);
+    XCloseDisplay(d);
+}
+
+unsafe fn read_selection(
+    d: *mut c_void,
+    window: c_ulong,
+    atoms: &Atoms,
+    target: c_ulong,
+) -> Option<String> {
+    XConvertSelection(d, atoms.clipboard, target, atoms.property, window, 0);
+    XFlush(d);
+    let mut event = XEvent { pad: [0; 24] };
+    let start = Instant::now();
+    while XCheckTypedWindowEvent(d, window, SELECTION_NOTIFY, &mut event) == 0 {
+        if start.elapsed() > SELECTION_TIMEOUT {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    // None if the owner has no such target
+    if event.selection.property == 0 {
+        return None;
+    }
+    let mut actual_type = 0;
+    let mut format = 0;
+    let mut nitems = 0;
+    let mut bytes_after = 0;
+    let mut data = std::ptr::null_mut();
+    if XGetWindowProperty(
+        d,
+        window,
+        atoms.property,
+        0,
+        c_long::MAX / 4,
+        1,
+        0,
+        &mut actual_type,
+        &mut format,
+        &mut nitems,
+        &mut bytes_after,
+        &mut data,
+    ) != 0
+        || data.is_null()
+    {
+        return None;
+    }
+    let res = if format == 8 {
+        let bytes = std::slice::from_raw_parts(data, nitems as _);
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    } else {
+        None
+    };
+    XFree(data as _);
+    res
+}
+
+unsafe fn reply_selection(
+    d: *mut c_void,
+    atoms: &Atoms,
+    request: XSelectionRequestEvent,
+    paths: Option<Vec<PathBuf>>,
+) {
+    // obsolete clients leave the property empty
+    let property = if request.property == 0 {
+        request.target
+    } else {
+        request.property
+    };
+    let mut replied = false;
+    if let Some(paths) = paths {
+        if request.target == atoms.targets {
+            // no text, the paths would be synced to the peers as the text clipboard
+            let targets = [atoms.targets, atoms.gnome_files, atoms.uri_list];
+            XChangeProperty(
+                d,
+                request.requestor,
+                property,
+                XA_ATOM,
+                32,
+                PROP_MODE_REPLACE,
+                targets.as_ptr() as _,
+                targets.len() as _,
+            );
+            replied = true;
+        } else {
+            let uris: Vec<String> = paths.iter().map(|p| get_uri(p)).collect();
+            let text = if request.target == atoms.gnome_files {
+                Some(format!("copy\n{}", uris.join("\n")))
+            } else if request.target == atoms.uri_list {
+                Some(uris.iter().map(|uri| format!("{}\r\n", uri)).collect())
+            } else {
+                None
+            };
+            if let Some(text) = text {
+                XChangeProperty(
+                    d,
+                    request.requestor,
+                    property,
+                    request.target,
+                    8,
+                    PROP_MODE_REPLACE,
+                    text.as_ptr(),
+                    text.len() as _,
+                );
+                replied = true;
+            }
+        }
+    }
+    let mut event = XEvent {
+        selection: XSelectionEvent {
+            type_: SELECTION_NOTIFY,
+            serial: 0,
+            send_event: 1,
+            display: d,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: if replied { property } else { 0 },
+            time: request.time,
+        },
+    };
+    XSendEvent(d, request.requestor, 0, 0, &mut event);
+    XFlush(d);
+}
@@ -1,6 +1,96 @@
-use crate::{Key, KeyboardControllable};
+use crate::{Key, KeyboardControllable, MouseButton, MouseControllable};
 use std::error::Error;
 use std::fmt;
+use std::time::{Duration, Instant};
+
+const MAX_REPEAT: u32 = 1000;
+const MAX_TOKENS: usize = 100_000;
+const MIN_RECORD_DELAY: u64 = 10;
+
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("ALT", Key::Alt),
+    ("BACKSPACE", Key::Backspace),
+    ("CAPSLOCK", Key::CapsLock),
+    ("CTRL", Key::Control),
+    ("DELETE", Key::Delete),
+    ("DOWN", Key::DownArrow),
+    ("END", Key::End),
+    ("ESC", Key::Escape),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("HOME", Key::Home),
+    ("LEFT", Key::LeftArrow),
+    ("META", Key::Meta),
+    ("PGDN", Key::PageDown),
+    ("PGUP", Key::PageUp),
+    ("ENTER", Key::Return),
+    ("RIGHT", Key::RightArrow),
+    ("SHIFT", Key::Shift),
+    ("SPACE", Key::Space),
+    ("TAB", Key::Tab),
+    ("UP", Key::UpArrow),
+    ("NUMPAD0", Key::Numpad0),
+    ("NUMPAD1", Key::Numpad1),
+    ("NUMPAD2", Key::Numpad2),
+    ("NUMPAD3", Key::Numpad3),
+    ("NUMPAD4", Key::Numpad4),
+    ("NUMPAD5", Key::Numpad5),
+    ("NUMPAD6", Key::Numpad6),
+    ("NUMPAD7", Key::Numpad7),
+    ("NUMPAD8", Key::Numpad8),
+    ("NUMPAD9", Key::Numpad9),
+    ("CANCEL", Key::Cancel),
+    ("CLEAR", Key::Clear),
+    ("PAUSE", Key::Pause),
+    ("KANA", Key::Kana),
+    ("HANGUL", Key::Hangul),
+    ("JUNJA", Key::Junja),
+    ("FINAL", Key::Final),
+    ("HANJA", Key::Hanja),
+    ("KANJI", Key::Kanji),
+    ("CONVERT", Key::Convert),
+    ("SELECT", Key::Select),
+    ("PRINT", Key::Print),
+    ("EXECUTE", Key::Execute),
+    ("PRINTSCREEN", Key::Snapshot),
+    ("INSERT", Key::Insert),
+    ("HELP", Key::Help),
+    ("SLEEP", Key::Sleep),
+    ("SEPARATOR", Key::Separator),
+    ("VOLUMEUP", Key::VolumeUp),
+    ("VOLUMEDOWN", Key::VolumeDown),
+    ("MUTE", Key::Mute),
+    ("SCROLLLOCK", Key::Scroll),
+    ("NUMLOCK", Key::NumLock),
+    ("RWIN", Key::RWin),
+    ("APPS", Key::Apps),
+    ("MULTIPLY", Key::Multiply),
+    ("ADD", Key::Add),
+    ("SUBTRACT", Key::Subtract),
+    ("DECIMAL", Key::Decimal),
+    ("DIVIDE", Key::Divide),
+    ("EQUALS", Key::Equals),
+    ("NUMPADENTER", Key::NumpadEnter),
+    ("RSHIFT", Key::RightShift),
+    ("RCTRL", Key::RightControl),
+    ("RALT", Key::RightAlt),
+];
+
+const BUTTON_NAMES: &[(&str, MouseButton)] = &[
+    ("LBUTTON", MouseButton::Left),
+    ("RBUTTON", MouseButton::Right),
+    ("MBUTTON", MouseButton::Middle),
+];
 
 /// An error that can occur when parsing DSL
 #[derive(Debug, PartialEq, Eq)]
@@ -24,6 +114,24 @@ pub enum ParseError {
     /// Example: +SHIFT}Hello{-SHIFT}
     ///         ^
     UnmatchedClose,
+
+    /// When the argument of a tag is missing or malformed.
+    /// Example: {DELAY abc}
+    ///                 ^^^
+    InvalidArgument(String),
+
+    /// When a {REPEAT n} is never matched with an {END}.
+    /// Example: {REPEAT 3}Hello
+    ///          ^^^^^^^^^^
+    UnmatchedRepeat,
+
+    /// Opposite of UnmatchedRepeat.
+    /// Example: Hello{END}
+    ///               ^^^^^
+    UnmatchedEnd,
+
+    /// When the repeats expand to too many tokens.
+    TooLong,
 }
 impl Error for ParseError {
     fn description(&self) -> &str {
@@ -32,47 +140,234 @@ impl Error for ParseError {
             ParseError::UnexpectedOpen => "Unescaped open bracket ({) found inside tag name",
             ParseError::UnmatchedOpen => "Unmatched open bracket ({). No matching close (})",
             ParseError::UnmatchedClose => "Unmatched close bracket (}). No previous open ({)",
+            ParseError::InvalidArgument(_) => "Invalid tag argument",
+            ParseError::UnmatchedRepeat => "Unmatched {REPEAT}. No matching {END}",
+            ParseError::UnmatchedEnd => "Unmatched {END}. No previous {REPEAT}",
+            ParseError::TooLong => "Too many tokens after expanding the repeats",
         }
     }
 }
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(&self.to_string())
+        match self {
+            ParseError::UnknownTag(tag) => write!(f, "Unknown tag: {}", tag),
+            ParseError::InvalidArgument(tag) => write!(f, "Invalid tag argument: {}", tag),
+            ParseError::UnexpectedOpen => f.write_str("Unescaped open bracket"),
+            ParseError::UnmatchedOpen => f.write_str("Unmatched open bracket"),
+            ParseError::UnmatchedClose => f.write_str("Unmatched close bracket"),
+            ParseError::UnmatchedRepeat => f.write_str("Unmatched REPEAT"),
+            ParseError::UnmatchedEnd => f.write_str("Unmatched END"),
+            ParseError::TooLong => f.write_str("Too many tokens"),
+        }
     }
 }
 
 /// Evaluate the DSL. This tokenizes the input and presses the keys.
+/// The mouse tokens are skipped, see [eval_all](fn.eval_all.html).
 pub fn eval<K>(enigo: &mut K, input: &str) -> Result<(), ParseError>
 where
     K: KeyboardControllable,
 {
     for token in tokenize(input)? {
-        match token {
-            Token::Sequence(buffer) => {
-                for key in buffer.chars() {
-                    enigo.key_click(Key::Layout(key));
-                }
+        eval_key_token(enigo, &token);
+    }
+    Ok(())
+}
+
+/// Evaluate the DSL, including the mouse tokens.
+pub fn eval_all<K>(enigo: &mut K, input: &str) -> Result<(), ParseError>
+where
+    K: KeyboardControllable + MouseControllable,
+{
+    for token in tokenize(input)? {
+        eval_token(enigo, &token);
+    }
+    Ok(())
+}
+
+/// Execute a single token, a delay blocks the current thread.
+pub fn eval_token<K>(enigo: &mut K, token: &Token)
+where
+    K: KeyboardControllable + MouseControllable,
+{
+    if eval_key_token(enigo, token) {
+        return;
+    }
+    match *token {
+        Token::MouseMove(x, y) => enigo.mouse_move_to(x, y),
+        Token::MouseMoveRelative(x, y) => enigo.mouse_move_relative(x, y),
+        Token::MouseDown(button) => enigo.mouse_down(button).unwrap_or(()),
+        Token::MouseUp(button) => enigo.mouse_up(button),
+        Token::MouseClick(button) => enigo.mouse_click(button),
+        Token::Scroll(x, y) => {
+            if x != 0 {
+                enigo.mouse_scroll_x(x);
+            }
+            if y != 0 {
+                enigo.mouse_scroll_y(y);
             }
-            Token::Unicode(buffer) => enigo.key_sequence(&buffer),
-            Token::KeyUp(key) => enigo.key_up(key),
-            Token::KeyDown(key) => enigo.key_down(key).unwrap_or(()),
         }
+        _ => {}
     }
-    Ok(())
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum Token {
+// returns false for the mouse tokens
+fn eval_key_token<K>(enigo: &mut K, token: &Token) -> bool
+where
+    K: KeyboardControllable,
+{
+    match token {
+        Token::Sequence(buffer) => {
+            for key in buffer.chars() {
+                enigo.key_click(Key::Layout(key));
+            }
+        }
+        Token::Unicode(buffer) => enigo.key_sequence(buffer),
+        Token::KeyUp(key) => enigo.key_up(*key),
+        Token::KeyDown(key) => enigo.key_down(*key).unwrap_or(()),
+        Token::KeyClick(key) => enigo.key_click(*key),
+        Token::Delay(ms) => std::thread::sleep(Duration::from_millis(*ms)),
+        _ => return false,
+    }
+    true
+}
+
+/// A token of the DSL, the repeats are expanded when tokenizing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// Text typed with the layout keys
     Sequence(String),
+    /// Text typed with key_sequence, between {+UNICODE} and {-UNICODE}
     Unicode(String),
+    /// {-KEY}
     KeyUp(Key),
+    /// {+KEY}
     KeyDown(Key),
+    /// {KEY}
+    KeyClick(Key),
+    /// {DELAY ms}
+    Delay(u64),
+    /// {MOVE x,y}
+    MouseMove(i32, i32),
+    /// {MOVEREL x,y}
+    MouseMoveRelative(i32, i32),
+    /// {+LBUTTON}
+    MouseDown(MouseButton),
+    /// {-LBUTTON}
+    MouseUp(MouseButton),
+    /// {LBUTTON}
+    MouseClick(MouseButton),
+    /// {WHEEL x,y}, the lengths passed to mouse_scroll_x and mouse_scroll_y
+    Scroll(i32, i32),
+}
+
+fn parse_pair(tag: &str, arg: Option<&str>) -> Result<(i32, i32), ParseError> {
+    let invalid = || ParseError::InvalidArgument(tag.to_owned());
+    let mut it = arg.ok_or_else(invalid)?.split(',');
+    match (it.next(), it.next(), it.next()) {
+        (Some(x), Some(y), None) => Ok((
+            x.trim().parse().map_err(|_| invalid())?,
+            y.trim().parse().map_err(|_| invalid())?,
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_tag(
+    tag: &str,
+    tokens: &mut Vec<Token>,
+    repeats: &mut Vec<(usize, u32)>,
+    unicode: &mut bool,
+) -> Result<(), ParseError> {
+    let (name, arg) = match tag.find(' ') {
+        Some(i) => (&tag[..i], Some(tag[i + 1..].trim())),
+        None => (tag, None),
+    };
+    let invalid = || ParseError::InvalidArgument(tag.to_owned());
+    match name {
+        "+UNICODE" => *unicode = true,
+        "-UNICODE" => *unicode = false,
+        "DELAY" => {
+            let ms = arg.ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+            tokens.push(Token::Delay(ms));
+        }
+        "REPEAT" => {
+            let n: u32 = arg.ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+            if n > MAX_REPEAT {
+                return Err(invalid());
+            }
+            repeats.push((tokens.len(), n));
+        }
+        "END" => {
+            let (start, n) = repeats.pop().ok_or(ParseError::UnmatchedEnd)?;
+            let body = tokens.split_off(start);
+            if tokens.len() + body.len() * n as usize > MAX_TOKENS {
+                return Err(ParseError::TooLong);
+            }
+            for _ in 0..n {
+                tokens.extend(body.iter().cloned());
+            }
+        }
+        "MOVE" => {
+            let (x, y) = parse_pair(tag, arg)?;
+            tokens.push(Token::MouseMove(x, y));
+        }
+        "MOVEREL" => {
+            let (x, y) = parse_pair(tag, arg)?;
+            tokens.push(Token::MouseMoveRelative(x, y));
+        }
+        "WHEEL" => {
+            let (x, y) = parse_pair(tag, arg)?;
+            tokens.push(Token::Scroll(x, y));
+        }
+        _ => {
+            let (kind, rest) = match name.chars().next() {
+                Some(c @ '+') | Some(c @ '-') => (c, &name[1..]),
+                _ => (' ', name),
+            };
+            if let Some((_, button)) = BUTTON_NAMES.iter().find(|x| x.0 == rest) {
+                if arg.is_some() {
+                    return Err(invalid());
+                }
+                tokens.push(match kind {
+                    '+' => Token::MouseDown(*button),
+                    '-' => Token::MouseUp(*button),
+                    _ => Token::MouseClick(*button),
+                });
+                return Ok(());
+            }
+            let key = if rest == "RAW" {
+                Key::Raw(arg.ok_or_else(invalid)?.parse().map_err(|_| invalid())?)
+            } else if arg.is_some() {
+                return Err(ParseError::UnknownTag(tag.to_owned()));
+            } else if let Some((_, key)) = KEY_NAMES.iter().find(|x| x.0 == rest) {
+                *key
+            } else {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Key::Layout(c),
+                    _ => return Err(ParseError::UnknownTag(tag.to_owned())),
+                }
+            };
+            tokens.push(match kind {
+                '+' => Token::KeyDown(key),
+                '-' => Token::KeyUp(key),
+                _ => Token::KeyClick(key),
+            });
+        }
+    }
+    if tokens.len() > MAX_TOKENS {
+        return Err(ParseError::TooLong);
+    }
+    Ok(())
 }
 
-fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+/// Tokenize the DSL without executing it.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
     let mut unicode = false;
 
     let mut tokens = Vec::new();
+    let mut repeats = Vec::new();
     let mut buffer = String::new();
     let mut iter = input.chars().peekable();
 
@@ -116,19 +411,7 @@ fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
                             None => return Err(ParseError::UnmatchedOpen),
                         }
                     }
-                    match &*tag {
-                        "+UNICODE" => unicode = true,
-                        "-UNICODE" => unicode = false,
-                        "+SHIFT" => tokens.push(Token::KeyDown(Key::Shift)),
-                        "-SHIFT" => tokens.push(Token::KeyUp(Key::Shift)),
-                        "+CTRL" => tokens.push(Token::KeyDown(Key::Control)),
-                        "-CTRL" => tokens.push(Token::KeyUp(Key::Control)),
-                        "+META" => tokens.push(Token::KeyDown(Key::Meta)),
-                        "-META" => tokens.push(Token::KeyUp(Key::Meta)),
-                        "+ALT" => tokens.push(Token::KeyDown(Key::Alt)),
-                        "-ALT" => tokens.push(Token::KeyUp(Key::Alt)),
-                        _ => return Err(ParseError::UnknownTag(tag)),
-                    }
+                    parse_tag(&tag, &mut tokens, &mut repeats, &mut unicode)?;
                 }
                 None => return Err(ParseError::UnmatchedOpen),
             }
@@ -144,9 +427,133 @@ fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
 
     flush(&mut tokens, buffer, unicode);
 
+    if !repeats.is_empty() {
+        return Err(ParseError::UnmatchedRepeat);
+    }
+
     Ok(tokens)
 }
 
+fn escape(s: &str) -> String {
+    s.replace('{', "{{").replace('}', "}}")
+}
+
+fn key_tag(key: Key) -> Option<String> {
+    match key {
+        Key::Layout(' ') => Some("SPACE".to_owned()),
+        Key::Layout(c) => Some(escape(&c.to_string())),
+        Key::Raw(code) => Some(format!("RAW {}", code)),
+        _ => KEY_NAMES
+            .iter()
+            .find(|x| x.1 == key)
+            .map(|x| x.0.to_owned()),
+    }
+}
+
+fn button_tag(button: MouseButton) -> Option<&'static str> {
+    BUTTON_NAMES.iter().find(|x| x.1 == button).map(|x| x.0)
+}
+
+/// Convert the tokens back to the DSL, the keys without a tag are dropped.
+pub fn format(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    let mut unicode = false;
+    for token in tokens {
+        match token {
+            Token::Unicode(_) if !unicode => {
+                out.push_str("{+UNICODE}");
+                unicode = true;
+            }
+            Token::Unicode(_) => {}
+            _ if unicode => {
+                out.push_str("{-UNICODE}");
+                unicode = false;
+            }
+            _ => {}
+        }
+        let tag = match token {
+            Token::Sequence(s) | Token::Unicode(s) => {
+                out.push_str(&escape(s));
+                continue;
+            }
+            Token::KeyUp(key) => key_tag(*key).map(|x| format!("-{}", x)),
+            Token::KeyDown(key) => key_tag(*key).map(|x| format!("+{}", x)),
+            Token::KeyClick(key) => key_tag(*key),
+            Token::Delay(ms) => Some(format!("DELAY {}", ms)),
+            Token::MouseMove(x, y) => Some(format!("MOVE {},{}", x, y)),
+            Token::MouseMoveRelative(x, y) => Some(format!("MOVEREL {},{}", x, y)),
+            Token::MouseDown(button) => button_tag(*button).map(|x| format!("+{}", x)),
+            Token::MouseUp(button) => button_tag(*button).map(|x| format!("-{}", x)),
+            Token::MouseClick(button) => button_tag(*button).map(|x| x.to_owned()),
+            Token::Scroll(x, y) => Some(format!("WHEEL {},{}", x, y)),
+        };
+        if let Some(tag) = tag {
+            out.push('{');
+            out.push_str(&tag);
+            out.push('}');
+        }
+    }
+    if unicode {
+        out.push_str("{-UNICODE}");
+    }
+    out
+}
+
+/// Records the tokens with the time elapsed between them.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    tokens: Vec<Token>,
+    last: Option<Instant>,
+}
+
+impl Recorder {
+    /// Start a new recording
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a token, preceded by a delay if enough time passed since the previous one.
+    /// A layout key pressed and released is merged into the text typed before it.
+    pub fn push(&mut self, token: Token) {
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            let ms = now.duration_since(last).as_millis() as u64;
+            if ms < MIN_RECORD_DELAY {
+                self.push_(token);
+                return;
+            }
+            self.tokens.push(Token::Delay(ms));
+        }
+        self.last = Some(now);
+        self.push_(token);
+    }
+
+    fn push_(&mut self, token: Token) {
+        if let Token::KeyUp(Key::Layout(c)) = token {
+            let n = self.tokens.len();
+            let down = match self.tokens.last() {
+                Some(Token::Delay(_)) if n >= 2 => n - 2,
+                _ => n.saturating_sub(1),
+            };
+            if self.tokens.get(down) == Some(&Token::KeyDown(Key::Layout(c))) {
+                self.tokens.truncate(down);
+                if let Some(Token::Sequence(s)) = self.tokens.last_mut() {
+                    s.push(c);
+                } else {
+                    self.tokens.push(Token::Sequence(c.to_string()));
+                }
+                return;
+            }
+        }
+        self.tokens.push(token);
+    }
+
+    /// The recorded tokens
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,6 +571,33 @@ mod tests {
         );
     }
     #[test]
+    fn repeat_and_mouse() {
+        assert_eq!(
+            tokenize("{REPEAT 2}a{DELAY 10}{END}{MOVE 1,-2}{+LBUTTON}{WHEEL 0,3}"),
+            Ok(vec![
+                Token::Sequence("a".into()),
+                Token::Delay(10),
+                Token::Sequence("a".into()),
+                Token::Delay(10),
+                Token::MouseMove(1, -2),
+                Token::MouseDown(MouseButton::Left),
+                Token::Scroll(0, 3)
+            ])
+        );
+        assert_eq!(tokenize("{REPEAT 2}a"), Err(ParseError::UnmatchedRepeat));
+        assert_eq!(tokenize("a{END}"), Err(ParseError::UnmatchedEnd));
+        assert_eq!(
+            tokenize("{DELAY x}"),
+            Err(ParseError::InvalidArgument("DELAY x".into()))
+        );
+    }
+    #[test]
+    fn format_roundtrip() {
+        let input = "{{a}}{+CTRL}{+c}{-c}{-CTRL}{ENTER}{+UNICODE}❤{-UNICODE}{RAW 56}{-RBUTTON}";
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(format(&tokens), input);
+    }
+    #[test]
     fn unexpected_open() {
         assert_eq!(tokenize("{hello{}world}"), Err(ParseError::UnexpectedOpen));
     }
@@ -69,11 +69,21 @@ mod macos;
 #[cfg(target_os = "macos")]
 pub use macos::Enigo;
 
+#[cfg(target_os = "linux")]
+mod keysym;
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "linux")]
 pub use crate::linux::Enigo;
 
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+pub use crate::uinput::{
+    GamepadState, PenState, Rumble, TouchContact, VirtualGamepad, VirtualPen, VirtualTouchscreen,
+    VirtualWheel,
+};
+
 /// DSL parser module
 pub mod dsl;
 
@@ -88,7 +98,7 @@ extern crate serde;
 pub type ResultType = std::result::Result<(), Box<dyn std::error::Error>>;
 
 #[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// MouseButton represents a mouse button,
 /// and is used in for example
 /// [mouse_click](trait.MouseControllable.html#tymethod.mouse_click).
@@ -241,6 +251,16 @@ pub trait MouseControllable {
     /// enigo.mouse_scroll_y(2);
     /// ```
     fn mouse_scroll_y(&mut self, length: i32);
+
+    /// Scroll smoothly by `x` and `y` in 1/120 of a notch, positive numbers
+    /// scroll to the right and up, as the high-resolution wheels do.
+    ///
+    /// Returns false if the platform can only scroll by whole notches,
+    /// [mouse_scroll_x](MouseControllable::mouse_scroll_x) and
+    /// [mouse_scroll_y](MouseControllable::mouse_scroll_y) have to be used then.
+    fn mouse_scroll_smooth(&mut self, _x: i32, _y: i32) -> bool {
+        false
+    }
 }
 
 /// A key on the keyboard.
@@ -1,9 +1,11 @@
 use libc;
 
-use crate::{Key, KeyboardControllable, MouseButton, MouseControllable};
+use crate::{
+    keysym::keysym_to_char, Key, KeyboardControllable, MouseButton, MouseControllable, VirtualWheel,
+};
 
-use self::libc::{c_char, c_int, c_void, useconds_t};
-use std::{borrow::Cow, ffi::CString, io::prelude::*, ptr, sync::mpsc};
+use self::libc::{c_char, c_int, c_uint, c_ulong, c_void, useconds_t};
+use std::{borrow::Cow, ffi::CString, ptr};
 
 const CURRENT_WINDOW: c_int = 0;
 const DEFAULT_DELAY: u64 = 12000;
@@ -60,16 +62,13 @@ fn mousebutton(button: MouseButton) -> c_int {
     }
 }
 
-enum PyMsg {
-    Char(char),
-    Str(&'static str),
-}
-
 /// The main struct for handling the event emitting
 pub struct Enigo {
     xdo: Xdo,
     delay: u64,
-    tx: mpsc::Sender<(PyMsg, bool)>,
+    keyboard: Option<XKeyboard>,
+    // created on the first smooth scroll, None inside if /dev/uinput can not be used
+    wheel: Option<Option<VirtualWheel>>,
 }
 // This is safe, we have a unique pointer.
 // TODO: use Unique<c_char> once stable.
@@ -78,12 +77,15 @@ unsafe impl Send for Enigo {}
 impl Default for Enigo {
     /// Create a new Enigo instance
     fn default() -> Self {
-        let (tx, rx) = mpsc::channel();
-        start_pynput_service(rx);
+        let keyboard = XKeyboard::new();
+        if keyboard.is_none() {
+            log::error!("Failed to open X display for XTest, fall back to xdo");
+        }
         Self {
             xdo: unsafe { xdo_new(ptr::null()) },
             delay: DEFAULT_DELAY,
-            tx,
+            keyboard,
+            wheel: None,
         }
     }
 }
@@ -99,98 +101,20 @@ impl Enigo {
     pub fn set_delay(&mut self, delay: u64) {
         self.delay = delay;
     }
-    ///
+    /// Release the keys still down and give back the borrowed keycodes.
+    /// This is Linux-specific.
     pub fn reset(&mut self) {
-        self.tx.send((PyMsg::Char('\0'), true)).ok();
+        if let Some(kb) = self.keyboard.as_mut() {
+            kb.reset();
+        }
     }
     #[inline]
-    fn send_pynput(&mut self, key: &Key, is_press: bool) -> bool {
-        if unsafe { PYNPUT_EXIT || !PYNPUT_REDAY } {
-            return false;
-        }
-        if let Key::Layout(c) = key {
-            return self.tx.send((PyMsg::Char(*c), is_press)).is_ok();
-        }
-        if let Key::Raw(_) = key {
-            return false;
+    fn send_xtest(&mut self, key: &Key, is_press: bool) -> bool {
+        let delay = self.delay;
+        match self.keyboard.as_mut() {
+            Some(kb) => kb.send_key(key, is_press, delay),
+            None => false,
         }
-        #[allow(deprecated)]
-        let s = match key {
-            Key::Alt => "Alt_L",
-            Key::Backspace => "BackSpace",
-            Key::CapsLock => "Caps_Lock",
-            Key::Control => "Control_L",
-            Key::Delete => "Delete",
-            Key::DownArrow => "Down",
-            Key::End => "End",
-            Key::Escape => "Escape",
-            Key::F1 => "F1",
-            Key::F10 => "F10",
-            Key::F11 => "F11",
-            Key::F12 => "F12",
-            Key::F2 => "F2",
-            Key::F3 => "F3",
-            Key::F4 => "F4",
-            Key::F5 => "F5",
-            Key::F6 => "F6",
-            Key::F7 => "F7",
-            Key::F8 => "F8",
-            Key::F9 => "F9",
-            Key::Home => "Home",
-            Key::LeftArrow => "Left",
-            Key::Option => "Option",
-            Key::PageDown => "Page_Down",
-            Key::PageUp => "Page_Up",
-            Key::Return => "Return",
-            Key::RightArrow => "Right",
-            Key::Shift => "Shift_L",
-            Key::Space => "space",
-            Key::Tab => "Tab",
-            Key::UpArrow => "Up",
-            Key::Numpad0 => "0",
-            Key::Numpad1 => "1",
-            Key::Numpad2 => "2",
-            Key::Numpad3 => "3",
-            Key::Numpad4 => "4",
-            Key::Numpad5 => "5",
-            Key::Numpad6 => "6",
-            Key::Numpad7 => "7",
-            Key::Numpad8 => "8",
-            Key::Numpad9 => "9",
-            Key::Decimal => "KP_Decimal",
-            Key::Cancel => "Cancel",
-            Key::Clear => "Clear",
-            Key::Pause => "Pause",
-            Key::Kana => "Kana",
-            Key::Hangul => "Hangul",
-            Key::Hanja => "Hanja",
-            Key::Kanji => "Kanji",
-            Key::Select => "Select",
-            Key::Print => "Print",
-            Key::Execute => "Execute",
-            Key::Snapshot => "3270_PrintScreen",
-            Key::Insert => "Insert",
-            Key::Help => "Help",
-            Key::Separator => "KP_Separator",
-            Key::Scroll => "Scroll_Lock",
-            Key::NumLock => "Num_Lock",
-            Key::RWin => "Super_R",
-            Key::Apps => "Menu",
-            Key::Multiply => "KP_Multiply",
-            Key::Add => "KP_Add",
-            Key::Subtract => "KP_Subtract",
-            Key::Divide => "KP_Divide",
-            Key::Equals => "KP_Equal",
-            Key::NumpadEnter => "KP_Enter",
-            Key::RightShift => "Shift_R",
-            Key::RightControl => "Control_R",
-            Key::RightAlt => "Mode_switch",
-            Key::Command | Key::Super | Key::Windows | Key::Meta => "Super_L",
-            _ => {
-                return true;
-            }
-        };
-        return self.tx.send((PyMsg::Str(s), is_press)).is_ok();
     }
 }
 impl Drop for Enigo {
@@ -281,6 +205,20 @@ impl MouseControllable for Enigo {
             self.mouse_click(button);
         }
     }
+
+    fn mouse_scroll_smooth(&mut self, x: i32, y: i32) -> bool {
+        let wheel = self.wheel.get_or_insert_with(|| match VirtualWheel::new() {
+            Ok(wheel) => Some(wheel),
+            Err(err) => {
+                log::error!("Failed to create the virtual wheel: {}", err);
+                None
+            }
+        });
+        match wheel {
+            Some(wheel) => wheel.scroll(x, y).is_ok(),
+            None => false,
+        }
+    }
 }
 fn keysequence<'a>(key: Key) -> Cow<'a, str> {
     if let Key::Layout(c) = key {
@@ -408,6 +346,11 @@ impl KeyboardControllable for Enigo {
             Key::Alt => mask & mod_alt != 0,
             Key::NumLock => mask & mod_numlock != 0,
             Key::Meta => mask & mod_meta != 0,
+            // not a modifier in X11, only its indicator tells
+            Key::Scroll => self
+                .keyboard
+                .as_ref()
+                .map_or(false, |kb| kb.indicator("Scroll Lock")),
             _ => false,
         }
     }
@@ -416,6 +359,13 @@ impl KeyboardControllable for Enigo {
         if self.xdo.is_null() {
             return;
         }
+        let delay = self.delay;
+        if let Some(kb) = self.keyboard.as_mut() {
+            for chr in sequence.chars() {
+                kb.type_char(chr, delay);
+            }
+            return;
+        }
         if let Ok(string) = CString::new(sequence) {
             unsafe {
                 xdo_enter_text_window(
@@ -431,7 +381,7 @@ impl KeyboardControllable for Enigo {
         if self.xdo.is_null() {
             return Ok(());
         }
-        if self.send_pynput(&key, true) {
+        if self.send_xtest(&key, true) {
             return Ok(());
         }
         let string = CString::new(&*keysequence(key))?;
@@ -449,7 +399,7 @@ impl KeyboardControllable for Enigo {
         if self.xdo.is_null() {
             return;
         }
-        if self.send_pynput(&key, false) {
+        if self.send_xtest(&key, false) {
             return;
         }
         if let Ok(string) = CString::new(&*keysequence(key)) {
@@ -467,6 +417,10 @@ impl KeyboardControllable for Enigo {
         if self.xdo.is_null() {
             return;
         }
+        if self.send_xtest(&key, true) {
+            self.send_xtest(&key, false);
+            return;
+        }
         if let Ok(string) = CString::new(&*keysequence(key)) {
             unsafe {
                 xdo_send_keysequence_window(
@@ -480,118 +434,511 @@ impl KeyboardControllable for Enigo {
     }
 }
 
-static mut PYNPUT_EXIT: bool = false;
-static mut PYNPUT_REDAY: bool = false;
-static IPC_FILE: &'static str = "/tmp/RustDesk/pynput_service";
-
-fn start_pynput_service(rx: mpsc::Receiver<(PyMsg, bool)>) {
-    let mut py = "./pynput_service.py".to_owned();
-    if !std::path::Path::new(&py).exists() {
-        py = "/usr/share/rustdesk/files/pynput_service.py".to_owned();
-        if !std::path::Path::new(&py).exists() {
-            log::error!("{} not exits", py);
-        }
-    }
-    log::info!("pynput service: {}", py);
-    std::thread::spawn(move || {
-        let username = std::env::var("PYNPUT_USERNAME").unwrap_or("".to_owned());
-        let userid = std::env::var("PYNPUT_USERID").unwrap_or("".to_owned());
-        let status = if username.is_empty() {
-            std::process::Command::new("python3")
-                .arg(&py)
-                .arg(IPC_FILE)
-                .status()
-                .map(|x| x.success())
-        } else {
-            let mut status = Ok(true);
-            for i in 0..100 {
-                if i % 10 == 0 {
-                    log::info!("#{} try to start pynput server", i);
+type Display = c_void;
+type KeySym = c_ulong;
+type KeyCode = u8;
+
+const NO_SYMBOL: KeySym = 0;
+const XK_RETURN: KeySym = 0xff0d;
+const XK_TAB: KeySym = 0xff09;
+const LOCK_MASK: c_uint = 1 << 1;
+// columns of the core keyboard mapping we look into:
+// group 1 level 1, level 2, then level 3 and level 4 (AltGr)
+const COLUMNS: [usize; 4] = [0, 1, 4, 5];
+const MAX_BORROWED_KEYCODES: usize = 10;
+
+#[link(name = "X11")]
+extern "C" {
+    fn XOpenDisplay(name: *const c_char) -> *mut Display;
+    fn XCloseDisplay(display: *mut Display) -> c_int;
+    fn XDefaultRootWindow(display: *mut Display) -> c_ulong;
+    fn XStringToKeysym(string: *const c_char) -> KeySym;
+    fn XKeysymToKeycode(display: *mut Display, keysym: KeySym) -> KeyCode;
+    fn XDisplayKeycodes(display: *mut Display, min: *mut c_int, max: *mut c_int) -> c_int;
+    fn XGetKeyboardMapping(
+        display: *mut Display,
+        first_keycode: KeyCode,
+        keycode_count: c_int,
+        keysyms_per_keycode: *mut c_int,
+    ) -> *mut KeySym;
+    fn XChangeKeyboardMapping(
+        display: *mut Display,
+        first_keycode: c_int,
+        keysyms_per_keycode: c_int,
+        keysyms: *const KeySym,
+        num_codes: c_int,
+    ) -> c_int;
+    fn XQueryKeymap(display: *mut Display, keys: *mut c_char) -> c_int;
+    fn XQueryPointer(
+        display: *mut Display,
+        window: c_ulong,
+        root: *mut c_ulong,
+        child: *mut c_ulong,
+        root_x: *mut c_int,
+        root_y: *mut c_int,
+        win_x: *mut c_int,
+        win_y: *mut c_int,
+        mask: *mut c_uint,
+    ) -> c_int;
+    fn XFree(data: *mut c_void) -> c_int;
+    fn XFlush(display: *mut Display) -> c_int;
+    fn XSync(display: *mut Display, discard: c_int) -> c_int;
+    fn XInternAtom(display: *mut Display, name: *const c_char, only_if_exists: c_int) -> c_ulong;
+    fn XkbGetNamedIndicator(
+        display: *mut Display,
+        name: c_ulong,
+        index: *mut c_int,
+        state: *mut c_int,
+        map: *mut c_void,
+        real: *mut c_int,
+    ) -> c_int;
+}
+
+#[link(name = "Xtst")]
+extern "C" {
+    fn XTestFakeKeyEvent(
+        display: *mut Display,
+        keycode: c_uint,
+        is_press: c_int,
+        delay: c_ulong,
+    ) -> c_int;
+}
+
+fn char_to_keysym(chr: char) -> KeySym {
+    match chr {
+        '\n' | '\r' => XK_RETURN,
+        '\t' => XK_TAB,
+        // latin-1 keysyms equal their code points, the others use the unicode keysym range
+        '\u{20}'..='\u{7e}' | '\u{a0}'..='\u{ff}' => chr as _,
+        _ => 0x0100_0000 | chr as KeySym,
+    }
+}
+
+fn keysym_names(key: &Key) -> &'static [&'static str] {
+    #[allow(deprecated)]
+    match key {
+        Key::Alt => &["Alt_L"],
+        Key::Backspace => &["BackSpace"],
+        Key::CapsLock => &["Caps_Lock"],
+        Key::Control => &["Control_L"],
+        Key::Delete => &["Delete"],
+        Key::DownArrow => &["Down"],
+        Key::End => &["End"],
+        Key::Escape => &["Escape"],
+        Key::F1 => &["F1"],
+        Key::F10 => &["F10"],
+        Key::F11 => &["F11"],
+        Key::F12 => &["F12"],
+        Key::F2 => &["F2"],
+        Key::F3 => &["F3"],
+        Key::F4 => &["F4"],
+        Key::F5 => &["F5"],
+        Key::F6 => &["F6"],
+        Key::F7 => &["F7"],
+        Key::F8 => &["F8"],
+        Key::F9 => &["F9"],
+        Key::Home => &["Home"],
+        Key::LeftArrow => &["Left"],
+        Key::PageDown => &["Page_Down"],
+        Key::PageUp => &["Page_Up"],
+        Key::Return => &["Return"],
+        Key::RightArrow => &["Right"],
+        Key::Shift => &["Shift_L"],
+        Key::Space => &["space"],
+        Key::Tab => &["Tab"],
+        Key::UpArrow => &["Up"],
+        Key::Numpad0 => &["0"],
+        Key::Numpad1 => &["1"],
+        Key::Numpad2 => &["2"],
+        Key::Numpad3 => &["3"],
+        Key::Numpad4 => &["4"],
+        Key::Numpad5 => &["5"],
+        Key::Numpad6 => &["6"],
+        Key::Numpad7 => &["7"],
+        Key::Numpad8 => &["8"],
+        Key::Numpad9 => &["9"],
+        Key::Decimal => &["KP_Decimal"],
+        Key::Cancel => &["Cancel"],
+        Key::Clear => &["Clear"],
+        Key::Pause => &["Pause"],
+        Key::Kana => &["Kana_Lock", "Katakana"],
+        Key::Hangul => &["Hangul"],
+        Key::Hanja => &["Hangul_Hanja"],
+        Key::Kanji => &["Kanji"],
+        Key::Select => &["Select"],
+        Key::Print => &["Print"],
+        Key::Execute => &["Execute"],
+        Key::Snapshot => &["Print", "3270_PrintScreen"],
+        Key::Insert => &["Insert"],
+        Key::Help => &["Help"],
+        Key::Separator => &["KP_Separator"],
+        Key::Scroll => &["Scroll_Lock"],
+        Key::NumLock => &["Num_Lock"],
+        Key::RWin => &["Super_R"],
+        Key::Apps => &["Menu"],
+        Key::Multiply => &["KP_Multiply"],
+        Key::Add => &["KP_Add"],
+        Key::Subtract => &["KP_Subtract"],
+        Key::Divide => &["KP_Divide"],
+        Key::Equals => &["KP_Equal"],
+        Key::NumpadEnter => &["KP_Enter"],
+        Key::RightShift => &["Shift_R"],
+        Key::RightControl => &["Control_R"],
+        Key::RightAlt => &["ISO_Level3_Shift", "Mode_switch", "Alt_R"],
+        Key::Command | Key::Super | Key::Windows | Key::Meta => &["Super_L"],
+        _ => &[],
+    }
+}
+
+/// Key injection through XTest, looking keysyms up in the current keyboard mapping.
+/// Characters missing from the layout are typed by remapping a spare keycode.
+struct XKeyboard {
+    display: *mut Display,
+    min_keycode: c_int,
+    max_keycode: c_int,
+    // remapped spare keycodes, least recently used first
+    borrowed: Vec<(KeyCode, KeySym)>,
+    // keycodes pressed for the keys still down, so the release hits the same keycode
+    down: Vec<(Key, KeyCode)>,
+}
+
+impl XKeyboard {
+    fn new() -> Option<Self> {
+        let display = unsafe { XOpenDisplay(ptr::null()) };
+        if display.is_null() {
+            return None;
+        }
+        let mut min_keycode = 0;
+        let mut max_keycode = 0;
+        unsafe {
+            XDisplayKeycodes(display, &mut min_keycode, &mut max_keycode);
+        }
+        Some(Self {
+            display,
+            min_keycode,
+            max_keycode,
+            borrowed: Vec::new(),
+            down: Vec::new(),
+        })
+    }
+
+    fn mapping(&self) -> (Vec<KeySym>, usize) {
+        let mut per = 0;
+        let count = self.max_keycode - self.min_keycode + 1;
+        unsafe {
+            let syms = XGetKeyboardMapping(self.display, self.min_keycode as _, count, &mut per);
+            if syms.is_null() {
+                return (Vec::new(), 0);
+            }
+            let map = std::slice::from_raw_parts(syms, (count * per) as usize).to_vec();
+            XFree(syms as _);
+            (map, per as usize)
+        }
+    }
+
+    // returns the keycode and the column in the mapping, i.e. the shift level
+    fn find(
+        &self,
+        map: &[KeySym],
+        per: usize,
+        sym: KeySym,
+        chr: Option<char>,
+    ) -> Option<(KeyCode, usize)> {
+        for col in COLUMNS.iter().filter(|c| **c < per) {
+            for (i, syms) in map.chunks(per).enumerate() {
+                let x = syms[*col];
+                if x == NO_SYMBOL {
+                    continue;
                 }
-                status = std::process::Command::new("sudo")
-                    .args(vec![
-                        &format!("XDG_RUNTIME_DIR=/run/user/{}", userid) as &str,
-                        "-u",
-                        &username,
-                        "python3",
-                        &py,
-                        IPC_FILE,
-                    ])
-                    .status()
-                    .map(|x| x.success());
-                match status {
-                    Ok(true) => break,
-                    _ => {}
+                let matched = x == sym || (chr.is_some() && keysym_to_char(x as _) == chr);
+                if matched {
+                    return Some(((self.min_keycode + i as c_int) as _, *col));
                 }
-                std::thread::sleep(std::time::Duration::from_millis(100));
             }
-            status
+        }
+        None
+    }
+
+    fn keycode(&self, name: &str) -> Option<(KeySym, KeyCode)> {
+        let name = CString::new(name).ok()?;
+        let sym = unsafe { XStringToKeysym(name.as_ptr()) };
+        if sym == NO_SYMBOL {
+            return None;
+        }
+        Some((sym, unsafe { XKeysymToKeycode(self.display, sym) }))
+    }
+
+    fn keycodes(&self, names: &[&str]) -> Vec<KeyCode> {
+        names
+            .iter()
+            .filter_map(|n| self.keycode(n))
+            .map(|x| x.1)
+            .filter(|kc| *kc != 0)
+            .collect()
+    }
+
+    fn pressed_keys(&self) -> [u8; 32] {
+        let mut keys = [0u8; 32];
+        unsafe {
+            XQueryKeymap(self.display, keys.as_mut_ptr() as _);
+        }
+        keys
+    }
+
+    fn indicator(&self, name: &str) -> bool {
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => return false,
         };
-        log::info!(
-            "pynput server exit with username/id {}/{}: {:?}",
-            username,
-            userid,
-            status
-        );
+        let mut state = 0;
         unsafe {
-            PYNPUT_EXIT = true;
-        }
-    });
-    std::thread::spawn(move || {
-        for i in 0..300 {
-            std::thread::sleep(std::time::Duration::from_millis(100));
-            let mut conn = match std::os::unix::net::UnixStream::connect(IPC_FILE) {
-                Ok(conn) => conn,
-                Err(err) => {
-                    if i % 15 == 0 {
-                        log::warn!("Failed to connect to {}: {}", IPC_FILE, err);
+            let atom = XInternAtom(self.display, name.as_ptr(), 1);
+            atom != 0
+                && XkbGetNamedIndicator(
+                    self.display,
+                    atom,
+                    ptr::null_mut(),
+                    &mut state,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                ) != 0
+                && state != 0
+        }
+    }
+
+    fn modifier_mask(&self) -> c_uint {
+        let (mut root, mut child) = (0, 0);
+        let (mut rx, mut ry, mut wx, mut wy) = (0, 0, 0, 0);
+        let mut mask = 0;
+        unsafe {
+            XQueryPointer(
+                self.display,
+                XDefaultRootWindow(self.display),
+                &mut root,
+                &mut child,
+                &mut rx,
+                &mut ry,
+                &mut wx,
+                &mut wy,
+                &mut mask,
+            );
+        }
+        mask
+    }
+
+    fn fake(&self, keycode: KeyCode, down: bool) {
+        unsafe {
+            XTestFakeKeyEvent(self.display, keycode as _, down as _, 0);
+        }
+    }
+
+    fn remap(&self, keycode: KeyCode, sym: KeySym, per: usize) {
+        // the same keysym on every level, so the modifiers held do not matter
+        let syms = vec![sym; per.max(1)];
+        unsafe {
+            XChangeKeyboardMapping(
+                self.display,
+                keycode as _,
+                syms.len() as _,
+                syms.as_ptr(),
+                1,
+            );
+            XSync(self.display, 0);
+        }
+    }
+
+    fn borrow(&mut self, sym: KeySym, map: &[KeySym], per: usize, delay: u64) -> Option<KeyCode> {
+        if let Some(pos) = self.borrowed.iter().position(|x| x.1 == sym) {
+            let x = self.borrowed.remove(pos);
+            self.borrowed.push(x);
+            return Some(x.0);
+        }
+        let keycode = if self.borrowed.len() < MAX_BORROWED_KEYCODES {
+            map.chunks(per)
+                .enumerate()
+                .rev()
+                .filter(|(_, syms)| syms.iter().all(|x| *x == NO_SYMBOL))
+                .map(|(i, _)| (self.min_keycode + i as c_int) as KeyCode)
+                .find(|kc| !self.borrowed.iter().any(|x| x.0 == *kc))
+        } else {
+            None
+        };
+        let keycode = match keycode {
+            Some(kc) => kc,
+            None => {
+                let down = &self.down;
+                let pos = self
+                    .borrowed
+                    .iter()
+                    .position(|x| !down.iter().any(|d| d.1 == x.0))?;
+                self.borrowed.remove(pos).0
+            }
+        };
+        self.remap(keycode, sym, per);
+        // give the clients time to pick up the new mapping before the key arrives
+        std::thread::sleep(std::time::Duration::from_micros(delay));
+        self.borrowed.push((keycode, sym));
+        Some(keycode)
+    }
+
+    // strict: the exact character is wanted whatever the modifiers held,
+    // otherwise a key found on the base level is pressed as is, like a physical key
+    fn press(
+        &mut self,
+        sym: KeySym,
+        chr: Option<char>,
+        strict: bool,
+        delay: u64,
+    ) -> Option<KeyCode> {
+        let (map, per) = self.mapping();
+        if per == 0 {
+            return None;
+        }
+        let keycode = match self.find(&map, per, sym, chr) {
+            Some((kc, 0)) if !strict => {
+                self.fake(kc, true);
+                kc
+            }
+            Some((kc, col)) => {
+                let mut need_shift = col % 2 == 1;
+                if let Some(c) = chr {
+                    let upper = c.to_uppercase().next() != Some(c);
+                    let lower = c.to_lowercase().next() != Some(c);
+                    if (upper || lower) && self.modifier_mask() & LOCK_MASK != 0 {
+                        need_shift = !need_shift;
                     }
-                    continue;
                 }
-            };
-            if let Err(err) = conn.set_nonblocking(true) {
-                log::error!("Failed to set ipc nonblocking: {}", err);
-                return;
+                self.press_with_modifiers(kc, need_shift, col >= 4);
+                kc
             }
-            log::info!("Conntected to pynput server");
-            let d = std::time::Duration::from_millis(30);
-            unsafe {
-                PYNPUT_REDAY = true;
+            None => {
+                let kc = self.borrow(sym, &map, per, delay)?;
+                self.fake(kc, true);
+                kc
             }
-            let mut buf = [0u8; 1024];
-            loop {
-                if unsafe { PYNPUT_EXIT } {
-                    break;
+        };
+        unsafe {
+            XFlush(self.display);
+        }
+        Some(keycode)
+    }
+
+    fn press_with_modifiers(&self, keycode: KeyCode, shift: bool, level3: bool) {
+        let keys = self.pressed_keys();
+        let is_down = |kc: &KeyCode| keys[*kc as usize / 8] & (1 << (*kc % 8)) != 0;
+        let mut undo = Vec::new();
+        for (names, need) in [
+            (&["Shift_L", "Shift_R"][..], shift),
+            (&["ISO_Level3_Shift", "Mode_switch"][..], level3),
+        ]
+        .iter()
+        {
+            let keycodes = self.keycodes(names);
+            let down: Vec<KeyCode> = keycodes.iter().filter(|kc| is_down(kc)).cloned().collect();
+            if *need && down.is_empty() {
+                if let Some(kc) = keycodes.first() {
+                    self.fake(*kc, true);
+                    undo.push((*kc, false));
                 }
-                match rx.recv_timeout(d) {
-                    Ok((msg, is_press)) => {
-                        let msg = match msg {
-                            PyMsg::Char(chr) => {
-                                format!("{}{}", if is_press { 'p' } else { 'r' }, chr)
-                            }
-                            PyMsg::Str(s) => format!("{}{}", if is_press { 'p' } else { 'r' }, s),
-                        };
-                        let n = msg.len();
-                        buf[0] = n as _;
-                        buf[1..(n + 1)].copy_from_slice(msg.as_bytes());
-                        if let Err(err) = conn.write_all(&buf[..n + 1]) {
-                            log::error!("Failed to write to ipc: {}", err);
-                            break;
-                        }
-                    }
-                    Err(err) => match err {
-                        mpsc::RecvTimeoutError::Disconnected => {
-                            log::error!("pynput sender disconnecte");
-                            break;
-                        }
-                        _ => {}
-                    },
+            } else if !*need {
+                for kc in down {
+                    self.fake(kc, false);
+                    undo.push((kc, true));
+                }
+            }
+        }
+        self.fake(keycode, true);
+        for (kc, down) in undo.into_iter().rev() {
+            self.fake(kc, down);
+        }
+    }
+
+    fn send_key(&mut self, key: &Key, is_press: bool, delay: u64) -> bool {
+        if !is_press {
+            if let Some(pos) = self.down.iter().position(|x| x.0 == *key) {
+                let (_, kc) = self.down.remove(pos);
+                self.fake(kc, false);
+                unsafe {
+                    XFlush(self.display);
                 }
+                return true;
+            }
+        }
+        let (sym, chr) = match key {
+            Key::Layout(c) => (char_to_keysym(*c), Some(*c)),
+            Key::Raw(keycode) => {
+                // an X11 keycode, the position on the keyboard whatever the layout
+                self.fake(*keycode as _, is_press);
+                if is_press {
+                    self.down.push((*key, *keycode as _));
+                }
+                unsafe {
+                    XFlush(self.display);
+                }
+                return true;
+            }
+            _ => {
+                let names = keysym_names(key);
+                match names
+                    .iter()
+                    .filter_map(|n| self.keycode(n))
+                    .find(|x| x.1 != 0)
+                    .or_else(|| names.iter().filter_map(|n| self.keycode(n)).next())
+                {
+                    Some((sym, _)) => (sym, None),
+                    None => return false,
+                }
+            }
+        };
+        if !is_press {
+            // not pressed by us, release it anyway if it is on the keyboard
+            let (map, per) = self.mapping();
+            if let Some((kc, _)) = self.find(&map, per, sym, chr) {
+                self.fake(kc, false);
+                unsafe {
+                    XFlush(self.display);
+                }
+            }
+            return true;
+        }
+        match self.press(sym, chr, false, delay) {
+            Some(kc) => {
+                self.down.push((*key, kc));
+                true
             }
+            None => false,
+        }
+    }
+
+    fn type_char(&mut self, chr: char, delay: u64) {
+        if let Some(kc) = self.press(char_to_keysym(chr), Some(chr), true, delay) {
+            self.fake(kc, false);
             unsafe {
-                PYNPUT_REDAY = false;
+                XFlush(self.display);
             }
-            break;
+            std::thread::sleep(std::time::Duration::from_micros(delay));
+        }
+    }
+
+    fn reset(&mut self) {
+        for (_, kc) in std::mem::take(&mut self.down) {
+            self.fake(kc, false);
+        }
+        let per = self.mapping().1;
+        for (kc, _) in std::mem::take(&mut self.borrowed) {
+            self.remap(kc, NO_SYMBOL, per);
+        }
+        unsafe {
+            XSync(self.display, 0);
+        }
+    }
+}
+
+impl Drop for XKeyboard {
+    fn drop(&mut self) {
+        self.reset();
+        unsafe {
+            XCloseDisplay(self.display);
         }
-    });
+    }
 }
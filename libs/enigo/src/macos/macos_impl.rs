@@ -107,6 +107,8 @@ pub struct Enigo {
     multiple_click: i64,
     flags: CGEventFlags,
     char_to_vkey_map: Map<String, Map<char, CGKeyCode>>,
+    // the smooth scrolling not yet posted as whole pixels
+    scroll_rest: (i32, i32),
 }
 
 impl Enigo {
@@ -159,6 +161,7 @@ impl Default for Enigo {
             last_click_time: None,
             flags: CGEventFlags::CGEventFlagNull,
             char_to_vkey_map: Default::default(),
+            scroll_rest: (0, 0),
         }
     }
 }
@@ -314,6 +317,36 @@ impl MouseControllable for Enigo {
             }
         }
     }
+
+    fn mouse_scroll_smooth(&mut self, x: i32, y: i32) -> bool {
+        // about 10 pixels per notch
+        const UNITS_PER_PIXEL: i32 = 12;
+        let src = match self.event_source.as_ref() {
+            Some(src) => src,
+            None => return false,
+        };
+        self.scroll_rest.0 += x;
+        self.scroll_rest.1 += y;
+        let dx = self.scroll_rest.0 / UNITS_PER_PIXEL;
+        let dy = self.scroll_rest.1 / UNITS_PER_PIXEL;
+        self.scroll_rest.0 -= dx * UNITS_PER_PIXEL;
+        self.scroll_rest.1 -= dy * UNITS_PER_PIXEL;
+        if dx != 0 || dy != 0 {
+            unsafe {
+                let mouse_ev = CGEventCreateScrollWheelEvent(
+                    &src,
+                    ScrollUnit::Pixel,
+                    2, // CGWheelCount 1 = y 2 = xy 3 = xyz
+                    dy,
+                    -dx, // positive is left
+                );
+
+                CGEventPost(CGEventTapLocation::HID, mouse_ev);
+                CFRelease(mouse_ev as *const std::ffi::c_void);
+            }
+        }
+        true
+    }
 }
 
 // https://stackoverflow.
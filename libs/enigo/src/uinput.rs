@@ -0,0 +1,868 @@
+//! Virtual touchscreen, pen tablet, wheel and gamepad devices created through
+//! `/dev/uinput`, the X server, Wayland compositors and games pick them up like
+//! real hardware.
+use crate::ResultType;
+use libc::{c_int, c_ulong};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+    mem,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    slice,
+};
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const EV_FF: u16 = 0x15;
+const EV_UINPUT: u16 = 0x0101;
+const SYN_REPORT: u16 = 0;
+const UI_FF_UPLOAD: u16 = 1;
+const UI_FF_ERASE: u16 = 2;
+
+const BTN_LEFT: u16 = 0x110;
+const BTN_TOOL_PEN: u16 = 0x140;
+const BTN_TOOL_RUBBER: u16 = 0x141;
+const BTN_TOOL_FINGER: u16 = 0x145;
+const BTN_TOUCH: u16 = 0x14a;
+const BTN_STYLUS: u16 = 0x14b;
+const BTN_STYLUS2: u16 = 0x14c;
+const BTN_SOUTH: u16 = 0x130;
+const BTN_EAST: u16 = 0x131;
+const BTN_NORTH: u16 = 0x133;
+const BTN_WEST: u16 = 0x134;
+const BTN_TL: u16 = 0x136;
+const BTN_TR: u16 = 0x137;
+const BTN_SELECT: u16 = 0x13a;
+const BTN_START: u16 = 0x13b;
+const BTN_MODE: u16 = 0x13c;
+const BTN_THUMBL: u16 = 0x13d;
+const BTN_THUMBR: u16 = 0x13e;
+
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+const ABS_PRESSURE: u16 = 0x18;
+const ABS_TILT_X: u16 = 0x1a;
+const ABS_TILT_Y: u16 = 0x1b;
+const ABS_MT_SLOT: u16 = 0x2f;
+const ABS_MT_POSITION_X: u16 = 0x35;
+const ABS_MT_POSITION_Y: u16 = 0x36;
+const ABS_MT_TRACKING_ID: u16 = 0x39;
+const ABS_MT_PRESSURE: u16 = 0x3a;
+const ABS_CNT: usize = 0x40;
+
+const FF_RUMBLE: u16 = 0x50;
+const FF_GAIN: u16 = 0x60;
+
+const INPUT_PROP_DIRECT: c_int = 0x01;
+const BUS_USB: u16 = 0x03;
+const BUS_VIRTUAL: u16 = 0x06;
+
+// _IOW('U', n, int) and _IO('U', n)
+const UI_DEV_CREATE: c_ulong = 0x5501;
+const UI_DEV_DESTROY: c_ulong = 0x5502;
+const UI_SET_EVBIT: c_ulong = 0x40045564;
+const UI_SET_KEYBIT: c_ulong = 0x40045565;
+const UI_SET_RELBIT: c_ulong = 0x40045566;
+const UI_SET_ABSBIT: c_ulong = 0x40045567;
+const UI_SET_FFBIT: c_ulong = 0x4004556b;
+const UI_SET_PROPBIT: c_ulong = 0x4004556e;
+const UI_BEGIN_FF_UPLOAD: c_ulong = ioc(3, 200, mem::size_of::<UinputFfUpload>());
+const UI_END_FF_UPLOAD: c_ulong = ioc(1, 201, mem::size_of::<UinputFfUpload>());
+const UI_BEGIN_FF_ERASE: c_ulong = ioc(3, 202, mem::size_of::<UinputFfErase>());
+const UI_END_FF_ERASE: c_ulong = ioc(1, 203, mem::size_of::<UinputFfErase>());
+
+const MAX_TOUCH_SLOTS: usize = 10;
+const MAX_TOUCH_PRESSURE: i32 = 255;
+const MAX_PEN_PRESSURE: i32 = 4095;
+const MAX_TILT: i32 = 90;
+// the high-resolution wheel unit, as on Windows
+const WHEEL_DELTA: i32 = 120;
+const MAX_STICK: i32 = 32767;
+const MAX_TRIGGER: i32 = 255;
+const MAX_FF_EFFECTS: u32 = 16;
+
+const fn ioc(dir: c_ulong, nr: c_ulong, size: usize) -> c_ulong {
+    dir << 30 | (size as c_ulong) << 16 | (b'U' as c_ulong) << 8 | nr
+}
+
+#[repr(C)]
+struct InputId {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+// the legacy setup struct, understood by all the kernels we may run on
+#[repr(C)]
+struct UinputUserDev {
+    name: [u8; 80],
+    id: InputId,
+    ff_effects_max: u32,
+    absmax: [i32; ABS_CNT],
+    absmin: [i32; ABS_CNT],
+    absfuzz: [i32; ABS_CNT],
+    absflat: [i32; ABS_CNT],
+}
+
+// the force feedback structs of linux/input.h, only the rumble is read
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FfEnvelope {
+    attack_length: u16,
+    attack_level: u16,
+    fade_length: u16,
+    fade_level: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FfPeriodicEffect {
+    waveform: u16,
+    period: u16,
+    magnitude: i16,
+    offset: i16,
+    phase: u16,
+    envelope: FfEnvelope,
+    custom_len: u32,
+    custom_data: *mut i16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FfRumbleEffect {
+    strong_magnitude: u16,
+    weak_magnitude: u16,
+}
+
+// the largest member, the periodic effect, sets the size
+#[repr(C)]
+#[derive(Clone, Copy)]
+union FfEffectUnion {
+    periodic: FfPeriodicEffect,
+    rumble: FfRumbleEffect,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FfEffect {
+    type_: u16,
+    id: i16,
+    direction: u16,
+    trigger_button: u16,
+    trigger_interval: u16,
+    replay_length: u16,
+    replay_delay: u16,
+    u: FfEffectUnion,
+}
+
+#[repr(C)]
+struct UinputFfUpload {
+    request_id: u32,
+    retval: i32,
+    effect: FfEffect,
+    old: FfEffect,
+}
+
+#[repr(C)]
+struct UinputFfErase {
+    request_id: u32,
+    retval: i32,
+    effect_id: u32,
+}
+
+struct Device {
+    file: File,
+}
+
+impl Device {
+    fn new(
+        name: &str,
+        id: InputId,
+        keys: &[u16],
+        rel: &[u16],
+        abs: &[(u16, i32, i32)],
+        ff: &[u16],
+        direct: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")?;
+        let dev = Self { file };
+        dev.ioctl(UI_SET_EVBIT, EV_SYN as _)?;
+        dev.ioctl(UI_SET_EVBIT, EV_KEY as _)?;
+        for key in keys {
+            dev.ioctl(UI_SET_KEYBIT, *key as _)?;
+        }
+        if !rel.is_empty() {
+            dev.ioctl(UI_SET_EVBIT, EV_REL as _)?;
+        }
+        for code in rel {
+            dev.ioctl(UI_SET_RELBIT, *code as _)?;
+        }
+        if !abs.is_empty() {
+            dev.ioctl(UI_SET_EVBIT, EV_ABS as _)?;
+        }
+        let mut setup: UinputUserDev = unsafe { mem::zeroed() };
+        for (code, min, max) in abs {
+            dev.ioctl(UI_SET_ABSBIT, *code as _)?;
+            setup.absmin[*code as usize] = *min;
+            setup.absmax[*code as usize] = *max;
+        }
+        if !ff.is_empty() {
+            dev.ioctl(UI_SET_EVBIT, EV_FF as _)?;
+            setup.ff_effects_max = MAX_FF_EFFECTS;
+        }
+        for code in ff {
+            dev.ioctl(UI_SET_FFBIT, *code as _)?;
+        }
+        if direct {
+            dev.ioctl(UI_SET_PROPBIT, INPUT_PROP_DIRECT)?;
+        }
+        let n = name.len().min(setup.name.len() - 1);
+        setup.name[..n].copy_from_slice(&name.as_bytes()[..n]);
+        setup.id = id;
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                &setup as *const UinputUserDev as *const u8,
+                mem::size_of::<UinputUserDev>(),
+            )
+        };
+        (&dev.file).write_all(bytes)?;
+        dev.ioctl(UI_DEV_CREATE, 0)?;
+        Ok(dev)
+    }
+
+    fn ioctl(&self, request: c_ulong, value: c_int) -> ResultType {
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, value) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn ioctl_ptr<T>(&self, request: c_ulong, arg: &mut T) -> ResultType {
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg as *mut T) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    // the events written back by the kernel, like the force feedback requests
+    fn read(&self) -> ResultType<Vec<libc::input_event>> {
+        let mut events = Vec::new();
+        let mut buf = [0u8; mem::size_of::<libc::input_event>()];
+        loop {
+            match (&self.file).read(&mut buf) {
+                Ok(n) if n == buf.len() => {
+                    events.push(unsafe {
+                        std::ptr::read_unaligned(buf.as_ptr() as *const libc::input_event)
+                    });
+                }
+                Ok(_) => break,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(events)
+    }
+
+    fn emit(&self, events: &[(u16, u16, i32)]) -> ResultType {
+        let mut buf = Vec::with_capacity((events.len() + 1) * mem::size_of::<libc::input_event>());
+        for (type_, code, value) in events
+            .iter()
+            .chain(std::iter::once(&(EV_SYN, SYN_REPORT, 0)))
+        {
+            // the kernel stamps the time itself
+            let ev = libc::input_event {
+                time: libc::timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                type_: *type_,
+                code: *code,
+                value: *value,
+            };
+            buf.extend_from_slice(unsafe {
+                slice::from_raw_parts(
+                    &ev as *const libc::input_event as *const u8,
+                    mem::size_of::<libc::input_event>(),
+                )
+            });
+        }
+        (&self.file).write_all(&buf)?;
+        Ok(())
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        self.ioctl(UI_DEV_DESTROY, 0).ok();
+    }
+}
+
+fn virtual_id(product: u16) -> InputId {
+    InputId {
+        bustype: BUS_VIRTUAL,
+        vendor: 0x1d6b,
+        product,
+        version: 1,
+    }
+}
+
+fn scale(v: f32, max: i32) -> i32 {
+    (v.max(0.).min(1.) * max as f32).round() as i32
+}
+
+/// One finger on a touch surface.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TouchContact {
+    /// Identifies the contact for as long as it stays on the surface
+    pub id: i32,
+    /// x coordinate in desktop pixels
+    pub x: i32,
+    /// y coordinate in desktop pixels
+    pub y: i32,
+    /// 0 - 1
+    pub pressure: f32,
+}
+
+/// The state of a pen hovering over or touching a tablet.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PenState {
+    /// x coordinate in desktop pixels
+    pub x: i32,
+    /// y coordinate in desktop pixels
+    pub y: i32,
+    /// 0 - 1
+    pub pressure: f32,
+    /// degrees, -90 - 90
+    pub tilt_x: i32,
+    /// degrees, -90 - 90
+    pub tilt_y: i32,
+    /// The eraser end is used
+    pub eraser: bool,
+    /// The tip touches the surface
+    pub touching: bool,
+    /// The pen is close enough to be tracked
+    pub in_range: bool,
+    /// Barrel buttons, bit 0 the first and bit 1 the second
+    pub buttons: u32,
+}
+
+// the contacts in the slots of the multi-touch protocol, by their ids
+#[derive(Default)]
+struct TouchSlots {
+    slots: [Option<i32>; MAX_TOUCH_SLOTS],
+    next_tracking_id: i32,
+}
+
+impl TouchSlots {
+    fn update(&mut self, contacts: &[TouchContact]) -> Vec<(u16, u16, i32)> {
+        let mut events = Vec::new();
+        for (slot, id) in self.slots.iter_mut().enumerate() {
+            if let Some(v) = *id {
+                if !contacts.iter().any(|c| c.id == v) {
+                    events.push((EV_ABS, ABS_MT_SLOT, slot as i32));
+                    events.push((EV_ABS, ABS_MT_TRACKING_ID, -1));
+                    *id = None;
+                }
+            }
+        }
+        for c in contacts {
+            let slot = match self.slots.iter().position(|s| *s == Some(c.id)) {
+                Some(slot) => slot,
+                None => match self.slots.iter().position(|s| s.is_none()) {
+                    Some(slot) => {
+                        self.slots[slot] = Some(c.id);
+                        events.push((EV_ABS, ABS_MT_SLOT, slot as i32));
+                        events.push((EV_ABS, ABS_MT_TRACKING_ID, self.next_tracking_id));
+                        self.next_tracking_id = self.next_tracking_id.wrapping_add(1) & i32::MAX;
+                        slot
+                    }
+                    None => continue,
+                },
+            };
+            events.push((EV_ABS, ABS_MT_SLOT, slot as i32));
+            events.push((EV_ABS, ABS_MT_POSITION_X, c.x));
+            events.push((EV_ABS, ABS_MT_POSITION_Y, c.y));
+            events.push((
+                EV_ABS,
+                ABS_MT_PRESSURE,
+                scale(c.pressure, MAX_TOUCH_PRESSURE),
+            ));
+        }
+        // single touch emulation for the clients not aware of slots
+        let first = self
+            .slots
+            .iter()
+            .filter_map(|s| *s)
+            .find_map(|id| contacts.iter().find(|c| c.id == id));
+        let down = first.is_some() as i32;
+        events.push((EV_KEY, BTN_TOUCH, down));
+        events.push((EV_KEY, BTN_TOOL_FINGER, down));
+        if let Some(c) = first {
+            events.push((EV_ABS, ABS_X, c.x));
+            events.push((EV_ABS, ABS_Y, c.y));
+            events.push((EV_ABS, ABS_PRESSURE, scale(c.pressure, MAX_TOUCH_PRESSURE)));
+        } else {
+            events.push((EV_ABS, ABS_PRESSURE, 0));
+        }
+        events
+    }
+}
+
+/// A virtual multi-touch screen covering the whole desktop.
+pub struct VirtualTouchscreen {
+    dev: Device,
+    slots: TouchSlots,
+}
+
+impl VirtualTouchscreen {
+    /// Create the device, `width` and `height` are the size of the desktop.
+    pub fn new(width: i32, height: i32) -> Result<Self, Box<dyn std::error::Error>> {
+        let dev = Device::new(
+            "RustDesk Touchscreen",
+            virtual_id(0x0001),
+            &[BTN_TOUCH, BTN_TOOL_FINGER],
+            &[],
+            &[
+                (ABS_X, 0, width - 1),
+                (ABS_Y, 0, height - 1),
+                (ABS_PRESSURE, 0, MAX_TOUCH_PRESSURE),
+                (ABS_MT_SLOT, 0, MAX_TOUCH_SLOTS as i32 - 1),
+                (ABS_MT_TRACKING_ID, 0, i32::MAX),
+                (ABS_MT_POSITION_X, 0, width - 1),
+                (ABS_MT_POSITION_Y, 0, height - 1),
+                (ABS_MT_PRESSURE, 0, MAX_TOUCH_PRESSURE),
+            ],
+            &[],
+            true,
+        )?;
+        Ok(Self {
+            dev,
+            slots: Default::default(),
+        })
+    }
+
+    /// Report all the contacts currently on the surface, the ones missing
+    /// compared to the previous call are lifted.
+    pub fn update(&mut self, contacts: &[TouchContact]) -> ResultType {
+        let events = self.slots.update(contacts);
+        self.dev.emit(&events)
+    }
+}
+
+// the reports moving the pen from `last` to `state`, each one synced on its own
+fn pen_events(last: &PenState, state: &PenState) -> Vec<Vec<(u16, u16, i32)>> {
+    let mut res = Vec::new();
+    let mut events = Vec::new();
+    // switching between tip and eraser needs the old tool out of range first
+    if last.in_range && (!state.in_range || last.eraser != state.eraser) {
+        events.push((EV_KEY, BTN_TOUCH, 0));
+        events.push((EV_ABS, ABS_PRESSURE, 0));
+        events.push((EV_KEY, pen_tool(last), 0));
+        res.push(std::mem::take(&mut events));
+    }
+    if state.in_range {
+        events.push((EV_ABS, ABS_X, state.x));
+        events.push((EV_ABS, ABS_Y, state.y));
+        events.push((
+            EV_ABS,
+            ABS_TILT_X,
+            state.tilt_x.max(-MAX_TILT).min(MAX_TILT),
+        ));
+        events.push((
+            EV_ABS,
+            ABS_TILT_Y,
+            state.tilt_y.max(-MAX_TILT).min(MAX_TILT),
+        ));
+        let pressure = if state.touching {
+            scale(state.pressure, MAX_PEN_PRESSURE).max(1)
+        } else {
+            0
+        };
+        events.push((EV_ABS, ABS_PRESSURE, pressure));
+        events.push((EV_KEY, pen_tool(state), 1));
+        events.push((EV_KEY, BTN_TOUCH, state.touching as i32));
+        events.push((EV_KEY, BTN_STYLUS, (state.buttons & 1 != 0) as i32));
+        events.push((EV_KEY, BTN_STYLUS2, (state.buttons & 2 != 0) as i32));
+        res.push(events);
+    }
+    res
+}
+
+fn pen_tool(state: &PenState) -> u16 {
+    if state.eraser {
+        BTN_TOOL_RUBBER
+    } else {
+        BTN_TOOL_PEN
+    }
+}
+
+/// A virtual pen tablet covering the whole desktop.
+pub struct VirtualPen {
+    dev: Device,
+    last: PenState,
+}
+
+impl VirtualPen {
+    /// Create the device, `width` and `height` are the size of the desktop.
+    pub fn new(width: i32, height: i32) -> Result<Self, Box<dyn std::error::Error>> {
+        let dev = Device::new(
+            "RustDesk Pen",
+            virtual_id(0x0002),
+            &[
+                BTN_TOOL_PEN,
+                BTN_TOOL_RUBBER,
+                BTN_TOUCH,
+                BTN_STYLUS,
+                BTN_STYLUS2,
+            ],
+            &[],
+            &[
+                (ABS_X, 0, width - 1),
+                (ABS_Y, 0, height - 1),
+                (ABS_PRESSURE, 0, MAX_PEN_PRESSURE),
+                (ABS_TILT_X, -MAX_TILT, MAX_TILT),
+                (ABS_TILT_Y, -MAX_TILT, MAX_TILT),
+            ],
+            &[],
+            true,
+        )?;
+        Ok(Self {
+            dev,
+            last: Default::default(),
+        })
+    }
+
+    /// Report the new state of the pen.
+    pub fn update(&mut self, state: &PenState) -> ResultType {
+        let reports = pen_events(&self.last, state);
+        self.last = *state;
+        for events in reports {
+            self.dev.emit(&events)?;
+        }
+        Ok(())
+    }
+}
+
+/// A virtual mouse with high-resolution wheels, which libinput turns into
+/// smooth scrolling.
+pub struct VirtualWheel {
+    dev: Device,
+    // the high-resolution values not yet reported as whole notches
+    rest: (i32, i32),
+}
+
+impl VirtualWheel {
+    /// Create the device.
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let dev = Device::new(
+            "RustDesk Wheel",
+            virtual_id(0x0003),
+            &[BTN_LEFT],
+            &[
+                REL_X,
+                REL_Y,
+                REL_WHEEL,
+                REL_HWHEEL,
+                REL_WHEEL_HI_RES,
+                REL_HWHEEL_HI_RES,
+            ],
+            &[],
+            &[],
+            false,
+        )?;
+        Ok(Self { dev, rest: (0, 0) })
+    }
+
+    /// Scroll by `x` and `y` in 1/120 of a notch, positive is right and up.
+    pub fn scroll(&mut self, x: i32, y: i32) -> ResultType {
+        let mut events = Vec::new();
+        // the legacy axes are still read by the clients not knowing the high-resolution ones
+        for (v, rest, hi_res, notch) in [
+            (y, &mut self.rest.1, REL_WHEEL_HI_RES, REL_WHEEL),
+            (x, &mut self.rest.0, REL_HWHEEL_HI_RES, REL_HWHEEL),
+        ] {
+            if v == 0 {
+                continue;
+            }
+            events.push((EV_REL, hi_res, v));
+            *rest += v;
+            let n = *rest / WHEEL_DELTA;
+            if n != 0 {
+                events.push((EV_REL, notch, n));
+                *rest -= n * WHEEL_DELTA;
+            }
+        }
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.dev.emit(&events)
+    }
+}
+
+/// The state of a game controller.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GamepadState {
+    /// Bits 0 - 14: south, east, west, north, left and right bumpers, select,
+    /// start, mode, left and right thumbs, d-pad up, down, left and right
+    pub buttons: u32,
+    /// -32767 - 32767, positive is right
+    pub left_x: i32,
+    /// -32767 - 32767, positive is down
+    pub left_y: i32,
+    /// -32767 - 32767, positive is right
+    pub right_x: i32,
+    /// -32767 - 32767, positive is down
+    pub right_y: i32,
+    /// 0 - 255
+    pub left_trigger: i32,
+    /// 0 - 255
+    pub right_trigger: i32,
+}
+
+/// Force feedback played by a game.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rumble {
+    /// 0 - 65535, the low frequency motor
+    pub strong: u16,
+    /// 0 - 65535, the high frequency motor
+    pub weak: u16,
+    /// ms, 0 until stopped
+    pub duration: u16,
+}
+
+// in the order of the bits of GamepadState::buttons, the d-pad is a hat
+const GAMEPAD_BUTTONS: [u16; 11] = [
+    BTN_SOUTH, BTN_EAST, BTN_WEST, BTN_NORTH, BTN_TL, BTN_TR, BTN_SELECT, BTN_START, BTN_MODE,
+    BTN_THUMBL, BTN_THUMBR,
+];
+
+/// A virtual Xbox 360 like controller with rumble.
+pub struct VirtualGamepad {
+    dev: Device,
+    effects: HashMap<i16, Rumble>,
+    playing: Option<i16>,
+}
+
+impl VirtualGamepad {
+    /// Create the device.
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let dev = Device::new(
+            "RustDesk Gamepad",
+            // the ids of the Xbox 360 controller, for the games to know the layout
+            InputId {
+                bustype: BUS_USB,
+                vendor: 0x045e,
+                product: 0x028e,
+                version: 0x0110,
+            },
+            &GAMEPAD_BUTTONS,
+            &[],
+            &[
+                (ABS_X, -MAX_STICK, MAX_STICK),
+                (ABS_Y, -MAX_STICK, MAX_STICK),
+                (ABS_RX, -MAX_STICK, MAX_STICK),
+                (ABS_RY, -MAX_STICK, MAX_STICK),
+                (ABS_Z, 0, MAX_TRIGGER),
+                (ABS_RZ, 0, MAX_TRIGGER),
+                (ABS_HAT0X, -1, 1),
+                (ABS_HAT0Y, -1, 1),
+            ],
+            &[FF_RUMBLE],
+            false,
+        )?;
+        Ok(Self {
+            dev,
+            effects: Default::default(),
+            playing: None,
+        })
+    }
+
+    /// Report the new state of the controller.
+    pub fn update(&mut self, state: &GamepadState) -> ResultType {
+        let bit = |i: u32| ((state.buttons >> i) & 1) as i32;
+        let stick = |v: i32| v.max(-MAX_STICK).min(MAX_STICK);
+        let trigger = |v: i32| v.max(0).min(MAX_TRIGGER);
+        let mut events: Vec<(u16, u16, i32)> = GAMEPAD_BUTTONS
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (EV_KEY, *key, bit(i as _)))
+            .collect();
+        events.push((EV_ABS, ABS_X, stick(state.left_x)));
+        events.push((EV_ABS, ABS_Y, stick(state.left_y)));
+        events.push((EV_ABS, ABS_RX, stick(state.right_x)));
+        events.push((EV_ABS, ABS_RY, stick(state.right_y)));
+        events.push((EV_ABS, ABS_Z, trigger(state.left_trigger)));
+        events.push((EV_ABS, ABS_RZ, trigger(state.right_trigger)));
+        events.push((EV_ABS, ABS_HAT0X, bit(14) - bit(13)));
+        events.push((EV_ABS, ABS_HAT0Y, bit(12) - bit(11)));
+        // the kernel drops the values not changed
+        self.dev.emit(&events)
+    }
+
+    /// Answer the force feedback requests of the games, returns the rumble to
+    /// play when it changes, all 0 to stop.
+    pub fn poll_rumble(&mut self) -> ResultType<Option<Rumble>> {
+        let mut res = None;
+        for ev in self.dev.read()? {
+            match (ev.type_, ev.code) {
+                (EV_UINPUT, UI_FF_UPLOAD) => {
+                    let mut upload: UinputFfUpload = unsafe { mem::zeroed() };
+                    upload.request_id = ev.value as _;
+                    self.dev.ioctl_ptr(UI_BEGIN_FF_UPLOAD, &mut upload)?;
+                    let effect = upload.effect;
+                    if effect.type_ == FF_RUMBLE {
+                        let rumble = unsafe { effect.u.rumble };
+                        let v = Rumble {
+                            strong: rumble.strong_magnitude,
+                            weak: rumble.weak_magnitude,
+                            duration: effect.replay_length,
+                        };
+                        self.effects.insert(effect.id, v);
+                        if self.playing == Some(effect.id) {
+                            res = Some(v);
+                        }
+                    } else {
+                        upload.retval = -libc::EINVAL;
+                    }
+                    self.dev.ioctl_ptr(UI_END_FF_UPLOAD, &mut upload)?;
+                }
+                (EV_UINPUT, UI_FF_ERASE) => {
+                    let mut erase: UinputFfErase = unsafe { mem::zeroed() };
+                    erase.request_id = ev.value as _;
+                    self.dev.ioctl_ptr(UI_BEGIN_FF_ERASE, &mut erase)?;
+                    let id = erase.effect_id as i16;
+                    self.effects.remove(&id);
+                    if self.playing == Some(id) {
+                        self.playing = None;
+                        res = Some(Default::default());
+                    }
+                    self.dev.ioctl_ptr(UI_END_FF_ERASE, &mut erase)?;
+                }
+                (EV_FF, FF_GAIN) => {}
+                (EV_FF, code) => {
+                    let id = code as i16;
+                    if ev.value > 0 {
+                        if let Some(v) = self.effects.get(&id) {
+                            self.playing = Some(id);
+                            res = Some(*v);
+                        }
+                    } else if self.playing == Some(id) {
+                        self.playing = None;
+                        res = Some(Default::default());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(id: i32, x: i32) -> TouchContact {
+        TouchContact {
+            id,
+            x,
+            y: 0,
+            pressure: 1.,
+        }
+    }
+
+    fn find(events: &[(u16, u16, i32)], code: u16) -> Vec<i32> {
+        events.iter().filter(|e| e.1 == code).map(|e| e.2).collect()
+    }
+
+    #[test]
+    fn test_touch_slots() {
+        let mut slots = TouchSlots::default();
+        let events = slots.update(&[contact(7, 10), contact(9, 20)]);
+        assert_eq!(find(&events, ABS_MT_TRACKING_ID), vec![0, 1]);
+        assert_eq!(slots.slots[..2], [Some(7), Some(9)]);
+        assert_eq!(find(&events, BTN_TOUCH), vec![1]);
+        assert_eq!(find(&events, ABS_X), vec![10]);
+        // the first lifted, the second keeps its slot and leads the single touch
+        let events = slots.update(&[contact(9, 25)]);
+        assert_eq!(find(&events, ABS_MT_TRACKING_ID), vec![-1]);
+        assert_eq!(slots.slots[..2], [None, Some(9)]);
+        assert_eq!(find(&events, ABS_MT_SLOT), vec![0, 1]);
+        assert_eq!(find(&events, ABS_X), vec![25]);
+        // a new contact takes the free slot with a new tracking id
+        let events = slots.update(&[contact(9, 25), contact(3, 30)]);
+        assert_eq!(find(&events, ABS_MT_TRACKING_ID), vec![2]);
+        assert_eq!(slots.slots[..2], [Some(3), Some(9)]);
+        let events = slots.update(&[]);
+        assert_eq!(find(&events, ABS_MT_TRACKING_ID), vec![-1, -1]);
+        assert_eq!(find(&events, BTN_TOUCH), vec![0]);
+        assert_eq!(find(&events, ABS_PRESSURE), vec![0]);
+        // the ones beyond the slots are dropped
+        let contacts: Vec<_> = (0..MAX_TOUCH_SLOTS as i32 + 2)
+            .map(|i| contact(i, i))
+            .collect();
+        slots.update(&contacts);
+        assert!(slots.slots.iter().all(|s| s.is_some()));
+        assert!(!slots.slots.contains(&Some(MAX_TOUCH_SLOTS as i32)));
+    }
+
+    #[test]
+    fn test_pen_events() {
+        let hover = PenState {
+            x: 5,
+            y: 6,
+            tilt_x: 120,
+            in_range: true,
+            ..Default::default()
+        };
+        let reports = pen_events(&Default::default(), &hover);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(find(&reports[0], BTN_TOOL_PEN), vec![1]);
+        assert_eq!(find(&reports[0], BTN_TOUCH), vec![0]);
+        assert_eq!(find(&reports[0], ABS_TILT_X), vec![MAX_TILT]);
+        // a touching tip never reports no pressure
+        let touch = PenState {
+            touching: true,
+            buttons: 2,
+            ..hover
+        };
+        let reports = pen_events(&hover, &touch);
+        assert_eq!(find(&reports[0], ABS_PRESSURE), vec![1]);
+        assert_eq!(find(&reports[0], BTN_STYLUS), vec![0]);
+        assert_eq!(find(&reports[0], BTN_STYLUS2), vec![1]);
+        // the pen is out of range before the eraser comes in
+        let eraser = PenState {
+            eraser: true,
+            ..touch
+        };
+        let reports = pen_events(&touch, &eraser);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(find(&reports[0], BTN_TOOL_PEN), vec![0]);
+        assert_eq!(find(&reports[1], BTN_TOOL_RUBBER), vec![1]);
+        let reports = pen_events(&eraser, &Default::default());
+        assert_eq!(reports.len(), 1);
+        assert_eq!(find(&reports[0], BTN_TOOL_RUBBER), vec![0]);
+        assert!(pen_events(&Default::default(), &Default::default()).is_empty());
+    }
+}
@@ -161,6 +161,16 @@ impl MouseControllable for Enigo {
     fn mouse_scroll_y(&mut self, length: i32) {
         mouse_event(MOUSEEVENTF_WHEEL, unsafe { transmute(length * 120) }, 0, 0);
     }
+
+    fn mouse_scroll_smooth(&mut self, x: i32, y: i32) -> bool {
+        if x != 0 {
+            mouse_event(MOUSEEVENTF_HWHEEL, unsafe { transmute(x) }, 0, 0);
+        }
+        if y != 0 {
+            mouse_event(MOUSEEVENTF_WHEEL, unsafe { transmute(y) }, 0, 0);
+        }
+        true
+    }
 }
 
 impl KeyboardControllable for Enigo {
@@ -138,15 +138,23 @@ pub struct PeerConfig {
     #[serde(default)]
     pub disable_audio: bool,
     #[serde(default)]
+    pub audio_profile: String, // standard, voice, music, off
+    #[serde(default)]
     pub disable_clipboard: bool,
     #[serde(default)]
     pub enable_file_transfer: bool,
+    #[serde(default)]
+    pub keymap: Vec<(String, String)>, // remapped key chords, from -> to
 
     // the other scalar value must before this
     #[serde(default)]
     pub options: HashMap<String, String>,
     #[serde(default)]
+    pub macros: HashMap<String, String>, // name -> enigo dsl
+    #[serde(default)]
     pub info: PeerInfoSerde,
+    #[serde(default)]
+    pub transfer_jobs: Vec<crate::fs::TransferJobMeta>, // unfinished
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, Clone)]
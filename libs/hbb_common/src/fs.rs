@@ -1,5 +1,10 @@
 use crate::{bail, message_proto::*, ResultType};
-use std::path::{Path, PathBuf};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 // https://doc.rust-lang.org/std/os/windows/fs/trait.MetadataExt.html
 use crate::{
     compress::{compress, decompress},
@@ -7,7 +12,10 @@ use crate::{
 };
 #[cfg(windows)]
 use std::os::windows::prelude::*;
-use tokio::{fs::File, io::*};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::*,
+};
 
 pub fn read_dir(path: &PathBuf, include_hidden: bool) -> ResultType<FileDirectory> {
     let mut dir = FileDirectory {
@@ -184,6 +192,274 @@ pub fn get_recursive_files(path: &str, include_hidden: bool) -> ResultType<Vec<F
     read_dir_recursive(&get_path(path), &get_path(""), include_hidden)
 }
 
+// the sizes already written in `path` of the files to be received again,
+// either partially in .download, or completely with the modified time set
+pub fn get_resume_offsets(path: &str, files: &[FileEntry]) -> Vec<FileTransferOffset> {
+    let path = get_path(path);
+    files
+        .iter()
+        .filter_map(|entry| {
+            let p = if entry.name.is_empty() {
+                path.clone()
+            } else {
+                path.join(&entry.name)
+            };
+            let download_path = format!("{}.download", get_string(&p));
+            let offset = if let Ok(meta) = std::fs::metadata(&download_path) {
+                meta.len()
+            } else {
+                let meta = std::fs::metadata(&p).ok()?;
+                if !is_identical(&meta, entry) {
+                    return None;
+                }
+                meta.len()
+            };
+            if offset == 0 || offset > entry.size {
+                return None;
+            }
+            Some(FileTransferOffset {
+                name: entry.name.clone(),
+                offset,
+                size: entry.size,
+                modified_time: entry.modified_time,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+// the same size and modified time as the entry
+fn is_identical(meta: &std::fs::Metadata, entry: &FileEntry) -> bool {
+    let modified_time = meta
+        .modified()
+        .ok()
+        .and_then(|x| x.duration_since(std::time::SystemTime::UNIX_EPOCH).ok())
+        .map(|x| x.as_secs());
+    meta.len() == entry.size && modified_time == Some(entry.modified_time)
+}
+
+// the files of `src` new or changed at `dst` by the size or modified time, and the files
+// at `dst` not in `src`, the names compared with either separator
+pub fn get_sync_files(src: &[FileEntry], dst: &[FileEntry]) -> (Vec<FileEntry>, Vec<FileEntry>) {
+    let key = |name: &str| name.replace('\\', "/");
+    let dst_files: HashMap<String, &FileEntry> = dst.iter().map(|f| (key(&f.name), f)).collect();
+    let src_names: HashSet<String> = src.iter().map(|f| key(&f.name)).collect();
+    let files = src
+        .iter()
+        .filter(|f| match dst_files.get(&key(&f.name)) {
+            Some(d) => d.size != f.size || d.modified_time != f.modified_time,
+            None => true,
+        })
+        .cloned()
+        .collect();
+    let extras = dst
+        .iter()
+        .filter(|f| !src_names.contains(&key(&f.name)))
+        .cloned()
+        .collect();
+    (files, extras)
+}
+
+const MIN_DELTA_SIZE: u64 = 64 * 1024;
+
+// about the square root of the file size as rsync
+#[inline]
+fn get_block_size(size: u64) -> usize {
+    ((size as f64).sqrt() as usize / 1024 * 1024).clamp(2048, 128 * 1024)
+}
+
+#[inline]
+fn get_strong_hash(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data)[..16].to_vec()
+}
+
+// the blocks of the files existing in `path`, for the sender to send what differs only,
+// the partial downloads are resumed instead
+pub fn get_signatures(path: &str, files: &[FileEntry]) -> Vec<FileSignature> {
+    use std::io::Read;
+    let path = get_path(path);
+    files
+        .iter()
+        .filter_map(|entry| {
+            let p = if entry.name.is_empty() {
+                path.clone()
+            } else {
+                path.join(&entry.name)
+            };
+            if Path::new(&format!("{}.download", get_string(&p))).exists() {
+                return None;
+            }
+            let meta = std::fs::metadata(&p).ok()?;
+            if !meta.is_file() || meta.len() < MIN_DELTA_SIZE {
+                return None;
+            }
+            let block_size = get_block_size(meta.len());
+            let mut file = std::fs::File::open(&p).ok()?;
+            let mut signature = FileSignature {
+                name: entry.name.clone(),
+                block_size: block_size as _,
+                ..Default::default()
+            };
+            // the partial block at the end is always sent
+            let mut buf = vec![0u8; block_size];
+            while file.read_exact(&mut buf).is_ok() {
+                signature.weak.push(RollingChecksum::new(&buf).digest());
+                signature.strong.push(get_strong_hash(&buf));
+            }
+            Some(signature)
+        })
+        .collect()
+}
+
+// the weak checksum of rsync, updated byte by byte when the window slides
+#[derive(Clone, Copy)]
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingChecksum {
+    fn new(data: &[u8]) -> Self {
+        let len = data.len() as u32;
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for (i, x) in data.iter().enumerate() {
+            a = a.wrapping_add(*x as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(*x as u32));
+        }
+        Self {
+            a: a & 0xffff,
+            b: b & 0xffff,
+            len,
+        }
+    }
+
+    #[inline]
+    fn roll(&mut self, out: u8, input: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(input as u32) & 0xffff;
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a)
+            & 0xffff;
+    }
+
+    #[inline]
+    fn digest(&self) -> u32 {
+        self.a | (self.b << 16)
+    }
+}
+
+// the file being read against the signature of the file at the destination
+struct Delta {
+    block_size: usize,
+    weak: HashMap<u32, Vec<usize>>,
+    strong: Vec<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize, // of the window in buf
+    eof: bool,
+    rolling: Option<RollingChecksum>, // of the window
+}
+
+impl Delta {
+    fn new(signature: &FileSignature) -> Self {
+        let mut weak: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, x) in signature.weak.iter().enumerate() {
+            weak.entry(*x).or_default().push(i);
+        }
+        Self {
+            block_size: signature.block_size as _,
+            weak,
+            strong: signature.strong.clone(),
+            buf: Vec::new(),
+            pos: 0,
+            eof: false,
+            rolling: None,
+        }
+    }
+
+    // the block at the destination with the same content
+    fn find(&self, weak: u32, data: &[u8]) -> Option<usize> {
+        let blocks = self.weak.get(&weak)?;
+        let strong = get_strong_hash(data);
+        blocks
+            .iter()
+            .find(|i| self.strong.get(**i) == Some(&strong))
+            .copied()
+    }
+}
+
+// a transfer job of the client, kept in the peer config until finished,
+// to be resumed after reconnecting
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+pub struct TransferJobMeta {
+    #[serde(default)]
+    pub id: i32, // in this session, 0 if not resumed yet
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub to: String,
+    #[serde(default)]
+    pub include_hidden: bool,
+    #[serde(default)]
+    pub is_remote: bool, // read from the peer
+    #[serde(default)]
+    pub file_num: i32,
+    #[serde(default)]
+    pub offset: u64, // of file_num when interrupted
+    #[serde(default)]
+    pub files: Vec<(String, u64, u64)>, // name, size and modified time when read at first
+}
+
+impl TransferJobMeta {
+    #[inline]
+    pub fn is_same_job(&self, is_remote: bool, path: &str, to: &str) -> bool {
+        self.is_remote == is_remote && self.path == path && self.to == to
+    }
+
+    pub fn set_files(&mut self, files: &[FileEntry]) {
+        self.files = files
+            .iter()
+            .map(|f| (f.name.clone(), f.size, f.modified_time))
+            .collect();
+    }
+
+    pub fn files(&self) -> Vec<FileEntry> {
+        self.files
+            .iter()
+            .map(|f| FileEntry {
+                entry_type: FileType::File.into(),
+                name: f.0.clone(),
+                size: f.1,
+                modified_time: f.2,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    // the offsets the peer reports of an upload are limited by the progress recorded,
+    // the files after it were never sent
+    pub fn check_offsets(&self, offsets: Vec<FileTransferOffset>) -> Vec<FileTransferOffset> {
+        offsets
+            .into_iter()
+            .filter_map(|mut o| {
+                let i = self.files.iter().position(|f| f.0 == o.name)?;
+                let (_, size, modified_time) = self.files[i];
+                if i as i32 > self.file_num {
+                    return None;
+                }
+                if i as i32 == self.file_num {
+                    o.offset = o.offset.min(self.offset);
+                }
+                o.size = size;
+                o.modified_time = modified_time;
+                Some(o)
+            })
+            .collect()
+    }
+}
+
 #[derive(Default)]
 pub struct TransferJob {
     id: i32,
@@ -194,8 +470,19 @@ pub struct TransferJob {
     total_size: u64,
     finished_size: u64,
     transferred: u64,
+    offset: u64, // in the file being read or written
+    offsets: HashMap<String, FileTransferOffset>,
+    waiting_offsets: bool,
+    hasher: Sha256, // of the file being read or written
+    signatures: HashMap<String, FileSignature>,
+    delta: Option<Delta>,            // of the file being read
+    basis: Option<(File, u64, u64)>, // to copy the blocks from, with the block size and count
+    conflict_policy: FileConflictPolicy,
+    skipped: HashSet<String>, // by the conflict policy
 }
 
+const BUF_SIZE: usize = 128 * 1024;
+
 #[inline]
 fn get_ext(name: &str) -> &str {
     if let Some(i) = name.rfind(".") {
@@ -218,6 +505,22 @@ fn is_compressed_file(name: &str) -> bool {
         || ext == "jpg"
 }
 
+// the part before the offset of a resumed file, to hash the whole file
+async fn hash_prefix(file: &mut File, len: u64, hasher: &mut Sha256) -> ResultType<()> {
+    let mut buf = vec![0u8; 128 * 1024];
+    let mut left = len;
+    while left > 0 {
+        let n = (buf.len() as u64).min(left) as usize;
+        let n = file.read(&mut buf[..n]).await?;
+        if n == 0 {
+            bail!("Unexpected end of file");
+        }
+        hasher.update(&buf[..n]);
+        left -= n as u64;
+    }
+    Ok(())
+}
+
 impl TransferJob {
     pub fn new_write(id: i32, path: String, files: Vec<FileEntry>) -> Self {
         let total_size = files.iter().map(|x| x.size as u64).sum();
@@ -249,6 +552,7 @@ impl TransferJob {
 
     #[inline]
     pub fn set_files(&mut self, files: Vec<FileEntry>) {
+        self.total_size = files.iter().map(|x| x.size as u64).sum();
         self.files = files;
     }
 
@@ -257,6 +561,11 @@ impl TransferJob {
         self.id
     }
 
+    #[inline]
+    pub fn path(&self) -> String {
+        get_string(&self.path)
+    }
+
     #[inline]
     pub fn total_size(&self) -> u64 {
         self.total_size
@@ -277,10 +586,151 @@ impl TransferJob {
         self.file_num
     }
 
+    // in the file of file_num, 0 if not opened yet
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        if self.file.is_some() {
+            self.offset
+        } else {
+            0
+        }
+    }
+
+    // the reading is held until the receiver replies the offsets
+    #[inline]
+    pub fn wait_offsets(&mut self) {
+        self.waiting_offsets = true;
+    }
+
+    #[inline]
+    pub fn is_waiting_offsets(&self) -> bool {
+        self.waiting_offsets
+    }
+
+    pub fn set_offsets(&mut self, offsets: Vec<FileTransferOffset>) {
+        let skipped = &self.skipped;
+        self.offsets.extend(
+            offsets
+                .into_iter()
+                .filter(|o| !skipped.contains(&o.name))
+                .map(|o| (o.name.clone(), o)),
+        );
+        self.waiting_offsets = false;
+    }
+
+    // only the blocks differing from these are sent, set on both sides
+    pub fn set_signatures(&mut self, signatures: Vec<FileSignature>) {
+        self.signatures = signatures
+            .into_iter()
+            .filter(|s| s.block_size > 0 && s.weak.len() == s.strong.len())
+            .map(|s| (s.name.clone(), s))
+            .collect();
+    }
+
+    #[inline]
+    pub fn set_conflict_policy(&mut self, policy: FileConflictPolicy) {
+        self.conflict_policy = policy;
+    }
+
+    #[inline]
+    pub fn conflict_policy(&self) -> FileConflictPolicy {
+        self.conflict_policy
+    }
+
+    // the files existing at the destination skipped by the conflict policy,
+    // and the ones to be asked, called on the receiver before any block is written
+    pub fn resolve_conflicts(&mut self) -> (Vec<String>, Vec<String>) {
+        let mut skipped = Vec::new();
+        let mut asked = Vec::new();
+        for entry in self.files.iter() {
+            let meta = match std::fs::metadata(self.join(&entry.name)) {
+                Ok(meta) if meta.is_file() => meta,
+                _ => continue,
+            };
+            match self.conflict_policy {
+                FileConflictPolicy::Skip => skipped.push(entry.name.clone()),
+                FileConflictPolicy::SkipIdentical if is_identical(&meta, entry) => {
+                    skipped.push(entry.name.clone())
+                }
+                FileConflictPolicy::Ask => asked.push(entry.name.clone()),
+                _ => {}
+            }
+        }
+        self.skip_files(&skipped);
+        (skipped, asked)
+    }
+
+    // neither read nor written, as if completely transferred before
+    pub fn skip_files(&mut self, names: &[String]) {
+        for name in names.iter() {
+            if let Some(entry) = self.files.iter().find(|x| &x.name == name) {
+                self.offsets.insert(
+                    name.clone(),
+                    FileTransferOffset {
+                        name: name.clone(),
+                        offset: entry.size,
+                        size: entry.size,
+                        modified_time: entry.modified_time,
+                        ..Default::default()
+                    },
+                );
+                self.skipped.insert(name.clone());
+            }
+        }
+    }
+
+    // "name (n).ext" existing neither completely nor partially
+    fn rename_if_exists(&mut self, file_num: usize) {
+        let path = self.join(&self.files[file_num].name);
+        let exists =
+            |p: &PathBuf| p.exists() || Path::new(&format!("{}.download", get_string(p))).exists();
+        if !exists(&path) {
+            return;
+        }
+        let stem = path
+            .file_stem()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|x| format!(".{}", x.to_string_lossy()))
+            .unwrap_or_default();
+        for i in 1.. {
+            let p = path.with_file_name(format!("{} ({}){}", stem, i, ext));
+            if !exists(&p) {
+                let entry = &mut self.files[file_num];
+                if entry.name.is_empty() {
+                    self.path = p;
+                } else {
+                    entry.name =
+                        get_string(&Path::new(&entry.name).with_file_name(get_file_name(&p)));
+                }
+                break;
+            }
+        }
+    }
+
+    // where to start reading, 0 if the file changed since the offset was written
+    fn get_offset(&self, entry: &FileEntry) -> u64 {
+        match self.offsets.get(&entry.name) {
+            Some(o)
+                if o.size == entry.size
+                    && o.modified_time == entry.modified_time
+                    && o.offset <= entry.size =>
+            {
+                o.offset
+            }
+            _ => 0,
+        }
+    }
+
     pub fn modify_time(&self) {
         let file_num = self.file_num as usize;
         if file_num < self.files.len() {
             let entry = &self.files[file_num];
+            if self.skipped.contains(&entry.name) {
+                return;
+            }
             let path = self.join(&entry.name);
             let download_path = format!("{}.download", get_string(&path));
             std::fs::rename(&download_path, &path).ok();
@@ -310,19 +760,72 @@ impl TransferJob {
         if file_num >= self.files.len() {
             bail!("Wrong file number");
         }
+        if self.skipped.contains(&self.files[file_num].name) {
+            return Ok(());
+        }
         if file_num != self.file_num as usize || self.file.is_none() {
             self.modify_time();
             if let Some(file) = self.file.as_mut() {
                 file.sync_all().await?;
             }
             self.file_num = block.file_num;
+            // the signatures are of the file existing before renamed
+            let name = self.files[file_num].name.clone();
+            if block.offset == 0 && self.conflict_policy == FileConflictPolicy::Rename {
+                self.rename_if_exists(file_num);
+            }
             let entry = &self.files[file_num];
             let path = self.join(&entry.name);
             if let Some(p) = path.parent() {
                 std::fs::create_dir_all(p).ok();
             }
             let path = format!("{}.download", get_string(&path));
-            self.file = Some(File::create(&path).await?);
+            // the part before the offset is kept when resumed
+            let mut file = OpenOptions::new()
+                .create(true)
+                .read(true)
+                .write(true)
+                .open(&path)
+                .await?;
+            file.set_len(block.offset).await?;
+            self.hasher = Sha256::new();
+            if block.offset > 0 {
+                file.seek(SeekFrom::Start(0)).await?;
+                hash_prefix(&mut file, block.offset, &mut self.hasher).await?;
+            }
+            self.file = Some(file);
+            self.offset = block.offset;
+            self.finished_size += block.offset;
+            self.basis = None;
+            if block.offset == 0 {
+                if let Some(s) = self.signatures.get(&name) {
+                    if let Ok(file) = File::open(self.join(&name)).await {
+                        self.basis = Some((file, s.block_size, s.weak.len() as u64));
+                    }
+                }
+            }
+        }
+        if !block.block_refs.is_empty() {
+            let (basis, block_size, count) = match self.basis.as_mut() {
+                Some((basis, block_size, count)) if *block_size > 0 => (basis, *block_size, *count),
+                _ => bail!("No blocks to copy"),
+            };
+            if block.block_refs.iter().any(|i| *i >= count) {
+                bail!("Block out of range");
+            }
+            let mut buf = vec![0u8; block_size as usize];
+            for i in block.block_refs.iter() {
+                let pos = match i.checked_mul(block_size) {
+                    Some(pos) => pos,
+                    None => bail!("Block out of range"),
+                };
+                basis.seek(SeekFrom::Start(pos)).await?;
+                basis.read_exact(&mut buf).await?;
+                self.file.as_mut().unwrap().write_all(&buf).await?;
+                self.hasher.update(&buf);
+                self.finished_size += block_size;
+                self.offset += block_size;
+            }
         }
         let data = if let Some(data) = raw {
             data
@@ -332,12 +835,26 @@ impl TransferJob {
         if block.compressed {
             let tmp = decompress(data);
             self.file.as_mut().unwrap().write_all(&tmp).await?;
+            self.hasher.update(&tmp);
             self.finished_size += tmp.len() as u64;
+            self.offset += tmp.len() as u64;
         } else {
             self.file.as_mut().unwrap().write_all(data).await?;
+            self.hasher.update(data);
             self.finished_size += data.len() as u64;
+            self.offset += data.len() as u64;
         }
         self.transferred += data.len() as u64;
+        // verified before renamed, the peers of old versions send no hash
+        if data.is_empty() && !block.sha256.is_empty() {
+            self.basis = None;
+            let hash = std::mem::take(&mut self.hasher).finalize();
+            if hash[..] != block.sha256[..] {
+                self.file = None;
+                self.remove_download_file();
+                bail!("Checksum mismatch");
+            }
+        }
         Ok(())
     }
 
@@ -351,6 +868,16 @@ impl TransferJob {
     }
 
     pub async fn read(&mut self) -> ResultType<Option<FileTransferBlock>> {
+        // the files completely written before are skipped when resumed
+        while self.file.is_none() && (self.file_num as usize) < self.files.len() {
+            let entry = &self.files[self.file_num as usize];
+            let offset = self.get_offset(entry);
+            if offset == 0 || offset < entry.size {
+                break;
+            }
+            self.finished_size += entry.size;
+            self.file_num += 1;
+        }
         let file_num = self.file_num as usize;
         if file_num >= self.files.len() {
             self.file.take();
@@ -358,9 +885,24 @@ impl TransferJob {
         }
         let name = &self.files[file_num].name;
         if self.file.is_none() {
+            let offset = self.get_offset(&self.files[file_num]);
             match File::open(self.join(&name)).await {
-                Ok(file) => {
+                Ok(mut file) => {
+                    self.hasher = Sha256::new();
+                    if offset > 0 {
+                        if let Err(err) = hash_prefix(&mut file, offset, &mut self.hasher).await {
+                            self.file_num += 1;
+                            return Err(err);
+                        }
+                        self.finished_size += offset;
+                    }
                     self.file = Some(file);
+                    self.offset = offset;
+                    self.delta = if offset == 0 {
+                        self.signatures.get(name).map(Delta::new)
+                    } else {
+                        None
+                    };
                 }
                 Err(err) => {
                     self.file_num += 1;
@@ -368,7 +910,9 @@ impl TransferJob {
                 }
             }
         }
-        const BUF_SIZE: usize = 128 * 1024;
+        if self.delta.is_some() {
+            return self.read_delta(file_num).await;
+        }
         let mut buf: Vec<u8> = Vec::with_capacity(BUF_SIZE);
         unsafe {
             buf.set_len(BUF_SIZE);
@@ -391,11 +935,16 @@ impl TransferJob {
             }
         }
         unsafe { buf.set_len(offset) };
+        let position = self.offset;
+        let mut sha256 = Vec::new();
         if offset == 0 {
             self.file_num += 1;
             self.file = None;
+            sha256 = std::mem::take(&mut self.hasher).finalize().to_vec();
         } else {
+            self.hasher.update(&buf);
             self.finished_size += offset as u64;
+            self.offset += offset as u64;
             if !is_compressed_file(name) {
                 let tmp = compress(&buf, COMPRESS_LEVEL);
                 if tmp.len() < buf.len() {
@@ -410,6 +959,120 @@ impl TransferJob {
             file_num: file_num as _,
             data: buf.into(),
             compressed,
+            offset: position,
+            sha256: sha256.into(),
+            ..Default::default()
+        }))
+    }
+
+    // the data not found in the blocks at the destination, after the blocks found
+    async fn read_delta(&mut self, file_num: usize) -> ResultType<Option<FileTransferBlock>> {
+        let mut delta = self.delta.take().unwrap();
+        let block_size = delta.block_size;
+        let mut block_refs = Vec::new();
+        let mut data = Vec::new();
+        loop {
+            // one more byte than the window to slide it
+            if !delta.eof && delta.buf.len() - delta.pos <= block_size {
+                delta.buf.drain(..delta.pos);
+                delta.pos = 0;
+                let len = delta.buf.len();
+                delta.buf.resize(len + BUF_SIZE, 0);
+                match self
+                    .file
+                    .as_mut()
+                    .unwrap()
+                    .read(&mut delta.buf[len..])
+                    .await
+                {
+                    Err(err) => {
+                        self.file_num += 1;
+                        self.file = None;
+                        return Err(err.into());
+                    }
+                    Ok(n) => {
+                        delta.buf.truncate(len + n);
+                        delta.eof = n == 0;
+                    }
+                }
+                continue;
+            }
+            let left = delta.buf.len() - delta.pos;
+            if left == 0 {
+                break;
+            }
+            if left < block_size {
+                data.extend_from_slice(&delta.buf[delta.pos..]);
+                delta.pos = delta.buf.len();
+                continue;
+            }
+            let window = &delta.buf[delta.pos..delta.pos + block_size];
+            let rolling = *delta
+                .rolling
+                .get_or_insert_with(|| RollingChecksum::new(window));
+            if let Some(i) = delta.find(rolling.digest(), window) {
+                // the data before it goes first
+                if !data.is_empty() {
+                    break;
+                }
+                self.hasher.update(window);
+                block_refs.push(i as u64);
+                delta.pos += block_size;
+                delta.rolling = None;
+                if block_refs.len() >= BUF_SIZE / 8 {
+                    break;
+                }
+                continue;
+            }
+            let x = delta.buf[delta.pos];
+            data.push(x);
+            if delta.pos + block_size < delta.buf.len() {
+                let input = delta.buf[delta.pos + block_size];
+                if let Some(rolling) = delta.rolling.as_mut() {
+                    rolling.roll(x, input);
+                }
+            } else {
+                delta.rolling = None;
+            }
+            delta.pos += 1;
+            if data.len() >= BUF_SIZE {
+                break;
+            }
+        }
+        let position = self.offset;
+        if block_refs.is_empty() && data.is_empty() {
+            self.file_num += 1;
+            self.file = None;
+            return Ok(Some(FileTransferBlock {
+                id: self.id,
+                file_num: file_num as _,
+                offset: position,
+                sha256: std::mem::take(&mut self.hasher).finalize().to_vec().into(),
+                ..Default::default()
+            }));
+        }
+        self.delta = Some(delta);
+        self.hasher.update(&data);
+        let size = block_refs.len() as u64 * block_size as u64 + data.len() as u64;
+        self.finished_size += size;
+        self.offset += size;
+        let mut compressed = false;
+        if !data.is_empty() && !is_compressed_file(&self.files[file_num].name) {
+            let tmp = compress(&data, COMPRESS_LEVEL);
+            if tmp.len() < data.len() {
+                data = tmp;
+                compressed = true;
+            }
+        }
+        // only the data is counted, the blocks found are saved
+        self.transferred += data.len() as u64;
+        Ok(Some(FileTransferBlock {
+            id: self.id,
+            file_num: file_num as _,
+            data: data.into(),
+            compressed,
+            offset: position,
+            block_refs: block_refs.into(),
             ..Default::default()
         }))
     }
@@ -452,12 +1115,22 @@ pub fn new_block(block: FileTransferBlock) -> Message {
 }
 
 #[inline]
-pub fn new_receive(id: i32, path: String, files: Vec<FileEntry>) -> Message {
+pub fn new_receive(
+    id: i32,
+    path: String,
+    files: Vec<FileEntry>,
+    resume: bool,
+    delta: bool,
+    conflict_policy: FileConflictPolicy,
+) -> Message {
     let mut action = FileAction::new();
     action.set_receive(FileTransferReceiveRequest {
         id,
         path,
         files: files.into(),
+        resume,
+        delta,
+        conflict_policy: conflict_policy.into(),
         ..Default::default()
     });
     let mut msg_out = Message::new();
@@ -466,12 +1139,24 @@ pub fn new_receive(id: i32, path: String, files: Vec<FileEntry>) -> Message {
 }
 
 #[inline]
-pub fn new_send(id: i32, path: String, include_hidden: bool) -> Message {
+pub fn new_send(
+    id: i32,
+    path: String,
+    include_hidden: bool,
+    offsets: Vec<FileTransferOffset>,
+    delta: bool,
+    files: Vec<String>,
+    conflict_policy: FileConflictPolicy,
+) -> Message {
     let mut action = FileAction::new();
     action.set_send(FileTransferSendRequest {
         id,
         path,
         include_hidden,
+        offsets: offsets.into(),
+        delta,
+        files: files.into(),
+        conflict_policy: conflict_policy.into(),
         ..Default::default()
     });
     let mut msg_out = Message::new();
@@ -492,6 +1177,46 @@ pub fn new_done(id: i32, file_num: i32) -> Message {
     msg_out
 }
 
+#[inline]
+pub fn new_offsets(
+    id: i32,
+    offsets: Vec<FileTransferOffset>,
+    signatures: Vec<FileSignature>,
+    skipped: Vec<String>,
+    conflicts: Vec<String>,
+) -> Message {
+    let mut resp = FileResponse::new();
+    resp.set_offsets(FileTransferOffsets {
+        id,
+        offsets: offsets.into(),
+        signatures: signatures.into(),
+        skipped: skipped.into(),
+        conflicts: conflicts.into(),
+        ..Default::default()
+    });
+    let mut msg_out = Message::new();
+    msg_out.set_file_response(resp);
+    msg_out
+}
+
+#[inline]
+pub fn new_offsets_action(
+    id: i32,
+    signatures: Vec<FileSignature>,
+    skipped: Vec<String>,
+) -> Message {
+    let mut action = FileAction::new();
+    action.set_offsets(FileTransferOffsets {
+        id,
+        signatures: signatures.into(),
+        skipped: skipped.into(),
+        ..Default::default()
+    });
+    let mut msg_out = Message::new();
+    msg_out.set_file_action(action);
+    msg_out
+}
+
 #[inline]
 pub fn remove_job(id: i32, jobs: &mut Vec<TransferJob>) {
     *jobs = jobs.drain(0..).filter(|x| x.id() != id).collect();
@@ -508,6 +1233,9 @@ pub async fn handle_read_jobs(
 ) -> ResultType<()> {
     let mut finished = Vec::new();
     for job in jobs.iter_mut() {
+        if job.is_waiting_offsets() {
+            continue;
+        }
         match job.read().await {
             Err(err) => {
                 stream
@@ -557,3 +1285,329 @@ pub fn create_dir(dir: &str) -> ResultType<()> {
     std::fs::create_dir_all(get_path(dir))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hbb_common_fs_{}_{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn new_entry(name: &str, size: u64, modified_time: u64) -> FileEntry {
+        FileEntry {
+            entry_type: FileType::File.into(),
+            name: name.to_owned(),
+            size,
+            modified_time,
+            ..Default::default()
+        }
+    }
+
+    fn write_file(path: &Path, size: usize, modified_time: u64) {
+        std::fs::write(path, vec![1u8; size]).unwrap();
+        filetime::set_file_mtime(
+            path,
+            filetime::FileTime::from_unix_time(modified_time as _, 0),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_resume_offsets() {
+        let dir = get_test_dir("resume");
+        write_file(&dir.join("partial.download"), 100, 0);
+        write_file(&dir.join("done"), 1000, 1_600_000_000);
+        write_file(&dir.join("changed"), 1000, 1_600_000_001);
+        write_file(&dir.join("larger.download"), 2000, 0);
+        let files = vec![
+            new_entry("partial", 1000, 1_600_000_000),
+            new_entry("done", 1000, 1_600_000_000),
+            new_entry("changed", 1000, 1_600_000_000),
+            new_entry("larger", 1000, 1_600_000_000),
+            new_entry("missing", 1000, 1_600_000_000),
+        ];
+        let offsets: Vec<(String, u64)> = get_resume_offsets(&get_string(&dir), &files)
+            .into_iter()
+            .map(|o| (o.name, o.offset))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![("partial".to_owned(), 100), ("done".to_owned(), 1000)]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_sync_files() {
+        let src = vec![
+            new_entry("same", 10, 1),
+            new_entry("size", 11, 1),
+            new_entry("time", 10, 2),
+            new_entry("new", 10, 1),
+            new_entry("dir\\a", 10, 1),
+            new_entry("dir/b", 10, 1),
+        ];
+        let dst = vec![
+            new_entry("same", 10, 1),
+            new_entry("size", 10, 1),
+            new_entry("time", 10, 1),
+            new_entry("extra", 10, 1),
+            new_entry("dir/a", 10, 1),
+            new_entry("dir\\b", 10, 2),
+            new_entry("dir\\c", 10, 1),
+        ];
+        let (files, extras) = get_sync_files(&src, &dst);
+        let names =
+            |files: Vec<FileEntry>| -> Vec<String> { files.into_iter().map(|f| f.name).collect() };
+        assert_eq!(names(files), vec!["size", "time", "new", "dir/b"]);
+        assert_eq!(names(extras), vec!["extra", "dir\\c"]);
+        assert_eq!(get_sync_files(&src, &[]).0.len(), src.len());
+        assert!(get_sync_files(&[], &dst).0.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_conflicts() {
+        let dir = get_test_dir("conflicts");
+        write_file(&dir.join("same"), 10, 1_600_000_000);
+        write_file(&dir.join("changed"), 10, 1_600_000_001);
+        let files = vec![
+            new_entry("same", 10, 1_600_000_000),
+            new_entry("changed", 10, 1_600_000_000),
+            new_entry("new", 10, 1_600_000_000),
+        ];
+        let new_job = |policy| {
+            let mut job = TransferJob::new_write(1, get_string(&dir), files.clone());
+            job.set_conflict_policy(policy);
+            job
+        };
+        let names = |v: &[&str]| -> Vec<String> { v.iter().map(|x| x.to_string()).collect() };
+
+        let mut job = new_job(FileConflictPolicy::Skip);
+        assert_eq!(
+            job.resolve_conflicts(),
+            (names(&["same", "changed"]), vec![])
+        );
+        assert_eq!(job.get_offset(&files[0]), 10);
+        assert_eq!(job.get_offset(&files[2]), 0);
+        // the offsets of the skipped files are not replaced
+        job.set_offsets(vec![FileTransferOffset {
+            name: "same".to_owned(),
+            offset: 5,
+            ..Default::default()
+        }]);
+        assert_eq!(job.get_offset(&files[0]), 10);
+
+        let mut job = new_job(FileConflictPolicy::SkipIdentical);
+        assert_eq!(job.resolve_conflicts(), (names(&["same"]), vec![]));
+        assert_eq!(job.get_offset(&files[1]), 0);
+
+        let mut job = new_job(FileConflictPolicy::Ask);
+        assert_eq!(
+            job.resolve_conflicts(),
+            (vec![], names(&["same", "changed"]))
+        );
+        assert_eq!(job.get_offset(&files[0]), 0);
+        job.skip_files(&names(&["changed", "unknown"]));
+        assert_eq!(job.get_offset(&files[1]), 10);
+
+        for policy in [FileConflictPolicy::Overwrite, FileConflictPolicy::Rename] {
+            assert_eq!(new_job(policy).resolve_conflicts(), (vec![], vec![]));
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rename_if_exists() {
+        let dir = get_test_dir("rename");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        write_file(&dir.join("a.txt"), 1, 0);
+        write_file(&dir.join("b.txt"), 1, 0);
+        write_file(&dir.join("b (1).txt.download"), 1, 0);
+        write_file(&dir.join("sub").join("c"), 1, 0);
+        let mut job = TransferJob::new_write(
+            1,
+            get_string(&dir),
+            vec![
+                new_entry("a.txt", 1, 0),
+                new_entry("b.txt", 1, 0),
+                new_entry("new.txt", 1, 0),
+                new_entry("sub/c", 1, 0),
+            ],
+        );
+        for i in 0..job.files().len() {
+            job.rename_if_exists(i);
+        }
+        let names: Vec<&str> = job.files().iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["a (1).txt", "b (2).txt", "new.txt", "sub/c (1)"]
+        );
+
+        // a single file is the path of the job
+        let mut job =
+            TransferJob::new_write(1, get_string(&dir.join("a.txt")), vec![new_entry("", 1, 0)]);
+        job.rename_if_exists(0);
+        assert_eq!(job.path(), get_string(&dir.join("a (1).txt")));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_check_offsets() {
+        let mut meta = TransferJobMeta {
+            file_num: 1,
+            offset: 50,
+            ..Default::default()
+        };
+        meta.set_files(&[
+            new_entry("a", 1000, 1),
+            new_entry("b", 1000, 2),
+            new_entry("c", 1000, 3),
+        ]);
+        let offset = |name: &str, offset: u64| FileTransferOffset {
+            name: name.to_owned(),
+            offset,
+            size: 1,
+            modified_time: 1,
+            ..Default::default()
+        };
+        let offsets: Vec<(String, u64, u64, u64)> = meta
+            .check_offsets(vec![
+                offset("a", 1000),
+                offset("b", 500),
+                offset("c", 100),
+                offset("d", 100),
+            ])
+            .into_iter()
+            .map(|o| (o.name, o.offset, o.size, o.modified_time))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                ("a".to_owned(), 1000, 1000, 1),
+                ("b".to_owned(), 50, 1000, 2)
+            ]
+        );
+    }
+
+    fn random_data(len: usize) -> Vec<u8> {
+        let mut x = 1u32;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    // sends the files of `src` to `dst`, with the signatures of what is there already,
+    // the bytes transferred and saved by the reader and the writer
+    async fn transfer(src: &Path, dst: &Path) -> (u64, u64, u64, u64) {
+        let mut reader = TransferJob::new_read(1, get_string(src), false).unwrap();
+        let mut writer = TransferJob::new_write(1, get_string(dst), reader.files().clone());
+        let signatures = get_signatures(&get_string(dst), reader.files());
+        reader.set_signatures(signatures.clone());
+        writer.set_signatures(signatures);
+        while let Some(block) = reader.read().await.unwrap() {
+            let done = block.data.is_empty() && !block.sha256.is_empty();
+            writer.write(block, None).await.unwrap();
+            if done {
+                writer.modify_time();
+            }
+        }
+        (
+            reader.transferred(),
+            reader.finished_size(),
+            writer.transferred(),
+            writer.finished_size(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_delta_transfer() {
+        let dir = get_test_dir("delta");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::create_dir_all(&dst).unwrap();
+        let basis = random_data(256 * 1024);
+        std::fs::write(dst.join("f"), &basis).unwrap();
+        let block_size = get_block_size(basis.len() as _);
+        // a block changed in the middle and a partial block appended
+        let mut data = basis.clone();
+        for x in data[100 * block_size + 10..100 * block_size + 20].iter_mut() {
+            *x = x.wrapping_add(1);
+        }
+        data.extend((0..block_size / 2).map(|i| i as u8));
+        std::fs::write(src.join("f"), &data).unwrap();
+
+        let signatures = get_signatures(&get_string(&dst), &[new_entry("f", 0, 0)]);
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].weak.len(), basis.len() / block_size);
+
+        let (read, read_size, written, written_size) = transfer(&src, &dst).await;
+        assert_eq!(std::fs::read(dst.join("f")).unwrap(), data);
+        assert!(!dst.join("f.download").exists());
+        assert_eq!(read_size, data.len() as u64);
+        assert_eq!(written_size, data.len() as u64);
+        assert_eq!(read, written);
+        // the changed block and the partial one only, compressed
+        assert!(read > 0 && read <= 2 * block_size as u64);
+
+        // the blocks to copy are within the signatures
+        for i in [signatures[0].weak.len() as u64, u64::MAX] {
+            let mut writer =
+                TransferJob::new_write(1, get_string(&dst), vec![new_entry("f", 1, 0)]);
+            writer.set_signatures(signatures.clone());
+            let block = FileTransferBlock {
+                id: 1,
+                block_refs: vec![i].into(),
+                ..Default::default()
+            };
+            assert!(writer.write(block, None).await.is_err());
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_delta_transfer_without_basis() {
+        let dir = get_test_dir("no_basis");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::create_dir_all(&dst).unwrap();
+        let data = random_data(200 * 1024);
+        std::fs::write(src.join("f"), &data).unwrap();
+        assert!(get_signatures(&get_string(&dst), &[new_entry("f", 0, 0)]).is_empty());
+
+        let (read, read_size, written, written_size) = transfer(&src, &dst).await;
+        assert_eq!(std::fs::read(dst.join("f")).unwrap(), data);
+        assert_eq!(read_size, data.len() as u64);
+        assert_eq!(written_size, data.len() as u64);
+        assert_eq!(read, written);
+        assert!(read > 0);
+
+        // the blocks to copy need the file they are from
+        let mut writer = TransferJob::new_write(1, get_string(&dst), vec![new_entry("g", 1, 0)]);
+        let block = FileTransferBlock {
+            id: 1,
+            block_refs: vec![0u64].into(),
+            ..Default::default()
+        };
+        assert!(writer.write(block, None).await.is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rolling_checksum() {
+        let data: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let len = 1024;
+        let mut rolling = RollingChecksum::new(&data[..len]);
+        for w in data.windows(len + 1) {
+            rolling.roll(w[0], w[len]);
+            assert_eq!(rolling.digest(), RollingChecksum::new(&w[1..]).digest());
+        }
+    }
+}
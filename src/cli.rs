@@ -1,11 +1,18 @@
 use crate::client::*;
 use hbb_common::{
-    config::PeerConfig,
-    log,
+    bail,
+    config::{PeerConfig, CONNECT_TIMEOUT},
+    fs, log,
     message_proto::*,
     protobuf::Message as _,
-    tokio::{self, sync::mpsc},
-    Stream,
+    rendezvous_proto::ConnType,
+    timeout,
+    tokio::{
+        self,
+        sync::mpsc,
+        time::{self, Duration},
+    },
+    ResultType, Stream,
 };
 use std::sync::{Arc, RwLock};
 
@@ -18,7 +25,7 @@ pub struct Session {
 }
 
 impl Session {
-    pub fn new(id: &str, sender: mpsc::UnboundedSender<Data>) -> Self {
+    pub fn new(id: &str, sender: mpsc::UnboundedSender<Data>, is_file_transfer: bool) -> Self {
         let mut password = "".to_owned();
         if PeerConfig::load(id).password.is_empty() {
             password = rpassword::read_password_from_tty(Some("Enter password: ")).unwrap();
@@ -33,7 +40,7 @@ impl Session {
             .lc
             .write()
             .unwrap()
-            .initialize(id.to_owned(), false, true);
+            .initialize(id.to_owned(), is_file_transfer, !is_file_transfer);
         session
     }
 }
@@ -83,7 +90,7 @@ pub async fn start_one_port_forward(id: String, port: i32, remote_host: String,
     crate::common::test_rendezvous_server();
     crate::common::test_nat_type();
     let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
-    let handler = Session::new(&id, sender);
+    let handler = Session::new(&id, sender, false);
     handler.lc.write().unwrap().port_forward = (remote_host, remote_port);
     if let Err(err) =
         crate::port_forward::listen(handler.id.clone(), port, handler.clone(), receiver).await
@@ -92,3 +99,322 @@ pub async fn start_one_port_forward(id: String, port: i32, remote_host: String,
     }
     log::info!("port forward (:{}) exit", port);
 }
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn start_sync(
+    id: String,
+    local: String,
+    remote: String,
+    pull: bool,
+    delete_extras: bool,
+    dry_run: bool,
+) {
+    crate::common::test_rendezvous_server();
+    crate::common::test_nat_type();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
+    let mut handler = Session::new(&id, sender, true);
+    let mut sync = SyncJob {
+        local,
+        remote,
+        pull,
+        delete_extras,
+        dry_run,
+        delta: false,
+        remote_sep: "/",
+        listed: false,
+        read_jobs: Vec::new(),
+        write_job: None,
+        failed: 0,
+    };
+    if let Err(err) = sync.run(&mut handler, &mut receiver).await {
+        log::error!("Failed to sync: {}", err);
+        std::process::exit(1);
+    }
+}
+
+const SYNC_ID: i32 = 1;
+const REMOVE_ID: i32 = 2;
+
+// a folder synchronized to the other side, only the files new or changed transferred
+struct SyncJob {
+    local: String,
+    remote: String,
+    pull: bool, // from the remote to the local
+    delete_extras: bool,
+    dry_run: bool,
+    delta: bool,
+    remote_sep: &'static str,
+    listed: bool,
+    read_jobs: Vec<fs::TransferJob>,
+    write_job: Option<fs::TransferJob>,
+    failed: usize, // files not written or not matching their checksums
+}
+
+impl SyncJob {
+    async fn run(
+        &mut self,
+        handler: &mut Session,
+        receiver: &mut mpsc::UnboundedReceiver<Data>,
+    ) -> ResultType<()> {
+        let (mut stream, _) = Client::start(&handler.id, ConnType::FILE_TRANSFER).await?;
+        login(handler, receiver, &mut stream).await?;
+        self.delta = handler.lc.read().unwrap().features.file_delta;
+        if handler.lc.read().unwrap().info.platform == "Windows" {
+            self.remote_sep = "\\";
+        }
+        let mut msg_out = Message::new();
+        let mut file_action = FileAction::new();
+        file_action.set_all_files(ReadAllFiles {
+            id: SYNC_ID,
+            path: self.remote.clone(),
+            include_hidden: true,
+            ..Default::default()
+        });
+        msg_out.set_file_action(file_action);
+        stream.send(&msg_out).await?;
+        let mut timer = time::interval(Duration::from_millis(1));
+        loop {
+            tokio::select! {
+                res = stream.next() => {
+                    match res {
+                        Some(Ok(bytes)) => {
+                            let msg_in = Message::parse_from_bytes(&bytes)?;
+                            if !self.handle_msg(handler, msg_in, &mut stream).await? {
+                                break;
+                            }
+                        }
+                        _ => {
+                            bail!("Reset by the peer");
+                        }
+                    }
+                }
+                _ = timer.tick() => {
+                    if !self.read_jobs.is_empty() {
+                        fs::handle_read_jobs(&mut self.read_jobs, &mut stream).await?;
+                        // the host sends no done for the files it received
+                        if self.read_jobs.is_empty() {
+                            return self.finish();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // false once finished
+    async fn handle_msg(
+        &mut self,
+        handler: &mut Session,
+        msg_in: Message,
+        stream: &mut Stream,
+    ) -> ResultType<bool> {
+        match msg_in.union {
+            Some(message::Union::file_response(fr)) => match fr.union {
+                Some(file_response::Union::dir(fd)) if fd.id == SYNC_ID => {
+                    if !self.listed {
+                        self.listed = true;
+                        return self.start(fd.entries.to_vec(), stream).await;
+                    }
+                    if let Some(job) = self.write_job.as_mut() {
+                        if self.delta {
+                            let signatures = fs::get_signatures(&self.local, &fd.entries);
+                            job.set_signatures(signatures.clone());
+                            stream
+                                .send(&fs::new_offsets_action(SYNC_ID, signatures, Vec::new()))
+                                .await?;
+                        }
+                        job.set_files(fd.entries.to_vec());
+                    }
+                }
+                Some(file_response::Union::block(block)) => {
+                    if let Some(job) = self.write_job.as_mut() {
+                        let file_num = block.file_num;
+                        if let Err(err) = job.write(block, None).await {
+                            log::error!("Failed to write file #{}: {}", file_num, err);
+                            self.failed += 1;
+                        }
+                    }
+                }
+                Some(file_response::Union::offsets(o)) => {
+                    if let Some(job) = fs::get_job(o.id, &mut self.read_jobs) {
+                        job.set_signatures(o.signatures.to_vec());
+                        job.set_offsets(o.offsets.to_vec());
+                    }
+                }
+                Some(file_response::Union::done(d)) if d.id == SYNC_ID => {
+                    if let Some(job) = self.write_job.as_mut() {
+                        job.modify_time();
+                    }
+                    self.finish()?;
+                    return Ok(false);
+                }
+                Some(file_response::Union::error(e)) => {
+                    if e.id == SYNC_ID && !self.listed {
+                        self.listed = true;
+                        // nothing at the destination yet
+                        if !self.pull {
+                            return self.start(Vec::new(), stream).await;
+                        }
+                        bail!(e.error);
+                    }
+                    if e.id == SYNC_ID && e.file_num < 0 {
+                        bail!(e.error);
+                    }
+                    log::error!("{} (file #{})", e.error, e.file_num);
+                    self.failed += 1;
+                }
+                _ => {}
+            },
+            Some(message::Union::test_delay(t)) => {
+                handler.handle_test_delay(t, stream).await;
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn finish(&self) -> ResultType<()> {
+        if self.failed > 0 {
+            bail!("{} files failed", self.failed);
+        }
+        log::info!("Synchronized");
+        Ok(())
+    }
+
+    // false if nothing to transfer
+    async fn start(&mut self, remote: Vec<FileEntry>, stream: &mut Stream) -> ResultType<bool> {
+        let local = match fs::get_recursive_files(&self.local, true) {
+            Ok(files) => files,
+            // nothing at the destination yet
+            Err(_) if self.pull => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let (src, dst) = if self.pull {
+            (remote, local)
+        } else {
+            (local, remote)
+        };
+        let (files, mut extras) = fs::get_sync_files(&src, &dst);
+        if !self.delete_extras {
+            extras.clear();
+        }
+        if self.dry_run {
+            for f in files.iter() {
+                println!("+ {}", f.name);
+            }
+            for f in extras.iter() {
+                println!("- {}", f.name);
+            }
+            return Ok(false);
+        }
+        for f in extras.iter() {
+            log::info!("Remove {}", f.name);
+            if self.pull {
+                let path = fs::get_path(&self.local).join(&f.name);
+                if let Err(err) = fs::remove_file(&fs::get_string(&path)) {
+                    log::error!("Failed to remove {}: {}", f.name, err);
+                }
+            } else {
+                let mut msg_out = Message::new();
+                let mut file_action = FileAction::new();
+                file_action.set_remove_file(FileRemoveFile {
+                    id: REMOVE_ID,
+                    path: format!("{}{}{}", self.remote, self.remote_sep, f.name),
+                    ..Default::default()
+                });
+                msg_out.set_file_action(file_action);
+                stream.send(&msg_out).await?;
+            }
+        }
+        if files.is_empty() {
+            log::info!("No changes");
+            return Ok(false);
+        }
+        if self.pull {
+            self.write_job = Some(fs::TransferJob::new_write(
+                SYNC_ID,
+                self.local.clone(),
+                Vec::new(),
+            ));
+            let files = files.into_iter().map(|f| f.name).collect();
+            stream
+                .send(&fs::new_send(
+                    SYNC_ID,
+                    self.remote.clone(),
+                    true,
+                    Vec::new(),
+                    self.delta,
+                    files,
+                    FileConflictPolicy::Overwrite,
+                ))
+                .await?;
+        } else {
+            let mut job = fs::TransferJob::new_read(SYNC_ID, self.local.clone(), true)?;
+            job.set_files(files);
+            if self.delta {
+                job.wait_offsets();
+            }
+            stream
+                .send(&fs::new_receive(
+                    SYNC_ID,
+                    self.remote.clone(),
+                    job.files().clone(),
+                    false,
+                    self.delta,
+                    FileConflictPolicy::Overwrite,
+                ))
+                .await?;
+            self.read_jobs.push(job);
+        }
+        Ok(true)
+    }
+}
+
+async fn login(
+    handler: &mut Session,
+    receiver: &mut mpsc::UnboundedReceiver<Data>,
+    stream: &mut Stream,
+) -> ResultType<()> {
+    loop {
+        tokio::select! {
+            res = timeout(CONNECT_TIMEOUT, stream.next()) => match res {
+                Err(_) => {
+                    bail!("Timeout");
+                }
+                Ok(Some(Ok(bytes))) => {
+                    let msg_in = Message::parse_from_bytes(&bytes)?;
+                    match msg_in.union {
+                        Some(message::Union::hash(hash)) => {
+                            handler.handle_hash(hash, stream).await;
+                        }
+                        Some(message::Union::login_response(lr)) => match lr.union {
+                            Some(login_response::Union::error(err)) => {
+                                if !handler.handle_login_error(&err) {
+                                    bail!(err);
+                                }
+                            }
+                            Some(login_response::Union::peer_info(pi)) => {
+                                handler.handle_peer_info(pi);
+                                return Ok(());
+                            }
+                            _ => {}
+                        }
+                        Some(message::Union::test_delay(t)) => {
+                            handler.handle_test_delay(t, stream).await;
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {
+                    bail!("Reset by the peer");
+                }
+            },
+            d = receiver.recv() => {
+                if let Some(Data::Login((password, remember))) = d {
+                    handler.handle_login_from_ui(password, remember, stream).await;
+                }
+            }
+        }
+    }
+}
@@ -9,6 +9,7 @@ use hbb_common::{
     anyhow::{anyhow, Context},
     bail,
     config::{Config, PeerConfig, PeerInfoSerde, CONNECT_TIMEOUT, RELAY_PORT, RENDEZVOUS_TIMEOUT},
+    fs::TransferJobMeta,
     log,
     message_proto::{option_message::BoolOption, *},
     protobuf::Message as _,
@@ -23,10 +24,11 @@ use magnum_opus::{Channels::*, Decoder as AudioDecoder};
 use scrap::{aom::AomImage, Decoder, Image, ImageApi, VideoCodecId};
 use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     net::SocketAddr,
     ops::Deref,
-    sync::{mpsc, Arc, RwLock},
+    sync::{mpsc, Arc, Mutex, RwLock},
+    time::Instant,
 };
 use uuid::Uuid;
 
@@ -472,9 +474,41 @@ impl Client {
     }
 }
 
+// pts of the last rendered video frame and when it was rendered
+pub type VideoClock = Arc<Mutex<Option<(i64, Instant)>>>;
+
+// prebuffer when nothing is queued in the output and there is no video to follow
+const JITTER_TARGET_MS: i64 = 60;
+const JITTER_MAX_MS: i64 = 1000;
+// within it audio is played as is, beyond it samples are stretched or shrunk
+const SYNC_THRESHOLD_MS: i64 = 20;
+// audio later than it is dropped, earlier than it is held back
+const DROP_THRESHOLD_MS: i64 = 120;
+const MAX_STRETCH: f32 = 0.1;
+// video stalled or no video at all, do not follow it
+const VIDEO_CLOCK_TIMEOUT: Duration = Duration::from_secs(3);
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+// the audio held back is played out without waiting for the next packet
+const PLAY_OUT_INTERVAL: Duration = Duration::from_millis(10);
+
+// the state of the jitter buffer shown to the user
+#[derive(Default, Clone, Copy)]
+pub struct AudioStats {
+    pub buffer_depth: i64,   // ms
+    pub output_latency: i64, // ms
+    pub drift: i64,          // ms, the audio ahead of the video if positive
+    pub dropped: usize,      // the packets too late or overflowing the buffer
+}
+
 #[derive(Default)]
 pub struct AudioHandler {
     audio_decoder: Option<(AudioDecoder, Vec<f32>)>,
+    video_clock: VideoClock,
+    stats: Arc<Mutex<AudioStats>>,
+    jitter_buffer: VecDeque<(i64, Vec<f32>)>,
+    drift: i64,
+    dropped: usize,
+    last_report: Option<Instant>,
     #[cfg(target_os = "android")]
     oboe: Option<OboePlayer>,
     #[cfg(target_os = "linux")]
@@ -488,6 +522,14 @@ pub struct AudioHandler {
 }
 
 impl AudioHandler {
+    pub fn new(video_clock: VideoClock, stats: Arc<Mutex<AudioStats>>) -> Self {
+        Self {
+            video_clock,
+            stats,
+            ..Default::default()
+        }
+    }
+
     #[cfg(target_os = "linux")]
     fn start_audio(&mut self, format0: AudioFormat) -> ResultType<()> {
         use psimple::Simple;
@@ -553,10 +595,18 @@ impl AudioHandler {
     }
 
     pub fn handle_format(&mut self, f: AudioFormat) {
+        log::info!(
+            "Audio format: {} Hz, {} channel(s), profile: {:?}, bitrate: {}",
+            f.sample_rate,
+            f.channels,
+            f.profile.enum_value_or_default(),
+            f.bitrate
+        );
         match AudioDecoder::new(f.sample_rate, if f.channels > 1 { Stereo } else { Mono }) {
             Ok(d) => {
                 let buffer = vec![0.; f.sample_rate as usize * f.channels as usize];
                 self.audio_decoder = Some((d, buffer));
+                self.jitter_buffer.clear();
                 self.channels = f.channels as _;
                 allow_err!(self.start_audio(f));
             }
@@ -579,46 +629,160 @@ impl AudioHandler {
         if self.oboe.is_none() {
             return;
         }
-        self.audio_decoder.as_mut().map(|(d, buffer)| {
-            if let Ok(n) = d.decode_float(&frame.data, buffer, false) {
-                let channels = self.channels;
-                let n = n * (channels as usize);
-                #[cfg(not(any(target_os = "android", target_os = "linux")))]
-                {
-                    let sample_rate0 = self.sample_rate.0;
-                    let sample_rate = self.sample_rate.1;
-                    let audio_buffer = self.audio_buffer.clone();
-                    // avoiding memory overflow if audio_buffer consumer side has problem
-                    if audio_buffer.lock().unwrap().len() as u32 > sample_rate * 120 {
-                        *audio_buffer.lock().unwrap() = Default::default();
+        let channels = self.channels as usize;
+        let data = match self.audio_decoder.as_mut() {
+            Some((d, buffer)) => match d.decode_float(&frame.data, buffer, false) {
+                Ok(n) => buffer[0..n * channels].to_vec(),
+                Err(_) => return,
+            },
+            None => return,
+        };
+        self.jitter_buffer.push_back((frame.timestamp, data));
+        self.tick();
+    }
+
+    // also called periodically, the audio held back becomes due without new packets
+    pub fn tick(&mut self) {
+        self.play_out();
+        let stats = AudioStats {
+            buffer_depth: self.buffer_depth(),
+            output_latency: self.output_latency(),
+            drift: self.drift,
+            dropped: self.dropped,
+        };
+        *self.stats.lock().unwrap() = stats;
+        if self
+            .last_report
+            .map(|x| x.elapsed() >= REPORT_INTERVAL)
+            .unwrap_or(true)
+        {
+            self.last_report = Some(Instant::now());
+            log::debug!(
+                "Audio jitter buffer: {}ms, output: {}ms, drift: {}ms, dropped: {}",
+                stats.buffer_depth,
+                stats.output_latency,
+                stats.drift,
+                stats.dropped
+            );
+        }
+    }
+
+    // ms of decoded audio waiting in the jitter buffer
+    pub fn buffer_depth(&self) -> i64 {
+        let n: usize = self.jitter_buffer.iter().map(|(_, x)| x.len()).sum();
+        self.samples_to_ms(n, self.sample_rate.0)
+    }
+
+    fn samples_to_ms(&self, n: usize, sample_rate: u32) -> i64 {
+        if self.channels == 0 || sample_rate == 0 {
+            return 0;
+        }
+        (n / self.channels as usize) as i64 * 1000 / sample_rate as i64
+    }
+
+    // ms of audio already handed to the output but not played yet
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    fn output_latency(&self) -> i64 {
+        let n = self.audio_buffer.lock().unwrap().len();
+        self.samples_to_ms(n, self.sample_rate.1)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn output_latency(&self) -> i64 {
+        self.simple
+            .as_ref()
+            .map(|x| x.get_latency().map(|x| x.0 as i64 / 1000).unwrap_or(0))
+            .unwrap_or(0)
+    }
+
+    // oboe does not tell, rely on the jitter buffer alone
+    #[cfg(target_os = "android")]
+    fn output_latency(&self) -> i64 {
+        0
+    }
+
+    // the server time of the video frame on the screen now
+    fn video_now(&self) -> Option<i64> {
+        let (pts, tm) = (*self.video_clock.lock().unwrap())?;
+        let elapsed = tm.elapsed();
+        if elapsed > VIDEO_CLOCK_TIMEOUT {
+            return None;
+        }
+        Some(pts + elapsed.as_millis() as i64)
+    }
+
+    fn play_out(&mut self) {
+        let video_now = self.video_now();
+        while let Some((timestamp, _)) = self.jitter_buffer.front() {
+            let timestamp = *timestamp;
+            let latency = self.output_latency();
+            let mut ratio = 1.;
+            match video_now {
+                // old peers do not stamp audio
+                Some(video_now) if timestamp > 0 => {
+                    // when the samples written now are heard, the video will be at video_now + latency
+                    self.drift = timestamp - (video_now + latency);
+                    if self.drift < -DROP_THRESHOLD_MS || self.buffer_depth() > JITTER_MAX_MS {
+                        self.jitter_buffer.pop_front();
+                        self.dropped += 1;
+                        continue;
                     }
-                    if sample_rate != sample_rate0 {
-                        let buffer = crate::resample_channels(
-                            &buffer[0..n],
-                            sample_rate0,
-                            sample_rate,
-                            channels,
-                        );
-                        audio_buffer.lock().unwrap().extend(buffer);
-                    } else {
-                        audio_buffer
-                            .lock()
-                            .unwrap()
-                            .extend(buffer[0..n].iter().cloned());
+                    if self.drift > DROP_THRESHOLD_MS {
+                        break;
+                    }
+                    if self.drift.abs() > SYNC_THRESHOLD_MS {
+                        ratio += MAX_STRETCH * self.drift.signum() as f32;
                     }
                 }
-                #[cfg(target_os = "android")]
-                {
-                    self.oboe.as_mut().map(|x| x.push(&buffer[0..n]));
+                _ => {
+                    self.drift = 0;
+                    if latency == 0 && self.buffer_depth() < JITTER_TARGET_MS {
+                        break;
+                    }
+                    if latency > JITTER_TARGET_MS * 2 {
+                        ratio -= MAX_STRETCH;
+                    }
                 }
-                #[cfg(target_os = "linux")]
-                {
-                    let data_u8 =
-                        unsafe { std::slice::from_raw_parts::<u8>(buffer.as_ptr() as _, n * 4) };
-                    self.simple.as_mut().map(|x| x.write(data_u8));
+            }
+            if let Some((_, data)) = self.jitter_buffer.pop_front() {
+                if ratio != 1. {
+                    let data = stretch(&data, self.channels as _, ratio);
+                    self.write(&data);
+                } else {
+                    self.write(&data);
                 }
             }
-        });
+        }
+    }
+
+    fn write(&mut self, buffer: &[f32]) {
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        {
+            let sample_rate0 = self.sample_rate.0;
+            let sample_rate = self.sample_rate.1;
+            let audio_buffer = self.audio_buffer.clone();
+            // avoiding memory overflow if audio_buffer consumer side has problem
+            if audio_buffer.lock().unwrap().len() as u32 > sample_rate * 120 {
+                *audio_buffer.lock().unwrap() = Default::default();
+            }
+            if sample_rate != sample_rate0 {
+                let buffer =
+                    crate::resample_channels(buffer, sample_rate0, sample_rate, self.channels);
+                audio_buffer.lock().unwrap().extend(buffer);
+            } else {
+                audio_buffer.lock().unwrap().extend(buffer.iter().cloned());
+            }
+        }
+        #[cfg(target_os = "android")]
+        {
+            self.oboe.as_mut().map(|x| x.push(buffer));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let data_u8 =
+                unsafe { std::slice::from_raw_parts::<u8>(buffer.as_ptr() as _, buffer.len() * 4) };
+            self.simple.as_mut().map(|x| x.write(data_u8));
+        }
     }
 
     #[cfg(not(any(target_os = "android", target_os = "linux")))]
@@ -656,6 +820,28 @@ impl AudioHandler {
     }
 }
 
+// change the length of interleaved data by ratio with linear interpolation
+fn stretch(data: &[f32], channels: usize, ratio: f32) -> Vec<f32> {
+    let frames = data.len() / channels;
+    let n = (frames as f32 * ratio).round() as usize;
+    if frames < 2 || n < 2 {
+        return data.to_vec();
+    }
+    let step = (frames - 1) as f32 / (n - 1) as f32;
+    let mut out = Vec::with_capacity(n * channels);
+    for i in 0..n {
+        let pos = i as f32 * step;
+        let j = (pos as usize).min(frames - 2);
+        let t = pos - j as f32;
+        for c in 0..channels {
+            let a = data[j * channels + c];
+            let b = data[(j + 1) * channels + c];
+            out.push(a + (b - a) * t);
+        }
+    }
+    out
+}
+
 pub struct VideoHandler {
     decoder: Decoder,
     pub rgb: Vec<u8>,
@@ -705,6 +891,7 @@ pub struct LoginConfigHandler {
     config: PeerConfig,
     pub port_forward: (String, i32),
     pub version: i64,
+    pub features: Features,
 }
 
 impl Deref for LoginConfigHandler {
@@ -725,8 +912,13 @@ impl LoginConfigHandler {
         self.id = id;
         self.is_file_transfer = is_file_transfer;
         self.is_port_forward = is_port_forward;
-        let config = self.load_config();
+        let mut config = self.load_config();
         self.remember = !config.password.is_empty();
+        // the ids are of the sessions they were started or resumed in
+        if is_file_transfer && config.transfer_jobs.iter().any(|j| j.id != 0) {
+            config.transfer_jobs.iter_mut().for_each(|j| j.id = 0);
+            config.store(&self.id);
+        }
         self.config = config;
     }
 
@@ -745,6 +937,49 @@ impl LoginConfigHandler {
         self.save_config(config);
     }
 
+    // the unfinished transfer jobs of the last sessions, not resumed yet
+    pub fn get_transfer_jobs(&self) -> Vec<TransferJobMeta> {
+        self.config
+            .transfer_jobs
+            .iter()
+            .filter(|j| j.id == 0)
+            .cloned()
+            .collect()
+    }
+
+    // the same job unfinished before is replaced
+    pub fn save_transfer_job(&mut self, job: TransferJobMeta) {
+        let mut config = self.load_config();
+        config
+            .transfer_jobs
+            .retain(|j| !j.is_same_job(job.is_remote, &job.path, &job.to));
+        config.transfer_jobs.push(job);
+        self.save_config(config);
+    }
+
+    pub fn update_transfer_job<F: FnOnce(&mut TransferJobMeta)>(&mut self, id: i32, f: F) {
+        let mut config = self.load_config();
+        if let Some(job) = config.transfer_jobs.iter_mut().find(|j| j.id == id) {
+            f(job);
+            self.save_config(config);
+        }
+    }
+
+    pub fn remove_transfer_job(&mut self, id: i32) {
+        let mut config = self.load_config();
+        let n = config.transfer_jobs.len();
+        config.transfer_jobs.retain(|j| j.id != id);
+        if config.transfer_jobs.len() != n {
+            self.save_config(config);
+        }
+    }
+
+    pub fn clear_transfer_jobs(&mut self) {
+        let mut config = self.load_config();
+        config.transfer_jobs.retain(|j| j.id != 0);
+        self.save_config(config);
+    }
+
     pub fn save_view_style(&mut self, value: String) {
         let mut config = self.load_config();
         config.view_style = value;
@@ -802,6 +1037,15 @@ impl LoginConfigHandler {
                 BoolOption::No
             })
             .into();
+        } else if name == "sync-primary-selection" {
+            let v = !self.get_toggle_option(&name);
+            if v {
+                config.options.insert(name.clone(), "Y".to_owned());
+            } else {
+                config.options.remove(&name);
+            }
+            option.sync_primary_selection =
+                (if v { BoolOption::Yes } else { BoolOption::No }).into();
         } else if name == "block-input" {
             option.block_input = BoolOption::Yes.into();
         } else if name == "unblock-input" {
@@ -864,6 +1108,10 @@ impl LoginConfigHandler {
             msg.disable_audio = BoolOption::Yes.into();
             n += 1;
         }
+        if let Some(p) = self.get_audio_profile_enum(&self.config.audio_profile, ignore_default) {
+            msg.audio_profile = p.into();
+            n += 1;
+        }
         if self.get_toggle_option("enable-file-transfer") {
             msg.enable_file_transfer = BoolOption::Yes.into();
             n += 1;
@@ -872,6 +1120,10 @@ impl LoginConfigHandler {
             msg.disable_clipboard = BoolOption::Yes.into();
             n += 1;
         }
+        if self.get_toggle_option("sync-primary-selection") {
+            msg.sync_primary_selection = BoolOption::Yes.into();
+            n += 1;
+        }
         if n > 0 {
             Some(msg)
         } else {
@@ -895,6 +1147,20 @@ impl LoginConfigHandler {
         }
     }
 
+    fn get_audio_profile_enum(&self, p: &str, ignore_default: bool) -> Option<AudioProfile> {
+        if p == "voice" {
+            Some(AudioProfile::Voice)
+        } else if p == "music" {
+            Some(AudioProfile::Music)
+        } else if p == "off" {
+            Some(AudioProfile::Off)
+        } else if ignore_default {
+            None
+        } else {
+            Some(AudioProfile::Standard)
+        }
+    }
+
     pub fn get_toggle_option(&self, name: &str) -> bool {
         if name == "show-remote-cursor" {
             self.config.show_remote_cursor
@@ -954,6 +1220,49 @@ impl LoginConfigHandler {
         res
     }
 
+    pub fn save_audio_profile(&mut self, value: String) -> Option<Message> {
+        let mut res = None;
+        if let Some(p) = self.get_audio_profile_enum(&value, false) {
+            let mut misc = Misc::new();
+            misc.set_option(OptionMessage {
+                audio_profile: p.into(),
+                ..Default::default()
+            });
+            let mut msg_out = Message::new();
+            msg_out.set_misc(misc);
+            res = Some(msg_out);
+        }
+        let mut config = self.load_config();
+        config.audio_profile = value;
+        self.save_config(config);
+        res
+    }
+
+    // send the key positions instead of the characters, if the peer can inject them
+    pub fn is_physical_keyboard(&self) -> bool {
+        self.features.physical_keyboard && !self.get_option("physical-keyboard").is_empty()
+    }
+
+    // the local game controllers are plugged on the peer
+    pub fn is_gamepad_forwarded(&self) -> bool {
+        self.features.gamepad && !self.get_option("forward-gamepad").is_empty()
+    }
+
+    // what the receiver does with the files existing at the destination, picked for the job
+    // in the transfer dialog, which defaults to the option "file-conflict-policy"
+    pub fn get_conflict_policy(&self, policy: &str) -> FileConflictPolicy {
+        if !self.features.file_conflict {
+            return FileConflictPolicy::Overwrite;
+        }
+        match policy {
+            "skip" => FileConflictPolicy::Skip,
+            "skip-identical" => FileConflictPolicy::SkipIdentical,
+            "rename" => FileConflictPolicy::Rename,
+            "ask" => FileConflictPolicy::Ask,
+            _ => FileConflictPolicy::Overwrite,
+        }
+    }
+
     pub fn get_option(&self, k: &str) -> String {
         if let Some(v) = self.config.options.get(k) {
             v.clone()
@@ -985,6 +1294,7 @@ impl LoginConfigHandler {
         if !pi.version.is_empty() {
             self.version = hbb_common::get_version_number(&pi.version);
         }
+        self.features = pi.features.as_ref().cloned().unwrap_or_default();
         let serde = PeerInfoSerde {
             username,
             hostname: pi.hostname.clone(),
@@ -1070,13 +1380,18 @@ pub enum MediaData {
 
 pub type MediaSender = mpsc::Sender<MediaData>;
 
-pub fn start_video_audio_threads<F>(video_callback: F) -> (MediaSender, MediaSender)
+pub fn start_video_audio_threads<F>(
+    video_callback: F,
+    audio_stats: Arc<Mutex<AudioStats>>,
+) -> (MediaSender, MediaSender)
 where
     F: 'static + FnMut(&[u8]) + Send,
 {
     let (video_sender, video_receiver) = mpsc::channel::<MediaData>();
     let (audio_sender, audio_receiver) = mpsc::channel::<MediaData>();
     let mut video_callback = video_callback;
+    let video_clock: VideoClock = Default::default();
+    let video_clock_cloned = video_clock.clone();
 
     std::thread::spawn(move || {
         let mut video_handler = VideoHandler::new();
@@ -1087,11 +1402,16 @@ where
                         if let Some(video_frame::Union::vp9s(vp9s)) = &vf.union {
                             if let Ok(true) = video_handler.handle_vp9s(vp9s) {
                                 video_callback(&video_handler.rgb);
+                                if let Some(vp9) = vp9s.frames.last() {
+                                    *video_clock_cloned.lock().unwrap() =
+                                        Some((vp9.pts, Instant::now()));
+                                }
                             }
                         }
                     }
                     MediaData::Reset => {
                         video_handler.reset();
+                        *video_clock_cloned.lock().unwrap() = None;
                     }
                     _ => {}
                 }
@@ -1102,20 +1422,20 @@ where
         log::info!("Video decoder loop exits");
     });
     std::thread::spawn(move || {
-        let mut audio_handler = AudioHandler::default();
+        let mut audio_handler = AudioHandler::new(video_clock, audio_stats);
         loop {
-            if let Ok(data) = audio_receiver.recv() {
-                match data {
-                    MediaData::AudioFrame(af) => {
-                        audio_handler.handle_frame(af);
-                    }
-                    MediaData::AudioFormat(f) => {
-                        audio_handler.handle_format(f);
-                    }
-                    _ => {}
+            match audio_receiver.recv_timeout(PLAY_OUT_INTERVAL) {
+                Ok(MediaData::AudioFrame(af)) => {
+                    audio_handler.handle_frame(af);
                 }
-            } else {
-                break;
+                Ok(MediaData::AudioFormat(f)) => {
+                    audio_handler.handle_format(f);
+                }
+                Ok(_) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    audio_handler.tick();
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
             }
         }
         log::info!("Audio decoder loop exits");
@@ -1192,7 +1512,7 @@ pub enum Data {
     Close,
     Login((String, bool)),
     Message(Message),
-    SendFiles((i32, String, String, bool, bool)),
+    SendFiles((i32, String, String, bool, bool, String)),
     RemoveDirAll((i32, String, bool)),
     ConfirmDeleteFiles((i32, i32)),
     SetNoConfirm(i32),
@@ -1200,10 +1520,14 @@ pub enum Data {
     RemoveFile((i32, String, i32, bool)),
     CreateDir((i32, String, bool)),
     CancelJob(i32),
+    ResumeJob((i32, String, String, bool, bool, String)),
+    SyncFiles((i32, String, String, bool, bool, bool, bool)),
+    ResolveConflict((i32, bool, bool)),
     RemovePortForward(i32),
     AddPortForward((i32, String, i32)),
     ToggleClipboardFile,
     NewRDP,
+    CancelClipboard,
 }
 
 #[derive(Clone)]
@@ -1380,3 +1704,91 @@ fn decode_id_pk(signed: &[u8], key: &sign::PublicKey) -> ResultType<(String, [u8
         bail!("Wrong public length");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stretch() {
+        // a stereo ramp, the channels apart
+        let data: Vec<f32> = (0..100).flat_map(|i| vec![i as f32, -i as f32]).collect();
+        for (ratio, n) in [(1.1, 110), (0.9, 90), (1., 100)] {
+            let out = stretch(&data, 2, ratio);
+            assert_eq!(out.len(), n * 2);
+            assert_eq!(out[..2], [0., 0.]);
+            assert!((out[out.len() - 2] - 99.).abs() < 1e-3);
+            for w in out.chunks(2).collect::<Vec<_>>().windows(2) {
+                assert!(w[1][0] > w[0][0]);
+                assert_eq!(w[1][0], -w[1][1]);
+            }
+        }
+        // too short to interpolate
+        assert_eq!(stretch(&[1., 2.], 2, 1.1), vec![1., 2.]);
+        assert_eq!(stretch(&[1., 2., 3.], 1, 0.1), vec![1., 2., 3.]);
+    }
+
+    // 1 sample of 1 channel per ms, the video at 1000 ms now
+    fn new_handler() -> AudioHandler {
+        let video_clock: VideoClock = Default::default();
+        *video_clock.lock().unwrap() = Some((1000, Instant::now()));
+        let mut handler = AudioHandler::new(video_clock, Default::default());
+        handler.channels = 1;
+        handler.sample_rate = (1000, 1000);
+        handler
+    }
+
+    fn push(handler: &mut AudioHandler, timestamp: i64, ms: usize) {
+        handler.jitter_buffer.push_back((timestamp, vec![0.; ms]));
+    }
+
+    #[test]
+    fn test_play_out_drift() {
+        // late beyond the threshold, dropped
+        let mut handler = new_handler();
+        push(&mut handler, 800, 20);
+        handler.play_out();
+        assert!(handler.jitter_buffer.is_empty());
+        assert_eq!(handler.dropped, 1);
+        assert!(handler.drift < -DROP_THRESHOLD_MS);
+        // early beyond the threshold, held back
+        let mut handler = new_handler();
+        push(&mut handler, 1300, 20);
+        handler.play_out();
+        assert_eq!(handler.jitter_buffer.len(), 1);
+        assert_eq!(handler.dropped, 0);
+        assert!(handler.drift > DROP_THRESHOLD_MS);
+        // in sync or a little off, played
+        for timestamp in [1005, 950, 1050] {
+            let mut handler = new_handler();
+            push(&mut handler, timestamp, 20);
+            handler.play_out();
+            assert!(handler.jitter_buffer.is_empty());
+            assert_eq!(handler.dropped, 0);
+            assert!(handler.drift.abs() <= DROP_THRESHOLD_MS);
+        }
+        // the oldest dropped until the buffer is within the max
+        let mut handler = new_handler();
+        for _ in 0..3 {
+            push(&mut handler, 1000, 600);
+        }
+        handler.play_out();
+        assert!(handler.jitter_buffer.is_empty());
+        assert_eq!(handler.dropped, 2);
+    }
+
+    #[test]
+    fn test_play_out_without_video() {
+        let mut handler = new_handler();
+        *handler.video_clock.lock().unwrap() = None;
+        // prebuffered before played
+        push(&mut handler, 1300, 20);
+        handler.play_out();
+        assert_eq!(handler.jitter_buffer.len(), 1);
+        assert_eq!(handler.drift, 0);
+        push(&mut handler, 1320, JITTER_TARGET_MS as _);
+        handler.play_out();
+        assert!(handler.jitter_buffer.is_empty());
+        assert_eq!(handler.dropped, 0);
+    }
+}
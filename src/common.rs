@@ -17,12 +17,35 @@ use std::sync::{Arc, Mutex};
 
 pub const CLIPBOARD_NAME: &'static str = "clipboard";
 pub const CLIPBOARD_INTERVAL: u64 = 333;
+// the larger clipboard messages are sent in chunks, one every CLIPBOARD_CHUNK_INTERVAL ms,
+// not to hold the video frames back on the same connection
+pub const CLIPBOARD_CHUNK_SIZE: usize = 64 * 1024;
+pub const CLIPBOARD_CHUNK_INTERVAL: u64 = 10;
+const DEFAULT_CLIPBOARD_SIZE_LIMIT: usize = 64 * 1024 * 1024;
+
+// the clipboard last read or written, the hash finds the changes without
+// encoding the images again
+#[derive(Debug, Clone, Default)]
+pub struct ClipboardContent {
+    pub format: ClipboardFormat,
+    // the text, html or png, empty if too large to be sent
+    pub content: Vec<u8>,
+    // the plain text alternative of the html
+    pub text: String,
+    pub selection: ClipboardSelection,
+    hash: u64,
+}
 
 lazy_static::lazy_static! {
-    pub static ref CONTENT: Arc<Mutex<String>> = Default::default();
+    pub static ref CONTENT: Arc<Mutex<ClipboardContent>> = Default::default();
     pub static ref SOFTWARE_UPDATE_URL: Arc<Mutex<String>> = Default::default();
 }
 
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    pub static ref PRIMARY_CONTENT: Arc<Mutex<ClipboardContent>> = Default::default();
+}
+
 #[cfg(any(target_os = "android", target_os = "ios"))]
 lazy_static::lazy_static! {
     pub static ref MOBILE_INFO1: Arc<Mutex<String>> = Default::default();
@@ -40,62 +63,472 @@ pub fn valid_for_numlock(evt: &KeyEvent) -> bool {
     }
 }
 
-pub fn create_clipboard_msg(content: String) -> Message {
-    let bytes = content.into_bytes();
+pub fn create_clipboard_msg(content: ClipboardContent) -> Message {
+    let bytes = content.content;
     let compressed = compress_func(&bytes, COMPRESS_LEVEL);
     let compress = compressed.len() < bytes.len();
-    let content = if compress { compressed } else { bytes };
+    let bytes = if compress { compressed } else { bytes };
     let mut msg = Message::new();
     msg.set_clipboard(Clipboard {
         compress,
-        content,
+        content: bytes,
+        format: content.format.into(),
+        text: content.text,
+        selection: content.selection.into(),
         ..Default::default()
     });
     msg
 }
 
+// the larger clipboard contents are neither sent nor received, "clipboard-size-limit" in bytes
+pub fn get_clipboard_size_limit() -> usize {
+    Config::get_option("clipboard-size-limit")
+        .parse()
+        .ok()
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_CLIPBOARD_SIZE_LIMIT)
+}
+
+struct ReceivingChunks {
+    id: u32,
+    total: u64,
+    data: Vec<u8>,
+}
+
+// the clipboard messages being split to, or reassembled from, chunks on one connection
+#[derive(Default)]
+pub struct ClipboardChunks {
+    id: u32,
+    sending: std::collections::VecDeque<Message>,
+    receiving: Option<ReceivingChunks>,
+}
+
+impl ClipboardChunks {
+    // queues the chunks of a large clipboard message, false if it can be sent as it is,
+    // the transfer in progress is replaced, the peer drops it on the first new chunk
+    pub fn push(&mut self, msg: &Message) -> bool {
+        let clipboard = match &msg.union {
+            Some(message::Union::clipboard(clipboard)) => clipboard,
+            _ => return false,
+        };
+        if clipboard.content.len() < CLIPBOARD_CHUNK_SIZE {
+            return false;
+        }
+        let bytes = match clipboard.write_to_bytes() {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                log::error!("Failed to serialize {}: {}", CLIPBOARD_NAME, err);
+                return false;
+            }
+        };
+        // the limits of the receiver are on the content decompressed
+        let size = if clipboard.compress {
+            decompress(&clipboard.content).len()
+        } else {
+            clipboard.content.len()
+        } as u64;
+        self.id = self.id.wrapping_add(1);
+        let total = bytes.len() as u64;
+        self.sending = bytes
+            .chunks(CLIPBOARD_CHUNK_SIZE)
+            .enumerate()
+            .map(|(i, data)| {
+                let mut msg = Message::new();
+                msg.set_clipboard_chunk(ClipboardChunk {
+                    id: self.id,
+                    total,
+                    offset: (i * CLIPBOARD_CHUNK_SIZE) as _,
+                    data: data.to_vec(),
+                    size: if i == 0 { size } else { 0 },
+                    ..Default::default()
+                });
+                msg
+            })
+            .collect();
+        log::info!("{} of {} bytes queued", CLIPBOARD_NAME, total);
+        true
+    }
+
+    // the next chunk to send and the percentage sent with it
+    pub fn next(&mut self) -> Option<(Message, i32)> {
+        let msg = self.sending.pop_front()?;
+        let progress = match &msg.union {
+            Some(message::Union::clipboard_chunk(chunk)) => {
+                ((chunk.offset + chunk.data.len() as u64) * 100 / chunk.total.max(1)) as i32
+            }
+            _ => 100,
+        };
+        Some((msg, progress))
+    }
+
+    #[inline]
+    pub fn is_sending(&self) -> bool {
+        !self.sending.is_empty()
+    }
+
+    // the percentage received, if any chunk is being waited for
+    pub fn receiving_progress(&self) -> Option<i32> {
+        self.receiving
+            .as_ref()
+            .map(|r| (r.data.len() as u64 * 100 / r.total.max(1)) as i32)
+    }
+
+    // stops sending, the message tells the peer to drop what it received
+    pub fn abort_sending(&mut self) -> Option<Message> {
+        if self.sending.is_empty() {
+            return None;
+        }
+        self.sending.clear();
+        Some(self.create_chunk_msg(ClipboardChunk {
+            id: self.id,
+            abort: true,
+            ..Default::default()
+        }))
+    }
+
+    // drops what was received, the message tells the peer to stop sending
+    pub fn reject_receiving(&mut self) -> Option<Message> {
+        let id = self.receiving.take()?.id;
+        Some(self.create_chunk_msg(ClipboardChunk {
+            id,
+            reject: true,
+            ..Default::default()
+        }))
+    }
+
+    // refuses the transfer the chunk starts, the message tells the peer to stop sending
+    pub fn reject(&mut self, chunk: &ClipboardChunk) -> Message {
+        self.receiving = None;
+        self.create_chunk_msg(ClipboardChunk {
+            id: chunk.id,
+            reject: true,
+            ..Default::default()
+        })
+    }
+
+    fn create_chunk_msg(&self, chunk: ClipboardChunk) -> Message {
+        let mut msg = Message::new();
+        msg.set_clipboard_chunk(chunk);
+        msg
+    }
+
+    // the clipboard once all its chunks are received, the error is answered with `reject_receiving`
+    pub fn receive(&mut self, chunk: ClipboardChunk) -> ResultType<Option<Clipboard>> {
+        if chunk.reject {
+            if chunk.id == self.id {
+                log::info!("{} rejected by peer", CLIPBOARD_NAME);
+                self.sending.clear();
+            }
+            return Ok(None);
+        }
+        if chunk.abort {
+            if self.receiving.as_ref().map(|r| r.id) == Some(chunk.id) {
+                self.receiving = None;
+            }
+            return Ok(None);
+        }
+        if chunk.offset == 0 {
+            let allowed = chunk.total as usize <= get_clipboard_size_limit();
+            self.receiving = Some(ReceivingChunks {
+                id: chunk.id,
+                total: chunk.total,
+                data: Vec::with_capacity(if allowed { chunk.total as _ } else { 0 }),
+            });
+            if !allowed {
+                bail!("{} too large: {}", CLIPBOARD_NAME, chunk.total);
+            }
+        }
+        // the rest of a replaced or rejected transfer
+        let r = match self.receiving.as_mut() {
+            Some(r) if r.id == chunk.id => r,
+            _ => return Ok(None),
+        };
+        if r.data.len() as u64 != chunk.offset
+            || r.data.len() as u64 + chunk.data.len() as u64 > r.total
+        {
+            bail!("Invalid {} chunk", CLIPBOARD_NAME);
+        }
+        r.data.extend(chunk.data);
+        if (r.data.len() as u64) < r.total {
+            return Ok(None);
+        }
+        let data = self.receiving.take().map(|r| r.data).unwrap_or_default();
+        Ok(Some(Clipboard::parse_from_bytes(&data)?))
+    }
+}
+
 pub fn check_clipboard(
     ctx: &mut ClipboardContext,
-    old: Option<&Arc<Mutex<String>>>,
+    old: Option<&Arc<Mutex<ClipboardContent>>>,
 ) -> Option<Message> {
     let side = if old.is_none() { "host" } else { "client" };
     let old = if let Some(old) = old { old } else { &CONTENT };
-    if let Ok(content) = ctx.get_text() {
-        if content.len() < 2_000_000 && !content.is_empty() {
-            let changed = content != *old.lock().unwrap();
-            if changed {
-                log::info!("{} update found on {}", CLIPBOARD_NAME, side);
-                *old.lock().unwrap() = content.clone();
-                return Some(create_clipboard_msg(content));
+    let mut old = old.lock().unwrap();
+    let content = read_clipboard(ctx, old.hash)?;
+    log::info!("{} update found on {}", CLIPBOARD_NAME, side);
+    if content.content.len() >= get_clipboard_size_limit() {
+        log::info!("{} too large: {}", CLIPBOARD_NAME, content.content.len());
+        // remembered, not to be read again, but not sent
+        *old = ClipboardContent {
+            hash: content.hash,
+            ..Default::default()
+        };
+        return None;
+    }
+    *old = content.clone();
+    Some(create_clipboard_msg(content))
+}
+
+// the content, if its hash is not `old`, the images are only encoded when they change
+fn read_clipboard(ctx: &mut ClipboardContext, old: u64) -> Option<ClipboardContent> {
+    if let Ok(text) = ctx.get_text() {
+        if text.is_empty() {
+            return None;
+        }
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        if let Some(html) = crate::platform::get_clipboard_html() {
+            let hash = get_hash(&[html.as_bytes(), text.as_bytes()]);
+            if hash == old {
+                return None;
             }
+            return Some(ClipboardContent {
+                format: ClipboardFormat::Html,
+                content: html.into_bytes(),
+                text,
+                hash,
+                ..Default::default()
+            });
+        }
+        let hash = get_hash(&[text.as_bytes()]);
+        if hash == old {
+            return None;
         }
+        return Some(ClipboardContent {
+            format: ClipboardFormat::Text,
+            content: text.into_bytes(),
+            text: "".to_owned(),
+            hash,
+            ..Default::default()
+        });
     }
-    None
+    let image = ctx.get_image().ok()?;
+    let hash = get_hash(&[image.bytes.as_ref()]);
+    if hash == old || image.bytes.is_empty() {
+        return None;
+    }
+    let mut png = Vec::new();
+    if let Err(err) = repng::encode(&mut png, image.width as _, image.height as _, &image.bytes) {
+        log::error!("Failed to encode the {} image: {}", CLIPBOARD_NAME, err);
+        return None;
+    }
+    Some(ClipboardContent {
+        format: ClipboardFormat::ImagePng,
+        content: png,
+        text: "".to_owned(),
+        hash,
+        ..Default::default()
+    })
 }
 
-pub fn update_clipboard(clipboard: Clipboard, old: Option<&Arc<Mutex<String>>>) {
+fn get_hash(parts: &[&[u8]]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    parts.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn update_clipboard(clipboard: Clipboard, old: Option<&Arc<Mutex<ClipboardContent>>>) {
+    if let Some(content) = get_clipboard_content(clipboard) {
+        set_clipboard_content(content, old);
+    }
+}
+
+// the decompressed content of the message, not hashed
+pub fn get_clipboard_content(clipboard: Clipboard) -> Option<ClipboardContent> {
+    let format = match clipboard.format.enum_value() {
+        Ok(format) => format,
+        Err(v) => {
+            log::debug!("Unknown {} format: {}", CLIPBOARD_NAME, v);
+            return None;
+        }
+    };
+    let selection = match clipboard.selection.enum_value() {
+        Ok(selection) => selection,
+        Err(v) => {
+            log::debug!("Unknown {} selection: {}", CLIPBOARD_NAME, v);
+            return None;
+        }
+    };
     let content = if clipboard.compress {
         decompress(&clipboard.content)
     } else {
         clipboard.content
     };
-    if let Ok(content) = String::from_utf8(content) {
-        match ClipboardContext::new() {
-            Ok(mut ctx) => {
-                let side = if old.is_none() { "host" } else { "client" };
-                let old = if let Some(old) = old { old } else { &CONTENT };
-                *old.lock().unwrap() = content.clone();
-                if !content.is_empty() {
-                    // empty content make ctx.set_text crash
-                    allow_err!(ctx.set_text(content));
+    Some(ClipboardContent {
+        format,
+        content,
+        text: clipboard.text,
+        selection,
+        ..Default::default()
+    })
+}
+
+pub fn set_clipboard_content(
+    content: ClipboardContent,
+    old: Option<&Arc<Mutex<ClipboardContent>>>,
+) {
+    match ClipboardContext::new() {
+        Ok(mut ctx) => {
+            let side = if old.is_none() { "host" } else { "client" };
+            let primary = content.selection == ClipboardSelection::Primary;
+            let res = if primary {
+                write_primary_selection(&mut ctx, content)
+            } else {
+                write_clipboard(&mut ctx, content.format, content.content, content.text)
+            };
+            let old: &Arc<Mutex<ClipboardContent>> = match old {
+                Some(old) => old,
+                #[cfg(target_os = "linux")]
+                None if primary => &PRIMARY_CONTENT,
+                None => &CONTENT,
+            };
+            match res {
+                Ok(content) => {
+                    *old.lock().unwrap() = content;
+                    log::debug!("{} updated on {}", CLIPBOARD_NAME, side);
+                }
+                Err(err) => {
+                    log::error!("Failed to update {}: {}", CLIPBOARD_NAME, err);
                 }
-                log::debug!("{} updated on {}", CLIPBOARD_NAME, side);
             }
-            Err(err) => {
-                log::error!("Failed to create clipboard context: {}", err);
+        }
+        Err(err) => {
+            log::error!("Failed to create clipboard context: {}", err);
+        }
+    }
+}
+
+// the X11 PRIMARY selection changes while a selection is dragged,
+// it is only sent once unchanged for PRIMARY_SELECTION_DELAY
+#[cfg(target_os = "linux")]
+pub const PRIMARY_SELECTION_DELAY: std::time::Duration = std::time::Duration::from_millis(300);
+
+// `pending` is the hash of the changed selection and when it was read first
+#[cfg(target_os = "linux")]
+pub fn check_primary_selection(
+    ctx: &mut ClipboardContext,
+    old: &Arc<Mutex<ClipboardContent>>,
+    pending: &mut Option<(u64, std::time::Instant)>,
+) -> Option<Message> {
+    use arboard::{ClipboardExtLinux, LinuxClipboardKind};
+    let text = ctx
+        .get_text_with_clipboard(LinuxClipboardKind::Primary)
+        .ok()?;
+    let hash = get_hash(&[text.as_bytes()]);
+    let mut old = old.lock().unwrap();
+    if text.is_empty() || hash == old.hash {
+        *pending = None;
+        return None;
+    }
+    match pending {
+        Some((h, tm)) if *h == hash => {
+            if tm.elapsed() < PRIMARY_SELECTION_DELAY {
+                return None;
             }
         }
+        _ => {
+            *pending = Some((hash, std::time::Instant::now()));
+            return None;
+        }
     }
+    *pending = None;
+    if text.len() >= get_clipboard_size_limit() {
+        log::info!("primary selection too large: {}", text.len());
+        *old = ClipboardContent {
+            hash,
+            ..Default::default()
+        };
+        return None;
+    }
+    *old = ClipboardContent {
+        format: ClipboardFormat::Text,
+        content: text.into_bytes(),
+        selection: ClipboardSelection::Primary,
+        hash,
+        ..Default::default()
+    };
+    Some(create_clipboard_msg(old.clone()))
+}
+
+#[cfg(target_os = "linux")]
+fn write_primary_selection(
+    ctx: &mut ClipboardContext,
+    content: ClipboardContent,
+) -> ResultType<ClipboardContent> {
+    use arboard::{ClipboardExtLinux, LinuxClipboardKind};
+    if content.format != ClipboardFormat::Text {
+        bail!("Unsupported format of the primary selection");
+    }
+    let text = std::str::from_utf8(&content.content)?;
+    if !text.is_empty() {
+        ctx.set_text_with_clipboard(text.to_owned(), LinuxClipboardKind::Primary)?;
+    }
+    let hash = get_hash(&[&content.content[..]]);
+    Ok(ClipboardContent { hash, ..content })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_primary_selection(
+    _ctx: &mut ClipboardContext,
+    _content: ClipboardContent,
+) -> ResultType<ClipboardContent> {
+    bail!("No primary selection on this platform");
+}
+
+// the content written, hashed as it will be read back
+fn write_clipboard(
+    ctx: &mut ClipboardContext,
+    format: ClipboardFormat,
+    content: Vec<u8>,
+    text: String,
+) -> ResultType<ClipboardContent> {
+    let hash = match format {
+        ClipboardFormat::Text => {
+            let text = std::str::from_utf8(&content)?;
+            // empty content make ctx.set_text crash
+            if !text.is_empty() {
+                ctx.set_text(text.to_owned())?;
+            }
+            get_hash(&[&content[..]])
+        }
+        ClipboardFormat::Html => {
+            let html = std::str::from_utf8(&content)?;
+            ctx.set_html(html, Some(text.as_str()))?;
+            get_hash(&[&content[..], text.as_bytes()])
+        }
+        ClipboardFormat::ImagePng => {
+            let (info, mut reader) = png::Decoder::new(&content[..]).read_info()?;
+            if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+                bail!("Unsupported png: {:?}", info.color_type);
+            }
+            let mut bytes = vec![0; info.buffer_size()];
+            reader.next_frame(&mut bytes)?;
+            let hash = get_hash(&[&bytes[..]]);
+            ctx.set_image(arboard::ImageData {
+                width: info.width as _,
+                height: info.height as _,
+                bytes: bytes.into(),
+            })?;
+            hash
+        }
+    };
+    Ok(ClipboardContent {
+        format,
+        content,
+        text,
+        hash,
+        ..Default::default()
+    })
 }
 
 #[cfg(feature = "use_rubato")]
@@ -465,3 +898,163 @@ pub fn is_ip(id: &str) -> bool {
         .unwrap()
         .is_match(id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clipboard_msg(len: usize) -> Message {
+        let mut msg = Message::new();
+        msg.set_clipboard(Clipboard {
+            content: (0..len).map(|i| (i % 251) as u8).collect(),
+            ..Default::default()
+        });
+        msg
+    }
+
+    fn get_chunk(msg: &Message) -> ClipboardChunk {
+        match &msg.union {
+            Some(message::Union::clipboard_chunk(chunk)) => chunk.clone(),
+            _ => panic!("not a chunk"),
+        }
+    }
+
+    fn get_chunks(sender: &mut ClipboardChunks) -> Vec<ClipboardChunk> {
+        let mut chunks = Vec::new();
+        let mut last = 0;
+        while let Some((msg, progress)) = sender.next() {
+            assert!(progress >= last);
+            last = progress;
+            chunks.push(get_chunk(&msg));
+        }
+        assert_eq!(last, 100);
+        chunks
+    }
+
+    #[test]
+    fn test_clipboard_chunks() {
+        let mut sender = ClipboardChunks::default();
+        assert!(!sender.push(&clipboard_msg(CLIPBOARD_CHUNK_SIZE - 1)));
+        assert!(!sender.is_sending());
+        let msg = clipboard_msg(CLIPBOARD_CHUNK_SIZE * 2 + 1);
+        let content = match &msg.union {
+            Some(message::Union::clipboard(clipboard)) => clipboard.content.clone(),
+            _ => unreachable!(),
+        };
+        assert!(sender.push(&msg));
+        let chunks = get_chunks(&mut sender);
+        assert!(chunks.len() >= 3);
+        assert!(!sender.is_sending());
+        let mut receiver = ClipboardChunks::default();
+        let n = chunks.len();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let res = receiver.receive(chunk).unwrap();
+            if i + 1 < n {
+                assert!(res.is_none());
+                assert!(receiver.receiving_progress().is_some());
+            } else {
+                assert_eq!(res.unwrap().content, content);
+                assert!(receiver.receiving_progress().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn test_clipboard_chunks_size() {
+        let mut sender = ClipboardChunks::default();
+        assert!(sender.push(&clipboard_msg(CLIPBOARD_CHUNK_SIZE * 2)));
+        let chunks = get_chunks(&mut sender);
+        assert_eq!(chunks[0].size, CLIPBOARD_CHUNK_SIZE as u64 * 2);
+        assert!(chunks[1..].iter().all(|c| c.size == 0));
+        // declared decompressed, larger than the bytes sent
+        let mut seed = 1u32;
+        let content: Vec<u8> = (0..CLIPBOARD_CHUNK_SIZE * 4)
+            .map(|i| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                if i % 2 == 0 {
+                    (seed >> 24) as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let compressed = compress_func(&content, COMPRESS_LEVEL);
+        assert!(compressed.len() >= CLIPBOARD_CHUNK_SIZE && compressed.len() < content.len());
+        let mut msg = Message::new();
+        msg.set_clipboard(Clipboard {
+            compress: true,
+            content: compressed,
+            ..Default::default()
+        });
+        assert!(sender.push(&msg));
+        let chunks = get_chunks(&mut sender);
+        assert_eq!(chunks[0].size, content.len() as u64);
+        assert!(chunks[0].total < chunks[0].size);
+    }
+
+    #[test]
+    fn test_clipboard_chunks_out_of_order() {
+        let mut sender = ClipboardChunks::default();
+        assert!(sender.push(&clipboard_msg(CLIPBOARD_CHUNK_SIZE * 3)));
+        let mut chunks = get_chunks(&mut sender);
+        let mut receiver = ClipboardChunks::default();
+        let first = chunks.remove(0);
+        let id = first.id;
+        assert!(receiver.receive(first).unwrap().is_none());
+        assert!(receiver.receive(chunks.remove(1)).is_err());
+        let reject = get_chunk(&receiver.reject_receiving().unwrap());
+        assert!(reject.reject);
+        assert_eq!(reject.id, id);
+        assert!(receiver.reject_receiving().is_none());
+        // the rest of the rejected transfer is ignored
+        assert!(receiver.receive(chunks.remove(0)).unwrap().is_none());
+        assert!(receiver.receiving_progress().is_none());
+    }
+
+    #[test]
+    fn test_clipboard_chunks_abort() {
+        let mut sender = ClipboardChunks::default();
+        assert!(sender.abort_sending().is_none());
+        assert!(sender.push(&clipboard_msg(CLIPBOARD_CHUNK_SIZE * 2)));
+        let (first, _) = sender.next().unwrap();
+        let mut receiver = ClipboardChunks::default();
+        assert!(receiver.receive(get_chunk(&first)).unwrap().is_none());
+        assert!(receiver.receiving_progress().unwrap() < 100);
+        let abort = get_chunk(&sender.abort_sending().unwrap());
+        assert!(abort.abort);
+        assert!(!sender.is_sending());
+        assert!(receiver.receive(abort).unwrap().is_none());
+        assert!(receiver.receiving_progress().is_none());
+    }
+
+    #[test]
+    fn test_clipboard_chunks_reject() {
+        let mut sender = ClipboardChunks::default();
+        assert!(sender.push(&clipboard_msg(CLIPBOARD_CHUNK_SIZE * 2)));
+        let (first, _) = sender.next().unwrap();
+        let mut receiver = ClipboardChunks::default();
+        let reject = get_chunk(&receiver.reject(&get_chunk(&first)));
+        assert!(reject.reject);
+        assert!(receiver.receive(reject).unwrap().is_none());
+        assert!(!sender.is_sending());
+        // a reject of an older transfer is ignored
+        assert!(sender.push(&clipboard_msg(CLIPBOARD_CHUNK_SIZE * 2)));
+        let mut old = get_chunk(&first);
+        old.reject = true;
+        assert!(sender.receive(old).unwrap().is_none());
+        assert!(sender.is_sending());
+    }
+
+    #[test]
+    fn test_clipboard_chunks_oversized() {
+        let mut receiver = ClipboardChunks::default();
+        let chunk = ClipboardChunk {
+            id: 1,
+            total: get_clipboard_size_limit() as u64 + 1,
+            data: vec![0; 16],
+            ..Default::default()
+        };
+        assert!(receiver.receive(chunk).is_err());
+        assert!(get_chunk(&receiver.reject_receiving().unwrap()).reject);
+    }
+}
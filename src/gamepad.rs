@@ -0,0 +1,164 @@
+// The game controllers of the client, their state is sent to the peer when it changes
+// and the rumble of the virtual controllers there is played back on them.
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Ticks},
+    Axis, Button, Gamepad, GamepadId, Gilrs,
+};
+use hbb_common::{
+    log,
+    message_proto::{GamepadEvent, GamepadRumble},
+};
+use std::{collections::HashMap, sync::mpsc, time::Duration};
+
+const INTERVAL: Duration = Duration::from_millis(8);
+const MAX_STICK: f32 = 32767.;
+const MAX_TRIGGER: f32 = 255.;
+
+// in the order of the bits of GamepadEvent::buttons, the bumpers are the triggers of gilrs
+const BUTTONS: &[Button] = &[
+    Button::South,
+    Button::East,
+    Button::West,
+    Button::North,
+    Button::LeftTrigger,
+    Button::RightTrigger,
+    Button::Select,
+    Button::Start,
+    Button::Mode,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+// runs until `send` fails, the controllers are unplugged on the peer when not `active`
+pub fn run(
+    rx_rumble: mpsc::Receiver<GamepadRumble>,
+    active: impl Fn() -> bool,
+    mut send: impl FnMut(GamepadEvent) -> bool,
+) {
+    let mut gilrs = match Gilrs::new() {
+        Ok(gilrs) => gilrs,
+        Err(err) => {
+            log::error!("Failed to open the game controllers: {}", err);
+            return;
+        }
+    };
+    let mut sent: HashMap<u32, GamepadEvent> = HashMap::new();
+    // dropping an effect stops it
+    let mut effects: HashMap<u32, Effect> = HashMap::new();
+    loop {
+        // the state of the controllers follows the events
+        while gilrs.next_event().is_some() {}
+        let mut states = HashMap::new();
+        if active() {
+            for (id, gamepad) in gilrs.gamepads() {
+                states.insert(get_index(id), get_state(get_index(id), &gamepad));
+            }
+        }
+        for (index, state) in states.iter() {
+            if sent.get(index) != Some(state) && !send(state.clone()) {
+                return;
+            }
+        }
+        for index in sent.keys() {
+            if !states.contains_key(index) {
+                effects.remove(index);
+                let evt = GamepadEvent {
+                    index: *index,
+                    connected: false,
+                    ..Default::default()
+                };
+                if !send(evt) {
+                    return;
+                }
+            }
+        }
+        sent = states;
+        while let Ok(rumble) = rx_rumble.try_recv() {
+            play(&mut gilrs, &mut effects, &rumble);
+        }
+        std::thread::sleep(INTERVAL);
+    }
+}
+
+#[inline]
+fn get_index(id: GamepadId) -> u32 {
+    usize::from(id) as _
+}
+
+fn get_state(index: u32, gamepad: &Gamepad) -> GamepadEvent {
+    let buttons = BUTTONS
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| gamepad.is_pressed(**b))
+        .fold(0, |acc, (i, _)| acc | 1 << i);
+    let stick = |axis: Axis| (gamepad.value(axis) * MAX_STICK) as i32;
+    let trigger = |button: Button| {
+        gamepad
+            .button_data(button)
+            .map(|d| (d.value() * MAX_TRIGGER) as u32)
+            .unwrap_or(0)
+    };
+    // up is positive in gilrs
+    GamepadEvent {
+        index,
+        connected: true,
+        buttons,
+        left_x: stick(Axis::LeftStickX),
+        left_y: -stick(Axis::LeftStickY),
+        right_x: stick(Axis::RightStickX),
+        right_y: -stick(Axis::RightStickY),
+        left_trigger: trigger(Button::LeftTrigger2),
+        right_trigger: trigger(Button::RightTrigger2),
+        ..Default::default()
+    }
+}
+
+fn play(gilrs: &mut Gilrs, effects: &mut HashMap<u32, Effect>, rumble: &GamepadRumble) {
+    effects.remove(&rumble.index);
+    if rumble.strong == 0 && rumble.weak == 0 {
+        return;
+    }
+    let id = match gilrs
+        .gamepads()
+        .find(|(id, gamepad)| get_index(*id) == rumble.index && gamepad.is_ff_supported())
+    {
+        Some((id, _)) => id,
+        None => return,
+    };
+    let repeat = if rumble.duration > 0 {
+        Repeat::For(Ticks::from_ms(rumble.duration))
+    } else {
+        Repeat::Infinitely
+    };
+    let magnitude = |v: u32| v.min(u16::MAX as _) as u16;
+    let res = EffectBuilder::new()
+        .add_effect(BaseEffect {
+            kind: BaseEffectType::Strong {
+                magnitude: magnitude(rumble.strong),
+            },
+            ..Default::default()
+        })
+        .add_effect(BaseEffect {
+            kind: BaseEffectType::Weak {
+                magnitude: magnitude(rumble.weak),
+            },
+            ..Default::default()
+        })
+        .repeat(repeat)
+        .gamepads(&[id])
+        .finish(gilrs)
+        .and_then(|effect| {
+            effect.play()?;
+            Ok(effect)
+        });
+    match res {
+        Ok(effect) => {
+            effects.insert(rumble.index, effect);
+        }
+        Err(err) => log::error!("Failed to play the rumble: {}", err),
+    }
+}
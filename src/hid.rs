@@ -0,0 +1,187 @@
+// USB HID keyboard usages (usage page 0x07) with the Linux evdev code of the key at the same
+// position, X11 keycodes are evdev codes + 8, and the set 1 scan codes of the main block
+// (those not prefixed by 0xE0) are equal to the evdev codes.
+const HID_EVDEV: &[(u32, u16)] = &[
+    (0x04, 30),  // A
+    (0x05, 48),  // B
+    (0x06, 46),  // C
+    (0x07, 32),  // D
+    (0x08, 18),  // E
+    (0x09, 33),  // F
+    (0x0a, 34),  // G
+    (0x0b, 35),  // H
+    (0x0c, 23),  // I
+    (0x0d, 36),  // J
+    (0x0e, 37),  // K
+    (0x0f, 38),  // L
+    (0x10, 50),  // M
+    (0x11, 49),  // N
+    (0x12, 24),  // O
+    (0x13, 25),  // P
+    (0x14, 16),  // Q
+    (0x15, 19),  // R
+    (0x16, 31),  // S
+    (0x17, 20),  // T
+    (0x18, 22),  // U
+    (0x19, 47),  // V
+    (0x1a, 17),  // W
+    (0x1b, 45),  // X
+    (0x1c, 21),  // Y
+    (0x1d, 44),  // Z
+    (0x1e, 2),   // 1
+    (0x1f, 3),   // 2
+    (0x20, 4),   // 3
+    (0x21, 5),   // 4
+    (0x22, 6),   // 5
+    (0x23, 7),   // 6
+    (0x24, 8),   // 7
+    (0x25, 9),   // 8
+    (0x26, 10),  // 9
+    (0x27, 11),  // 0
+    (0x28, 28),  // Enter
+    (0x29, 1),   // Escape
+    (0x2a, 14),  // Backspace
+    (0x2b, 15),  // Tab
+    (0x2c, 57),  // Space
+    (0x2d, 12),  // -
+    (0x2e, 13),  // =
+    (0x2f, 26),  // [
+    (0x30, 27),  // ]
+    (0x31, 43),  // \
+    (0x32, 43),  // Non-US # and ~, at the place of \ on ISO keyboards
+    (0x33, 39),  // ;
+    (0x34, 40),  // '
+    (0x35, 41),  // `
+    (0x36, 51),  // ,
+    (0x37, 52),  // .
+    (0x38, 53),  // /
+    (0x39, 58),  // CapsLock
+    (0x3a, 59),  // F1
+    (0x3b, 60),  // F2
+    (0x3c, 61),  // F3
+    (0x3d, 62),  // F4
+    (0x3e, 63),  // F5
+    (0x3f, 64),  // F6
+    (0x40, 65),  // F7
+    (0x41, 66),  // F8
+    (0x42, 67),  // F9
+    (0x43, 68),  // F10
+    (0x44, 87),  // F11
+    (0x45, 88),  // F12
+    (0x46, 99),  // PrintScreen
+    (0x47, 70),  // ScrollLock
+    (0x48, 119), // Pause
+    (0x49, 110), // Insert
+    (0x4a, 102), // Home
+    (0x4b, 104), // PageUp
+    (0x4c, 111), // Delete
+    (0x4d, 107), // End
+    (0x4e, 109), // PageDown
+    (0x4f, 106), // Right
+    (0x50, 105), // Left
+    (0x51, 108), // Down
+    (0x52, 103), // Up
+    (0x53, 69),  // NumLock
+    (0x54, 98),  // Keypad /
+    (0x55, 55),  // Keypad *
+    (0x56, 74),  // Keypad -
+    (0x57, 78),  // Keypad +
+    (0x58, 96),  // Keypad Enter
+    (0x59, 79),  // Keypad 1
+    (0x5a, 80),  // Keypad 2
+    (0x5b, 81),  // Keypad 3
+    (0x5c, 75),  // Keypad 4
+    (0x5d, 76),  // Keypad 5
+    (0x5e, 77),  // Keypad 6
+    (0x5f, 71),  // Keypad 7
+    (0x60, 72),  // Keypad 8
+    (0x61, 73),  // Keypad 9
+    (0x62, 82),  // Keypad 0
+    (0x63, 83),  // Keypad .
+    (0x64, 86),  // the key between left shift and Z on ISO keyboards
+    (0x65, 127), // Menu
+    (0x67, 117), // Keypad =
+    (0x68, 183), // F13
+    (0x69, 184), // F14
+    (0x6a, 185), // F15
+    (0x6b, 186), // F16
+    (0x6c, 187), // F17
+    (0x6d, 188), // F18
+    (0x6e, 189), // F19
+    (0x6f, 190), // F20
+    (0x70, 191), // F21
+    (0x71, 192), // F22
+    (0x72, 193), // F23
+    (0x73, 194), // F24
+    (0x7f, 113), // Mute
+    (0x80, 115), // VolumeUp
+    (0x81, 114), // VolumeDown
+    (0x85, 121), // Keypad ,
+    (0x87, 89),  // Ro
+    (0x88, 93),  // Katakana/Hiragana
+    (0x89, 124), // Yen
+    (0x8a, 92),  // Henkan
+    (0x8b, 94),  // Muhenkan
+    (0x90, 122), // Hangul
+    (0x91, 123), // Hanja
+    (0xe0, 29),  // LeftControl
+    (0xe1, 42),  // LeftShift
+    (0xe2, 56),  // LeftAlt
+    (0xe3, 125), // LeftMeta
+    (0xe4, 97),  // RightControl
+    (0xe5, 54),  // RightShift
+    (0xe6, 100), // RightAlt
+    (0xe7, 126), // RightMeta
+];
+
+pub fn hid_to_evdev(usage: u32) -> Option<u16> {
+    HID_EVDEV.iter().find(|x| x.0 == usage).map(|x| x.1)
+}
+
+pub fn evdev_to_hid(code: u16) -> Option<u32> {
+    HID_EVDEV.iter().find(|x| x.1 == code).map(|x| x.0)
+}
+
+#[inline]
+pub fn hid_to_x11_keycode(usage: u32) -> Option<u16> {
+    hid_to_evdev(usage).map(|x| x + 8)
+}
+
+// the character keys, whose virtual key codes follow the layout on Windows
+#[inline]
+pub fn is_layout_key(usage: u32) -> bool {
+    (0x04..=0x27).contains(&usage) || (0x2d..=0x38).contains(&usage) || usage == 0x64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hid_to_x11_keycode() {
+        // (usage, X11 keycode, layout key)
+        let cases = [
+            (0x04, Some(38), true),   // A
+            (0x1d, Some(52), true),   // Z
+            (0x27, Some(19), true),   // 0
+            (0x28, Some(36), false),  // Enter
+            (0x2c, Some(65), false),  // Space
+            (0x31, Some(51), true),   // \
+            (0x32, Some(51), true),   // Non-US #
+            (0x38, Some(61), true),   // /
+            (0x39, Some(66), false),  // CapsLock
+            (0x64, Some(94), true),   // Non-US \
+            (0xe0, Some(37), false),  // LeftControl
+            (0xe7, Some(134), false), // RightMeta
+            (0x00, None, false),
+            (0x03, None, false),
+            (0xff, None, false),
+        ];
+        for (usage, keycode, layout) in cases {
+            assert_eq!(hid_to_x11_keycode(usage), keycode, "{:#x}", usage);
+            assert_eq!(is_layout_key(usage), layout, "{:#x}", usage);
+        }
+        // \ of the US layout comes back first
+        assert_eq!(evdev_to_hid(43), Some(0x31));
+    }
+}
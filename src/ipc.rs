@@ -43,7 +43,10 @@ pub enum FS {
     NewWrite {
         path: String,
         id: i32,
-        files: Vec<(String, u64)>,
+        files: Vec<(String, u64, u64)>, // name, modified time and size
+        resume: bool,
+        delta: bool,
+        conflict_policy: i32,
     },
     CancelWrite {
         id: i32,
@@ -53,6 +56,9 @@ pub enum FS {
         file_num: i32,
         data: Vec<u8>,
         compressed: bool,
+        offset: u64,
+        sha256: Vec<u8>,
+        block_refs: Vec<u64>,
     },
     WriteDone {
         id: i32,
@@ -107,6 +113,11 @@ pub enum Data {
     SyncConfigToUserResp(bool),
     ClipbaordFile(ClipbaordFile),
     ClipboardFileEnabled(bool),
+    GetAudioSources,
+    InputControl {
+        has_control: bool,
+        requested: bool,
+    },
 }
 
 #[tokio::main(flavor = "current_thread")]
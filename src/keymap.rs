@@ -0,0 +1,344 @@
+// Per-peer keyboard remapping, applied by the client before sending the key events.
+// One rule per line, like `Meta+LeftArrow -> Home`, the keys are named as in ControlKey
+// or are a single character, the modifiers are Control, Alt, Shift and Meta.
+// A rule without modifiers on the left renames the key, also where it is held as a
+// modifier, the other rules only apply to the exact chord.
+use hbb_common::{
+    bail,
+    message_proto::{key_event, ControlKey, KeyEvent},
+    protobuf::ProtobufEnum,
+    ResultType,
+};
+use std::collections::HashMap;
+
+const MAC_TO_PC: &str = "Meta+LeftArrow -> Home
+Meta+RightArrow -> End
+Meta+UpArrow -> Control+Home
+Meta+DownArrow -> Control+End
+Alt+LeftArrow -> Control+LeftArrow
+Alt+RightArrow -> Control+RightArrow
+Alt+Backspace -> Control+Backspace
+Meta -> Control
+Control -> Meta";
+
+pub const PRESETS: &[(&str, &str)] = &[
+    ("Mac to Linux", MAC_TO_PC),
+    ("Mac to Windows", MAC_TO_PC),
+    ("Swap Ctrl/Caps", "CapsLock -> Control\nControl -> CapsLock"),
+];
+
+const MODIFIERS: &[ControlKey] = &[
+    ControlKey::Control,
+    ControlKey::Alt,
+    ControlKey::Shift,
+    ControlKey::Meta,
+];
+
+const ALIASES: &[(&str, ControlKey)] = &[
+    ("Ctrl", ControlKey::Control),
+    ("Cmd", ControlKey::Meta),
+    ("Command", ControlKey::Meta),
+    ("Win", ControlKey::Meta),
+    ("Super", ControlKey::Meta),
+    ("Option", ControlKey::Alt),
+    ("Caps", ControlKey::CapsLock),
+    ("Left", ControlKey::LeftArrow),
+    ("Right", ControlKey::RightArrow),
+    ("Up", ControlKey::UpArrow),
+    ("Down", ControlKey::DownArrow),
+    ("Enter", ControlKey::Return),
+    ("Esc", ControlKey::Escape),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Code {
+    Control(ControlKey),
+    Chr(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Chord {
+    code: Code,
+    // in the order of MODIFIERS
+    modifiers: Vec<ControlKey>,
+}
+
+#[derive(Debug, Default)]
+pub struct Keymap {
+    rules: Vec<(Chord, Chord)>,
+    // the keys pressed as a remapped chord, released as their target
+    pressed: HashMap<Code, Chord>,
+    // the Caps Lock state sent to the peer when the key is remapped,
+    // the local state does not follow the keys then
+    caps_lock: Option<bool>,
+}
+
+impl Keymap {
+    pub fn new(rules: &[(String, String)]) -> Self {
+        let mut keymap = Self::default();
+        for (from, to) in rules {
+            match (parse_chord(from), parse_chord(to)) {
+                (Ok(from), Ok(to)) => keymap.rules.push((from, to)),
+                _ => log::error!("Invalid key mapping: {} -> {}", from, to),
+            }
+        }
+        let caps_lock = Code::Control(ControlKey::CapsLock);
+        if keymap.rules.iter().any(|(from, to)| {
+            from.modifiers.is_empty() && (from.code == caps_lock || to.code == caps_lock)
+        }) {
+            keymap.caps_lock = Some(false);
+        }
+        keymap
+    }
+
+    pub fn caps_lock(&self) -> Option<bool> {
+        self.caps_lock
+    }
+
+    pub fn apply(&mut self, evt: &mut KeyEvent) {
+        let code = match get_code(evt) {
+            Some(code) => code,
+            None => return,
+        };
+        let modifiers: Vec<ControlKey> = MODIFIERS
+            .iter()
+            .filter(|m| evt.modifiers.iter().any(|x| x.value() == m.value()))
+            .cloned()
+            .collect();
+        let (code, modifiers) = if let Some(to) = self.pressed.get(&code).cloned() {
+            if !evt.down {
+                self.pressed.remove(&code);
+            }
+            (to.code, to.modifiers)
+        } else if let Some((_, to)) = self.rules.iter().find(|(from, _)| {
+            !from.modifiers.is_empty() && from.code == code && from.modifiers == modifiers
+        }) {
+            if evt.down && !evt.press {
+                self.pressed.insert(code, to.clone());
+            }
+            (to.code, to.modifiers.clone())
+        } else if self.rules.is_empty() {
+            return;
+        } else {
+            let (code, mut extra) = self.rename(code);
+            for m in modifiers {
+                if let (Code::Control(m), added) = self.rename(Code::Control(m)) {
+                    if MODIFIERS.contains(&m) {
+                        extra.push(m);
+                        extra.extend(added);
+                    }
+                }
+            }
+            let modifiers = MODIFIERS
+                .iter()
+                .filter(|m| extra.contains(m))
+                .cloned()
+                .collect();
+            (code, modifiers)
+        };
+        match code {
+            Code::Control(ck) => {
+                if ck == ControlKey::CapsLock && (evt.down || evt.press) {
+                    if let Some(state) = self.caps_lock.as_mut() {
+                        *state = !*state;
+                    }
+                }
+                evt.set_control_key(ck);
+            }
+            Code::Chr(chr) => evt.set_chr(chr),
+        }
+        evt.modifiers
+            .retain(|x| !MODIFIERS.iter().any(|m| m.value() == x.value()));
+        for m in modifiers {
+            if code != Code::Control(m) {
+                evt.modifiers.push(m.into());
+            }
+        }
+    }
+
+    fn rename(&self, code: Code) -> (Code, Vec<ControlKey>) {
+        match self
+            .rules
+            .iter()
+            .find(|(from, _)| from.modifiers.is_empty() && from.code == code)
+        {
+            Some((_, to)) => (to.code, to.modifiers.clone()),
+            None => (code, Vec::new()),
+        }
+    }
+}
+
+// the text of the rules, one per line, checked and normalized
+pub fn parse(text: &str) -> ResultType<Vec<(String, String)>> {
+    let mut rules = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (from, to) = match line.split_once("->") {
+            Some(x) => x,
+            None => bail!("Missing '->' in: {}", line),
+        };
+        rules.push((parse_chord(from)?.to_string(), parse_chord(to)?.to_string()));
+    }
+    Ok(rules)
+}
+
+pub fn format(rules: &[(String, String)]) -> String {
+    rules
+        .iter()
+        .map(|(from, to)| format!("{} -> {}", from, to))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn get_code(evt: &KeyEvent) -> Option<Code> {
+    match evt.union {
+        Some(key_event::Union::control_key(ck)) => ck.enum_value().ok().map(Code::Control),
+        Some(key_event::Union::chr(chr)) => Some(Code::Chr(chr)),
+        _ => None,
+    }
+}
+
+fn parse_chord(s: &str) -> ResultType<Chord> {
+    let s = s.trim();
+    // the key comes last and may be '+' itself
+    let (mods, key) = if s == "+" {
+        ("", "+")
+    } else if let Some(mods) = s.strip_suffix("++") {
+        (mods, "+")
+    } else {
+        s.rsplit_once('+').unwrap_or(("", s))
+    };
+    let code = parse_key(key)?;
+    let mut modifiers = Vec::new();
+    if !mods.is_empty() {
+        for m in mods.split('+') {
+            match parse_key(m)? {
+                Code::Control(ck) if MODIFIERS.contains(&ck) => modifiers.push(ck),
+                _ => bail!("Not a modifier: {}", m.trim()),
+            }
+        }
+    }
+    let modifiers = MODIFIERS
+        .iter()
+        .filter(|m| modifiers.contains(m))
+        .cloned()
+        .collect();
+    Ok(Chord { code, modifiers })
+}
+
+fn parse_key(name: &str) -> ResultType<Code> {
+    let name = name.trim();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Code::Chr(c.to_ascii_lowercase() as _));
+    }
+    if let Some((_, ck)) = ALIASES.iter().find(|x| x.0.eq_ignore_ascii_case(name)) {
+        return Ok(Code::Control(*ck));
+    }
+    match (1..=ControlKey::LockScreen.value())
+        .filter_map(ControlKey::from_i32)
+        .find(|ck| format!("{:?}", ck).eq_ignore_ascii_case(name))
+    {
+        Some(ck) => Ok(Code::Control(ck)),
+        None => bail!("Unknown key: {}", name),
+    }
+}
+
+impl std::fmt::Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for m in self.modifiers.iter() {
+            write!(f, "{:?}+", m)?;
+        }
+        match self.code {
+            Code::Control(ck) => write!(f, "{:?}", ck),
+            Code::Chr(chr) => match std::char::from_u32(chr) {
+                Some(c) => write!(f, "{}", c),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(ck: ControlKey, down: bool, modifiers: &[ControlKey]) -> KeyEvent {
+        let mut evt = KeyEvent::new();
+        evt.set_control_key(ck);
+        evt.down = down;
+        for m in modifiers {
+            evt.modifiers.push((*m).into());
+        }
+        evt
+    }
+
+    #[test]
+    fn parse_rules() {
+        let rules = parse("# comment\nCmd+Left -> Home\nctrl+c -> Meta+C\nControl++ -> =").unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                ("Meta+LeftArrow".to_owned(), "Home".to_owned()),
+                ("Control+c".to_owned(), "Meta+c".to_owned()),
+                ("Control++".to_owned(), "=".to_owned()),
+            ]
+        );
+        assert!(parse("Home -> Foo").is_err());
+        assert!(parse("Home+Left -> End").is_err());
+        assert!(parse("Home End").is_err());
+        for (_, text) in PRESETS {
+            assert!(parse(text).is_ok());
+        }
+    }
+
+    #[test]
+    fn mac_to_linux() {
+        let mut keymap = Keymap::new(&parse(MAC_TO_PC).unwrap());
+        let mut evt = key(ControlKey::Meta, true, &[]);
+        keymap.apply(&mut evt);
+        assert_eq!(get_code(&evt), Some(Code::Control(ControlKey::Control)));
+        assert!(evt.modifiers.is_empty());
+
+        let mut evt = KeyEvent::new();
+        evt.set_chr('c' as _);
+        evt.down = true;
+        evt.modifiers.push(ControlKey::Meta.into());
+        evt.modifiers.push(ControlKey::CapsLock.into());
+        keymap.apply(&mut evt);
+        assert_eq!(get_code(&evt), Some(Code::Chr('c' as _)));
+        assert_eq!(
+            evt.modifiers.iter().map(|m| m.value()).collect::<Vec<_>>(),
+            vec![ControlKey::CapsLock.value(), ControlKey::Control.value()]
+        );
+
+        // released as the key it was pressed as, whatever the modifiers are then
+        let mut evt = key(ControlKey::LeftArrow, true, &[ControlKey::Meta]);
+        keymap.apply(&mut evt);
+        assert_eq!(get_code(&evt), Some(Code::Control(ControlKey::Home)));
+        assert!(evt.modifiers.is_empty());
+        let mut evt = key(ControlKey::LeftArrow, false, &[]);
+        keymap.apply(&mut evt);
+        assert_eq!(get_code(&evt), Some(Code::Control(ControlKey::Home)));
+        let mut evt = key(ControlKey::LeftArrow, false, &[]);
+        keymap.apply(&mut evt);
+        assert_eq!(get_code(&evt), Some(Code::Control(ControlKey::LeftArrow)));
+        assert_eq!(keymap.caps_lock(), None);
+    }
+
+    #[test]
+    fn swap_caps() {
+        let mut keymap = Keymap::new(&parse(PRESETS[2].1).unwrap());
+        assert_eq!(keymap.caps_lock(), Some(false));
+        let mut evt = key(ControlKey::Control, true, &[]);
+        keymap.apply(&mut evt);
+        assert_eq!(get_code(&evt), Some(Code::Control(ControlKey::CapsLock)));
+        assert_eq!(keymap.caps_lock(), Some(true));
+        let mut evt = key(ControlKey::Home, true, &[ControlKey::Control]);
+        keymap.apply(&mut evt);
+        assert!(evt.modifiers.is_empty());
+    }
+}
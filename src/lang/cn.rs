@@ -265,5 +265,83 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("android_version_audio_tip", "当前安卓版本不支持音频录制，请升级至安卓10或更高。"),
         ("android_start_service_tip", "点击 [启动服务] 或打开 [屏幕录制] 权限开启手机屏幕共享服务。"),
         ("Account", "账号"),
+        ("Standard audio", "标准音频"),
+        ("Voice", "语音"),
+        ("Music", "音乐"),
+        ("Audio off", "关闭音频"),
+        ("Audio source", "音频源"),
+        ("Application", "应用程序"),
+        ("Default", "默认"),
+        ("Relative mouse mode", "相对鼠标模式"),
+        ("Physical keyboard", "物理键盘"),
+        ("Type clipboard", "键入剪贴板内容"),
+        ("Stop typing", "停止键入"),
+        ("Delay between keystrokes", "按键间隔"),
+        ("No text in the clipboard", "剪贴板中没有文本"),
+        ("Record macro", "录制宏"),
+        ("Stop recording", "停止录制"),
+        ("Macros", "宏"),
+        ("Edit macro", "编辑宏"),
+        ("New macro", "新建宏"),
+        ("Macro", "宏"),
+        ("Nothing was recorded", "没有录制任何内容"),
+        ("Has control", "正在控制"),
+        ("Requesting control", "请求控制中"),
+        ("View only", "仅查看"),
+        ("Give control", "授予控制"),
+        ("Controlled by", "控制者"),
+        ("Request control", "请求控制"),
+        ("Withdraw control request", "撤回控制请求"),
+        ("Release control", "释放控制"),
+        ("Control request", "控制请求"),
+        ("requests the control", "请求控制"),
+        ("Key mapping", "键位映射"),
+        ("Edit key mapping", "编辑键位映射"),
+        ("Preset", "预设"),
+        ("Import", "导入"),
+        ("Export", "导出"),
+        ("One rule per line, e.g.", "每行一条规则，例如"),
+        ("Caps Lock", "大写锁定"),
+        ("Num Lock", "数字锁定"),
+        ("Scroll Lock", "滚动锁定"),
+        ("Local input method", "使用本地输入法"),
+        ("Forward gamepads", "转发游戏手柄"),
+        ("Clipboard Policy", "剪贴板策略"),
+        ("Direction", "方向"),
+        ("Both", "双向"),
+        ("To the client only", "仅发送到控制端"),
+        ("From the client only", "仅从控制端接收"),
+        ("Maximum size", "最大大小"),
+        ("Formats", "格式"),
+        ("Text", "文本"),
+        ("Image", "图片"),
+        ("Deny-list", "拒绝规则"),
+        ("Invalid size", "无效大小"),
+        ("Select at least one format", "请至少选择一种格式"),
+        ("Sync primary selection", "同步主选区"),
+        ("Transfer limit", "传输上限"),
+        ("Receiving clipboard", "正在接收剪贴板"),
+        ("Sending clipboard", "正在发送剪贴板"),
+        ("Resume transfers", "继续传输"),
+        ("resume_transfers_tip", "以下传输上次被中断，是否继续？取消将不再保留。"),
+        ("Retry transfers", "重试传输"),
+        ("retry_transfers_tip", "部分文件传输失败或与源文件不一致，是否重新传输？取消将不再保留。"),
+        ("Sync", "同步"),
+        ("sync_tip", "仅传输目标中新增或已修改（按大小和修改时间）的文件。"),
+        ("Delete the extra files at the destination", "删除目标中多余的文件"),
+        ("Dry run", "仅列出变更"),
+        ("No changes", "没有变更"),
+        ("Confirm Overwrite", "确认覆盖"),
+        ("overwrite_tip", "目标位置已存在此文件，是否覆盖？"),
+        ("If files exist", "文件已存在时"),
+        ("Overwrite", "覆盖"),
+        ("Skip if identical", "相同时跳过"),
+        ("Ask", "询问"),
+        ("Audio statistics", "音频统计"),
+        ("Jitter buffer", "抖动缓冲"),
+        ("Output latency", "输出延迟"),
+        ("Audio/video drift", "音视频偏差"),
+        ("Dropped", "丢弃"),
+        ("audio_source_tip", "音频被所有连接共享，已保留另一个访问者选择的音频源。"),
     ].iter().cloned().collect();
 }
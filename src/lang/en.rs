@@ -24,5 +24,10 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("android_stop_service_tip", "Closing the service will automatically close all established connections."),
         ("android_version_audio_tip", "The current Android version does not support audio capture, please upgrade to Android 10 or higher."),
         ("android_start_service_tip", "Tap [Start Service] or OPEN [Screen Capture] permission to start the screen sharing service."),
+        ("resume_transfers_tip", "These transfers were interrupted last time. Resume them? Cancel to forget them."),
+        ("retry_transfers_tip", "Some files failed or did not match their source. Transfer them again? Cancel to forget them."),
+        ("sync_tip", "Only the files new or changed at the destination, by size and modified time, are transferred."),
+        ("overwrite_tip", "This file already exists at the destination. Do you want to overwrite it?"),
+        ("audio_source_tip", "The audio is shared by all the connections, the source chosen by another viewer is kept."),
     ].iter().cloned().collect();
 }
@@ -265,5 +265,83 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("android_version_audio_tip", ""),
         ("android_start_service_tip", ""),
         ("Account", ""),
+        ("Standard audio", ""),
+        ("Voice", ""),
+        ("Music", ""),
+        ("Audio off", ""),
+        ("Audio source", ""),
+        ("Application", ""),
+        ("Default", ""),
+        ("Relative mouse mode", ""),
+        ("Physical keyboard", ""),
+        ("Type clipboard", ""),
+        ("Stop typing", ""),
+        ("Delay between keystrokes", ""),
+        ("No text in the clipboard", ""),
+        ("Record macro", ""),
+        ("Stop recording", ""),
+        ("Macros", ""),
+        ("Edit macro", ""),
+        ("New macro", ""),
+        ("Macro", ""),
+        ("Nothing was recorded", ""),
+        ("Has control", ""),
+        ("Requesting control", ""),
+        ("View only", ""),
+        ("Give control", ""),
+        ("Controlled by", ""),
+        ("Request control", ""),
+        ("Withdraw control request", ""),
+        ("Release control", ""),
+        ("Control request", ""),
+        ("requests the control", ""),
+        ("Key mapping", ""),
+        ("Edit key mapping", ""),
+        ("Preset", ""),
+        ("Import", ""),
+        ("Export", ""),
+        ("One rule per line, e.g.", ""),
+        ("Caps Lock", ""),
+        ("Num Lock", ""),
+        ("Scroll Lock", ""),
+        ("Local input method", ""),
+        ("Forward gamepads", ""),
+        ("Clipboard Policy", ""),
+        ("Direction", ""),
+        ("Both", ""),
+        ("To the client only", ""),
+        ("From the client only", ""),
+        ("Maximum size", ""),
+        ("Formats", ""),
+        ("Text", ""),
+        ("Image", ""),
+        ("Deny-list", ""),
+        ("Invalid size", ""),
+        ("Select at least one format", ""),
+        ("Sync primary selection", ""),
+        ("Transfer limit", ""),
+        ("Receiving clipboard", ""),
+        ("Sending clipboard", ""),
+        ("Resume transfers", ""),
+        ("resume_transfers_tip", ""),
+        ("Retry transfers", ""),
+        ("retry_transfers_tip", ""),
+        ("Sync", ""),
+        ("sync_tip", ""),
+        ("Delete the extra files at the destination", ""),
+        ("Dry run", ""),
+        ("No changes", ""),
+        ("Confirm Overwrite", ""),
+        ("overwrite_tip", ""),
+        ("If files exist", ""),
+        ("Overwrite", ""),
+        ("Skip if identical", ""),
+        ("Ask", ""),
+        ("Audio statistics", ""),
+        ("Jitter buffer", ""),
+        ("Output latency", ""),
+        ("Audio/video drift", ""),
+        ("Dropped", ""),
+        ("audio_source_tip", ""),
     ].iter().cloned().collect();
 }
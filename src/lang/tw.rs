@@ -265,5 +265,83 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("android_version_audio_tip", "當前安卓版本不支持音頻錄製，請升級至安卓10或更高。"),
         ("android_start_service_tip", "點擊 [啟動服務] 或打開 [屏幕錄製] 權限開啟手機屏幕共享服務。"),
         ("Account", "帳戶"),
+        ("Standard audio", "標準音訊"),
+        ("Voice", "語音"),
+        ("Music", "音樂"),
+        ("Audio off", "關閉音訊"),
+        ("Audio source", "音訊來源"),
+        ("Application", "應用程式"),
+        ("Default", "預設"),
+        ("Relative mouse mode", "相對滑鼠模式"),
+        ("Physical keyboard", "實體鍵盤"),
+        ("Type clipboard", "鍵入剪貼簿內容"),
+        ("Stop typing", "停止鍵入"),
+        ("Delay between keystrokes", "按鍵間隔"),
+        ("No text in the clipboard", "剪貼簿中沒有文字"),
+        ("Record macro", "錄製巨集"),
+        ("Stop recording", "停止錄製"),
+        ("Macros", "巨集"),
+        ("Edit macro", "編輯巨集"),
+        ("New macro", "新增巨集"),
+        ("Macro", "巨集"),
+        ("Nothing was recorded", "沒有錄製任何內容"),
+        ("Has control", "正在控制"),
+        ("Requesting control", "請求控制中"),
+        ("View only", "僅檢視"),
+        ("Give control", "授予控制"),
+        ("Controlled by", "控制者"),
+        ("Request control", "請求控制"),
+        ("Withdraw control request", "撤回控制請求"),
+        ("Release control", "釋放控制"),
+        ("Control request", "控制請求"),
+        ("requests the control", "請求控制"),
+        ("Key mapping", "鍵位映射"),
+        ("Edit key mapping", "編輯鍵位映射"),
+        ("Preset", "預設"),
+        ("Import", "匯入"),
+        ("Export", "匯出"),
+        ("One rule per line, e.g.", "每行一條規則，例如"),
+        ("Caps Lock", "大寫鎖定"),
+        ("Num Lock", "數字鎖定"),
+        ("Scroll Lock", "捲動鎖定"),
+        ("Local input method", "使用本地輸入法"),
+        ("Forward gamepads", "轉發遊戲手把"),
+        ("Clipboard Policy", "剪貼簿策略"),
+        ("Direction", "方向"),
+        ("Both", "雙向"),
+        ("To the client only", "僅傳送到控制端"),
+        ("From the client only", "僅從控制端接收"),
+        ("Maximum size", "最大大小"),
+        ("Formats", "格式"),
+        ("Text", "文字"),
+        ("Image", "圖片"),
+        ("Deny-list", "拒絕規則"),
+        ("Invalid size", "無效大小"),
+        ("Select at least one format", "請至少選擇一種格式"),
+        ("Sync primary selection", "同步主選取區"),
+        ("Transfer limit", "傳輸上限"),
+        ("Receiving clipboard", "正在接收剪貼簿"),
+        ("Sending clipboard", "正在傳送剪貼簿"),
+        ("Resume transfers", "繼續傳輸"),
+        ("resume_transfers_tip", "以下傳輸上次被中斷，是否繼續？取消將不再保留。"),
+        ("Retry transfers", "重試傳輸"),
+        ("retry_transfers_tip", "部分檔案傳輸失敗或與來源檔案不一致，是否重新傳輸？取消將不再保留。"),
+        ("Sync", "同步"),
+        ("sync_tip", "僅傳輸目標中新增或已修改（按大小和修改時間）的檔案。"),
+        ("Delete the extra files at the destination", "刪除目標中多餘的檔案"),
+        ("Dry run", "僅列出變更"),
+        ("No changes", "沒有變更"),
+        ("Confirm Overwrite", "確認覆蓋"),
+        ("overwrite_tip", "目標位置已存在此檔案，是否覆蓋？"),
+        ("If files exist", "檔案已存在時"),
+        ("Overwrite", "覆蓋"),
+        ("Skip if identical", "相同時跳過"),
+        ("Ask", "詢問"),
+        ("Audio statistics", "音訊統計"),
+        ("Jitter buffer", "抖動緩衝"),
+        ("Output latency", "輸出延遲"),
+        ("Audio/video drift", "影音偏差"),
+        ("Dropped", "丟棄"),
+        ("audio_source_tip", "音訊由所有連線共享，已保留另一位訪問者選擇的音訊來源。"),
     ].iter().cloned().collect();
 }
@@ -28,6 +28,12 @@ pub mod cli;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 mod port_forward;
 mod lang;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod hid;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod keymap;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod gamepad;
 
-#[cfg(windows)]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 pub mod clipboard_file;
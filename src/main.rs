@@ -106,7 +106,13 @@ fn main() {
     use clap::App;
     let args = format!(
         "-p, --port-forward=[PORT-FORWARD-OPTIONS] 'Format: remote-id:local-port:remote-port[:remote-host]'
-       -s, --server... 'Start server'",
+       -s, --server... 'Start server'
+       --sync=[REMOTE-ID] 'Sync the folder of --local to the one of --remote, or the reverse with --pull'
+       --local=[LOCAL-PATH] 'The local folder to sync'
+       --remote=[REMOTE-PATH] 'The remote folder to sync'
+       --pull 'Sync the remote folder to the local one'
+       --delete 'Delete the extra files at the destination'
+       --dry-run 'List the changes only'",
     );
     let matches = App::new("rustdesk")
         .version(crate::VERSION)
@@ -142,4 +148,21 @@ fn main() {
         }
         cli::start_one_port_forward(options[0].clone(), port, remote_host, remote_port);
     }
+    if let Some(id) = matches.value_of("sync") {
+        let (local, remote) = match (matches.value_of("local"), matches.value_of("remote")) {
+            (Some(local), Some(remote)) => (local.to_owned(), remote.to_owned()),
+            _ => {
+                log::error!("Both --local and --remote are required to sync");
+                return;
+            }
+        };
+        cli::start_sync(
+            id.to_owned(),
+            local,
+            remote,
+            matches.is_present("pull"),
+            matches.is_present("delete"),
+            matches.is_present("dry-run"),
+        );
+    }
 }
@@ -1,6 +1,6 @@
 use super::{CursorData, ResultType};
 use hbb_common::{allow_err, bail, log};
-use libc::{c_char, c_int, c_void};
+use libc::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_void};
 use std::io::prelude::*;
 use std::{
     cell::RefCell,
@@ -17,6 +17,7 @@ static mut UNMODIFIED: bool = true;
 thread_local! {
     static XDO: RefCell<Xdo> = RefCell::new(unsafe { xdo_new(std::ptr::null()) });
     static DISPLAY: RefCell<*mut c_void> = RefCell::new(unsafe { XOpenDisplay(std::ptr::null())});
+    static CLIPBOARD_HTML: RefCell<Option<ClipboardHtml>> = RefCell::new(None);
 }
 
 extern "C" {
@@ -33,11 +34,64 @@ extern "C" {
 extern "C" {
     fn XOpenDisplay(display_name: *const c_char) -> *mut c_void;
     // fn XCloseDisplay(d: *mut c_void) -> c_int;
+    fn XDefaultRootWindow(display: *mut c_void) -> c_ulong;
+    fn XCreateSimpleWindow(
+        display: *mut c_void,
+        parent: c_ulong,
+        x: c_int,
+        y: c_int,
+        width: c_uint,
+        height: c_uint,
+        border_width: c_uint,
+        border: c_ulong,
+        background: c_ulong,
+    ) -> c_ulong;
+    fn XInternAtom(display: *mut c_void, name: *const c_char, only_if_exists: c_int) -> c_ulong;
+    fn XConvertSelection(
+        display: *mut c_void,
+        selection: c_ulong,
+        target: c_ulong,
+        property: c_ulong,
+        requestor: c_ulong,
+        time: c_ulong,
+    ) -> c_int;
+    fn XFlush(display: *mut c_void) -> c_int;
+    fn XCheckTypedWindowEvent(
+        display: *mut c_void,
+        w: c_ulong,
+        event_type: c_int,
+        event: *mut c_long,
+    ) -> c_int;
+    fn XGetWindowProperty(
+        display: *mut c_void,
+        w: c_ulong,
+        property: c_ulong,
+        long_offset: c_long,
+        long_length: c_long,
+        delete: c_int,
+        req_type: c_ulong,
+        actual_type: *mut c_ulong,
+        actual_format: *mut c_int,
+        nitems: *mut c_ulong,
+        bytes_after: *mut c_ulong,
+        prop: *mut *mut c_uchar,
+    ) -> c_int;
 }
 
+const SELECTION_NOTIFY: c_int = 31;
+// XFixesSetSelectionOwnerNotifyMask | XFixesSelectionWindowDestroyNotifyMask
+// | XFixesSelectionClientCloseNotifyMask
+const SELECTION_OWNER_MASKS: c_ulong = 0b111;
+
 #[link(name = "Xfixes")]
 extern "C" {
-    // fn XFixesQueryExtension(dpy: *mut c_void, event: *mut c_int, error: *mut c_int) -> c_int;
+    fn XFixesQueryExtension(dpy: *mut c_void, event: *mut c_int, error: *mut c_int) -> c_int;
+    fn XFixesSelectSelectionInput(
+        dpy: *mut c_void,
+        window: c_ulong,
+        selection: c_ulong,
+        event_mask: c_ulong,
+    );
     fn XFixesGetCursorImage(dpy: *mut c_void) -> *const xcb_xfixes_get_cursor_image;
     fn XFree(data: *mut c_void);
 }
@@ -93,6 +147,145 @@ pub fn get_cursor() -> ResultType<Option<u64>> {
     Ok(res)
 }
 
+// the window the selection is converted to, kept for the life of the thread
+struct ClipboardHtml {
+    window: c_ulong,
+    notify_event: c_int,
+    owner: Option<(c_ulong, c_ulong)>, // (owner, timestamp) of the selection
+    stale: bool,
+    html: Option<String>,
+}
+
+impl ClipboardHtml {
+    unsafe fn new(d: *mut c_void) -> Option<Self> {
+        let mut event_base = 0;
+        let mut error_base = 0;
+        if XFixesQueryExtension(d, &mut event_base, &mut error_base) == 0 {
+            return None;
+        }
+        let window = XCreateSimpleWindow(d, XDefaultRootWindow(d), 0, 0, 1, 1, 0, 0, 0);
+        let selection = XInternAtom(d, b"CLIPBOARD\0".as_ptr() as _, 0);
+        XFixesSelectSelectionInput(d, window, selection, SELECTION_OWNER_MASKS);
+        Some(Self {
+            window,
+            // XFixesSelectionNotify
+            notify_event: event_base,
+            owner: None,
+            stale: true,
+            html: None,
+        })
+    }
+
+    unsafe fn update_owner(&mut self, d: *mut c_void) {
+        // XFixesSelectionNotifyEvent, owner and selection_timestamp as longs 6 and 9
+        let mut event = [0 as c_long; 24];
+        while XCheckTypedWindowEvent(d, self.window, self.notify_event, event.as_mut_ptr()) != 0 {
+            let owner = Some((event[6] as c_ulong, event[9] as c_ulong));
+            if owner != self.owner {
+                self.owner = owner;
+                self.stale = true;
+            }
+        }
+    }
+}
+
+// arboard can only write the html, the large ones sent in increments are skipped,
+// read again only after the owner or the timestamp of the selection changed
+pub fn get_clipboard_html() -> Option<String> {
+    let mut res = None;
+    DISPLAY.with(|conn| {
+        if let Ok(d) = conn.try_borrow_mut() {
+            if d.is_null() {
+                return;
+            }
+            CLIPBOARD_HTML.with(|html| {
+                let mut html = html.borrow_mut();
+                unsafe {
+                    if html.is_none() {
+                        *html = ClipboardHtml::new(*d);
+                    }
+                    if let Some(html) = html.as_mut() {
+                        html.update_owner(*d);
+                        if html.stale {
+                            html.stale = false;
+                            html.html =
+                                read_selection(*d, html.window, b"CLIPBOARD\0", b"text/html\0");
+                        }
+                        res = html.html.clone();
+                    }
+                }
+            });
+        }
+    });
+    res
+}
+
+unsafe fn read_selection(
+    d: *mut c_void,
+    window: c_ulong,
+    selection: &[u8],
+    target: &[u8],
+) -> Option<String> {
+    let selection = XInternAtom(d, selection.as_ptr() as _, 0);
+    let target = XInternAtom(d, target.as_ptr() as _, 0);
+    let property = XInternAtom(d, b"RUSTDESK_SELECTION\0".as_ptr() as _, 0);
+    // XEvent is a union of 24 longs
+    let mut event = [0 as c_long; 24];
+    // the late reply of a conversion timed out before
+    while XCheckTypedWindowEvent(d, window, SELECTION_NOTIFY, event.as_mut_ptr()) != 0 {}
+    XConvertSelection(d, selection, target, property, window, 0);
+    XFlush(d);
+    let start = std::time::Instant::now();
+    while XCheckTypedWindowEvent(d, window, SELECTION_NOTIFY, event.as_mut_ptr()) == 0 {
+        if start.elapsed() > std::time::Duration::from_millis(100) {
+            return None;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    // XSelectionEvent.property, None if the owner has no such target
+    if event[7] == 0 {
+        return None;
+    }
+    let mut actual_type = 0;
+    let mut format = 0;
+    let mut nitems = 0;
+    let mut bytes_after = 0;
+    let mut data = std::ptr::null_mut();
+    if XGetWindowProperty(
+        d,
+        window,
+        property,
+        0,
+        c_long::MAX / 4,
+        1,
+        0,
+        &mut actual_type,
+        &mut format,
+        &mut nitems,
+        &mut bytes_after,
+        &mut data,
+    ) != 0
+        || data.is_null()
+    {
+        return None;
+    }
+    let bytes = std::slice::from_raw_parts(data, nitems as _);
+    let res = if format != 8 || actual_type != target {
+        None
+    } else if bytes.starts_with(&[0xff, 0xfe]) {
+        // firefox gives utf-16
+        let utf16: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        Some(String::from_utf16_lossy(&utf16))
+    } else {
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    };
+    XFree(data as _);
+    res
+}
+
 pub fn get_cursor_data(hcursor: u64) -> ResultType<CursorData> {
     let mut res = None;
     DISPLAY.with(|conn| {
@@ -584,6 +777,122 @@ pub fn get_pa_sources() -> Vec<(String, String)> {
     out
 }
 
+pub fn get_pa_sinks() -> Vec<(String, String)> {
+    use pulsectl::controllers::*;
+    let mut out = Vec::new();
+    match SinkController::create() {
+        Ok(mut handler) => {
+            if let Ok(devices) = handler.list_devices() {
+                for dev in devices.clone() {
+                    let name = dev.name.unwrap_or("".to_owned());
+                    if name.starts_with(PA_CAPTURE_SINK) {
+                        continue;
+                    }
+                    out.push((name, dev.description.unwrap_or("".to_owned())));
+                }
+            }
+        }
+        Err(err) => {
+            log::error!("Failed to get_pa_sinks: {:?}", err);
+        }
+    }
+    out
+}
+
+// (sink input index, application name, sink index)
+pub fn get_pa_apps() -> Vec<(String, String, String)> {
+    use pulsectl::controllers::*;
+    let mut out = Vec::new();
+    match SinkController::create() {
+        Ok(mut handler) => {
+            if let Ok(apps) = handler.list_applications() {
+                for app in apps.clone() {
+                    // skip loopbacks and other streams created by modules
+                    if app.owner_module.is_some() {
+                        continue;
+                    }
+                    let name = app
+                        .proplist
+                        .get_str("application.name")
+                        .or(app.name)
+                        .unwrap_or("".to_owned());
+                    out.push((app.index.to_string(), name, app.connection_id.to_string()));
+                }
+            }
+        }
+        Err(err) => {
+            log::error!("Failed to get_pa_apps: {:?}", err);
+        }
+    }
+    out
+}
+
+// prefix of the null sinks, one per captured application stream
+const PA_CAPTURE_SINK: &str = "rustdesk_capture";
+
+// The application stream is moved to a null sink which we record from, and looped back
+// to its original sink so that it is still heard on the host. Undone on drop.
+pub struct PaAppCapture {
+    index: String,
+    sink: String,
+    capture_sink: String,
+    modules: Vec<String>,
+}
+
+impl PaAppCapture {
+    pub fn new(index: &str) -> ResultType<Self> {
+        let sink = match get_pa_apps().drain(..).find(|x| x.0 == index) {
+            Some(app) => app.2,
+            None => bail!("No such application stream"),
+        };
+        let mut capture = Self {
+            index: index.to_owned(),
+            sink,
+            capture_sink: format!("{}_{}_{}", PA_CAPTURE_SINK, std::process::id(), index),
+            modules: Vec::new(),
+        };
+        capture.modules.push(run_pactl(&[
+            "load-module",
+            "module-null-sink",
+            &format!("sink_name={}", capture.capture_sink),
+        ])?);
+        capture.modules.push(run_pactl(&[
+            "load-module",
+            "module-loopback",
+            &format!("source={}.monitor", capture.capture_sink),
+            &format!("sink={}", capture.sink),
+        ])?);
+        run_pactl(&["move-sink-input", index, &capture.capture_sink])?;
+        Ok(capture)
+    }
+
+    pub fn monitor(&self) -> String {
+        format!("{}.monitor", self.capture_sink)
+    }
+}
+
+impl Drop for PaAppCapture {
+    fn drop(&mut self) {
+        // the application may have gone already
+        run_pactl(&["move-sink-input", &self.index, &self.sink]).ok();
+        for m in self.modules.iter().rev() {
+            allow_err!(run_pactl(&["unload-module", m]));
+        }
+    }
+}
+
+fn run_pactl(args: &[&str]) -> ResultType<String> {
+    let output = std::process::Command::new("pactl").args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "pactl {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
 pub fn lock_screen() {
     std::process::Command::new("xdg-screensaver").arg("lock").spawn().ok();
 }
@@ -430,3 +430,26 @@ pub fn is_installed() -> bool {
     }
     false
 }
+
+// arboard can only write the html
+pub fn get_clipboard_html() -> Option<String> {
+    use cocoa::{
+        appkit::{NSPasteboard, NSPasteboardTypeHTML},
+        foundation::NSAutoreleasePool,
+    };
+    unsafe {
+        let pool = NSAutoreleasePool::new(nil);
+        let html = NSPasteboard::generalPasteboard(nil).stringForType(NSPasteboardTypeHTML);
+        let res = if html == nil {
+            None
+        } else {
+            Some(
+                std::ffi::CStr::from_ptr(html.UTF8String())
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        };
+        pool.drain();
+        res
+    }
+}
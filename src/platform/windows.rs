@@ -55,6 +55,26 @@ pub fn get_cursor() -> ResultType<Option<u64>> {
     }
 }
 
+// (hidden, confined), games and 3D tools hide the cursor and clip it to their window
+pub fn get_pointer_state() -> (bool, bool) {
+    unsafe {
+        let mut ci: CURSORINFO = mem::zeroed();
+        ci.cbSize = std::mem::size_of::<CURSORINFO>() as _;
+        let hidden = GetCursorInfo(&mut ci) != FALSE && ci.flags & CURSOR_SHOWING == 0;
+        let mut rc: winapi::shared::windef::RECT = mem::zeroed();
+        let confined = if GetClipCursor(&mut rc) != FALSE {
+            let x = GetSystemMetrics(SM_XVIRTUALSCREEN);
+            let y = GetSystemMetrics(SM_YVIRTUALSCREEN);
+            let w = GetSystemMetrics(SM_CXVIRTUALSCREEN);
+            let h = GetSystemMetrics(SM_CYVIRTUALSCREEN);
+            rc.left > x || rc.top > y || rc.right < x + w || rc.bottom < y + h
+        } else {
+            false
+        };
+        (hidden, confined)
+    }
+}
+
 struct IconInfo(ICONINFO);
 
 impl IconInfo {
@@ -1096,3 +1116,49 @@ oLink.Save
     allow_err!(std::fs::remove_file(shortcut));
     Ok(())
 }
+
+// the fragment of the "HTML Format", arboard can only write it
+pub fn get_clipboard_html() -> Option<String> {
+    use std::os::windows::ffi::OsStrExt;
+    let name: Vec<u16> = std::ffi::OsStr::new("HTML Format")
+        .encode_wide()
+        .chain(Some(0).into_iter())
+        .collect();
+    unsafe {
+        let format = RegisterClipboardFormatW(name.as_ptr());
+        if format == 0 || IsClipboardFormatAvailable(format) == FALSE {
+            return None;
+        }
+        if OpenClipboard(NULL as _) == FALSE {
+            return None;
+        }
+        let mut res = None;
+        let handle = GetClipboardData(format);
+        if !handle.is_null() {
+            let data = GlobalLock(handle) as *const u8;
+            if !data.is_null() {
+                res = get_html_fragment(std::slice::from_raw_parts(data, GlobalSize(handle)));
+                GlobalUnlock(handle);
+            }
+        }
+        CloseClipboard();
+        res
+    }
+}
+
+// the header gives the byte offsets, like "StartFragment:0000000123"
+fn get_html_fragment(data: &[u8]) -> Option<String> {
+    let header = String::from_utf8_lossy(&data[..data.len().min(1024)]);
+    let offset = |name: &str| -> Option<usize> {
+        header
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|v| v.trim().parse().ok())
+    };
+    let start = offset("StartFragment:")?;
+    let end = offset("EndFragment:")?;
+    if start > end || end > data.len() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&data[start..end]).into_owned())
+}
@@ -41,6 +41,14 @@ type ConnMap = HashMap<i32, ConnInner>;
 
 lazy_static::lazy_static! {
     pub static ref CHILD_PROCESS: Childs = Default::default();
+    static ref START: std::time::Instant = std::time::Instant::now();
+}
+
+// ms since the server started, audio and video frames are stamped with it
+// so that the client can synchronise them
+#[inline]
+pub fn get_pts(tm: std::time::Instant) -> i64 {
+    tm.saturating_duration_since(*START).as_millis() as _
 }
 
 pub struct Server {
@@ -61,6 +69,8 @@ pub fn new() -> ServerPtr {
     server.add_service(Box::new(audio_service::new()));
     server.add_service(Box::new(video_service::new()));
     server.add_service(Box::new(clipboard_service::new()));
+    #[cfg(target_os = "linux")]
+    server.add_service(Box::new(clipboard_service::new_primary()));
     server.add_service(Box::new(input_service::new_cursor()));
     server.add_service(Box::new(input_service::new_pos()));
     Arc::new(RwLock::new(server))
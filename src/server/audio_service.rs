@@ -13,13 +13,24 @@
 // https://github.com/krruzic/pulsectl
 
 use super::*;
-use magnum_opus::{Application::*, Channels::*, Encoder};
+use magnum_opus::{Application, Application::*, Bitrate, Channels::*, Encoder};
 use std::sync::atomic::{AtomicBool, Ordering};
 
 pub const NAME: &'static str = "audio";
 pub const AUDIO_DATA_SIZE_U8: usize = 960 * 4; // 10ms in 48000 stereo
 static RESTARTING: AtomicBool = AtomicBool::new(false);
 
+lazy_static::lazy_static! {
+    static ref AUDIO_PROFILES: Arc<Mutex<HashMap<i32, i32>>> = Default::default();
+}
+
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    // the audio is captured once for all the connections, so the source chosen first
+    // is kept until its connection goes back to the host setting or closes
+    static ref AUDIO_SOURCE: Arc<Mutex<Option<(i32, AudioSource)>>> = Default::default();
+}
+
 #[cfg(not(target_os = "linux"))]
 pub fn new() -> GenericService {
     let sp = GenericService::new(NAME, true);
@@ -42,6 +53,148 @@ pub fn restart() {
     RESTARTING.store(true, Ordering::SeqCst);
 }
 
+pub fn update_audio_profile(id: i32, profile: Option<AudioProfile>) {
+    match profile {
+        Some(p) if p == AudioProfile::Voice || p == AudioProfile::Music => {
+            AUDIO_PROFILES.lock().unwrap().insert(id, p.value());
+        }
+        _ => {
+            AUDIO_PROFILES.lock().unwrap().remove(&id);
+        }
+    }
+}
+
+// the source of another connection is returned if kept instead
+#[cfg(target_os = "linux")]
+pub fn update_audio_source(id: i32, source: Option<AudioSource>) -> Option<AudioSource> {
+    let mut lock = AUDIO_SOURCE.lock().unwrap();
+    match source.filter(|x| !x.name.is_empty()) {
+        Some(source) => {
+            if let Some((owner, current)) = lock.as_ref() {
+                if *owner != id
+                    && (current.source_type != source.source_type || current.name != source.name)
+                {
+                    return Some(current.clone());
+                }
+            }
+            *lock = Some((id, source));
+        }
+        None => {
+            if lock.as_ref().map(|x| x.0) == Some(id) {
+                *lock = None;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn get_audio_source() -> String {
+    match AUDIO_SOURCE.lock().unwrap().as_ref() {
+        Some((_, source)) => {
+            let prefix = match source.source_type.enum_value_or_default() {
+                audio_source::SourceType::Sink => "sink",
+                audio_source::SourceType::Application => "app",
+            };
+            format!("{}:{}", prefix, source.name)
+        }
+        None => "".to_owned(),
+    }
+}
+
+// the encoder is shared by all connections, so music wins over voice,
+// nobody should get a worse stream than it asked for
+fn get_audio_profile() -> AudioProfile {
+    let lock = AUDIO_PROFILES.lock().unwrap();
+    if lock.values().any(|p| *p == AudioProfile::Music.value()) {
+        AudioProfile::Music
+    } else if lock.values().any(|p| *p == AudioProfile::Voice.value()) {
+        AudioProfile::Voice
+    } else {
+        AudioProfile::Standard
+    }
+}
+
+pub struct AudioEncoder {
+    encoder: Encoder,
+    profile: AudioProfile,
+    sample_rate0: u32,
+    sample_rate: u32,
+    channels0: u16,
+    channels: u16,
+    bitrate: i32,
+    dtx: bool,
+}
+
+impl AudioEncoder {
+    // sample_rate0 and channels0 describe the captured data,
+    // sample_rate is the opus rate used when the profile does not force one
+    fn new(
+        sample_rate0: u32,
+        sample_rate: u32,
+        channels0: u16,
+        profile: AudioProfile,
+    ) -> ResultType<Self> {
+        let (sample_rate, channels, application, bitrate, dtx): (_, _, Application, _, _) =
+            match profile {
+                AudioProfile::Voice => (VOICE_SAMPLE_RATE, 1, Voip, VOICE_BITRATE, true),
+                AudioProfile::Music => (sample_rate, channels0.min(2), Audio, MUSIC_BITRATE, false),
+                _ => (sample_rate, channels0.min(2), LowDelay, 0, false),
+            };
+        let mut encoder = Encoder::new(
+            sample_rate,
+            if channels > 1 { Stereo } else { Mono },
+            application,
+        )?;
+        if bitrate > 0 {
+            encoder.set_bitrate(Bitrate::Bits(bitrate))?;
+        }
+        log::info!(
+            "Audio encoder: {:?}, {} -> {} Hz, {} -> {} channel(s), bitrate: {}",
+            profile,
+            sample_rate0,
+            sample_rate,
+            channels0,
+            channels,
+            bitrate
+        );
+        Ok(Self {
+            encoder,
+            profile,
+            sample_rate0,
+            sample_rate,
+            channels0,
+            channels,
+            bitrate,
+            dtx,
+        })
+    }
+
+    fn format_msg(&self) -> Message {
+        create_format_msg(self.sample_rate, self.channels, self.profile, self.bitrate)
+    }
+
+    // worst case of one packet at the configured bitrate with 2x headroom for vbr peaks,
+    // bounded by the size recommended by libopus
+    fn max_packet_size(&self, len: usize) -> usize {
+        let bitrate = if self.bitrate > 0 {
+            self.bitrate
+        } else {
+            MAX_OPUS_BITRATE
+        };
+        let samples = len / self.channels as usize;
+        let n = bitrate as usize / 8 * samples / self.sample_rate as usize;
+        (n * 2).max(MIN_PACKET_SIZE).min(MAX_PACKET_SIZE)
+    }
+}
+
+const VOICE_SAMPLE_RATE: u32 = 16000;
+const VOICE_BITRATE: i32 = 24_000;
+const MUSIC_BITRATE: i32 = 128_000;
+const MAX_OPUS_BITRATE: i32 = 510_000;
+const MIN_PACKET_SIZE: usize = 256;
+const MAX_PACKET_SIZE: usize = 4000;
+
 #[cfg(target_os = "linux")]
 mod pa_impl {
     use super::*;
@@ -53,7 +206,14 @@ mod pa_impl {
         unsafe {
             AUDIO_ZERO_COUNT = 0;
         }
-        let mut encoder = Encoder::new(crate::platform::linux::PA_SAMPLE_RATE, Stereo, LowDelay)?;
+        let profile = get_audio_profile();
+        let mut encoder = AudioEncoder::new(
+            crate::platform::linux::PA_SAMPLE_RATE,
+            crate::platform::linux::PA_SAMPLE_RATE,
+            2,
+            profile,
+        )?;
+        sp.send(encoder.format_msg());
         allow_err!(
             stream
                 .send(&crate::ipc::Data::Config((
@@ -62,10 +222,27 @@ mod pa_impl {
                 )))
                 .await
         );
+        let mut source = "".to_owned();
         let zero_audio_frame: Vec<f32> = vec![0.; AUDIO_DATA_SIZE_U8 / 4];
         while sp.ok() && !RESTARTING.load(Ordering::SeqCst) {
+            if get_audio_profile() != profile {
+                log::info!("audio profile changed, recreating encoder");
+                break;
+            }
+            let tmp = get_audio_source();
+            if tmp != source {
+                source = tmp;
+                allow_err!(
+                    stream
+                        .send(&crate::ipc::Data::Config((
+                            "audio-source".to_owned(),
+                            Some(source.clone())
+                        )))
+                        .await
+                );
+            }
             sp.snapshot(|sps| {
-                sps.send(create_format_msg(crate::platform::linux::PA_SAMPLE_RATE, 2));
+                sps.send(encoder.format_msg());
                 Ok(())
             })?;
             if let Ok(data) = stream.next_raw().await {
@@ -101,6 +278,7 @@ mod cpal_impl {
     #[derive(Default)]
     pub struct State {
         stream: Option<(Box<dyn StreamTrait>, Arc<Message>)>,
+        profile: AudioProfile,
     }
 
     impl super::service::Reset for State {
@@ -110,10 +288,21 @@ mod cpal_impl {
     }
 
     pub fn run(sp: GenericService, state: &mut State) -> ResultType<()> {
+        let profile = get_audio_profile();
+        if state.stream.is_some() && state.profile != profile {
+            log::info!("audio profile changed, recreating encoder");
+            state.stream.take();
+            state.stream = Some(play(&sp, profile)?);
+            state.profile = profile;
+            if let Some((_, format)) = &state.stream {
+                sp.send_shared(format.clone());
+            }
+        }
         sp.snapshot(|sps| {
             match &state.stream {
                 None => {
-                    state.stream = Some(play(&sp)?);
+                    state.stream = Some(play(&sp, profile)?);
+                    state.profile = profile;
                 }
                 _ => {}
             }
@@ -125,17 +314,10 @@ mod cpal_impl {
         Ok(())
     }
 
-    fn send(
-        data: &[f32],
-        sample_rate0: u32,
-        sample_rate: u32,
-        channels: u16,
-        encoder: &mut Encoder,
-        sp: &GenericService,
-    ) {
+    fn send(data: &[f32], encoder: &mut AudioEncoder, sp: &GenericService) {
         let buffer;
-        let data = if sample_rate0 != sample_rate {
-            buffer = crate::common::resample_channels(data, sample_rate0, sample_rate, channels);
+        let data = if encoder.channels0 > 2 {
+            buffer = remix_channels(data, encoder.channels0, 2);
             &buffer
         } else {
             data
@@ -199,7 +381,10 @@ mod cpal_impl {
         Ok((device, format))
     }
 
-    fn play(sp: &GenericService) -> ResultType<(Box<dyn StreamTrait>, Arc<Message>)> {
+    fn play(
+        sp: &GenericService,
+        profile: AudioProfile,
+    ) -> ResultType<(Box<dyn StreamTrait>, Arc<Message>)> {
         let (device, config) = get_device()?;
         let sp = sp.clone();
         let err_fn = move |err| {
@@ -223,24 +408,14 @@ mod cpal_impl {
         unsafe {
             AUDIO_ZERO_COUNT = 0;
         }
-        let mut encoder = Encoder::new(
-            sample_rate,
-            if config.channels() > 1 { Stereo } else { Mono },
-            LowDelay,
-        )?;
-        let channels = config.channels();
+        let mut encoder =
+            AudioEncoder::new(sample_rate_0, sample_rate, config.channels(), profile)?;
+        let format = Arc::new(encoder.format_msg());
         let stream = match config.sample_format() {
             cpal::SampleFormat::F32 => device.build_input_stream(
                 &config.into(),
                 move |data, _: &_| {
-                    send(
-                        data,
-                        sample_rate_0,
-                        sample_rate,
-                        channels,
-                        &mut encoder,
-                        &sp,
-                    );
+                    send(data, &mut encoder, &sp);
                 },
                 err_fn,
             )?,
@@ -248,14 +423,7 @@ mod cpal_impl {
                 &config.into(),
                 move |data: &[i16], _: &_| {
                     let buffer: Vec<_> = data.iter().map(|s| cpal::Sample::to_f32(s)).collect();
-                    send(
-                        &buffer,
-                        sample_rate_0,
-                        sample_rate,
-                        channels,
-                        &mut encoder,
-                        &sp,
-                    );
+                    send(&buffer, &mut encoder, &sp);
                 },
                 err_fn,
             )?,
@@ -263,30 +431,27 @@ mod cpal_impl {
                 &config.into(),
                 move |data: &[u16], _: &_| {
                     let buffer: Vec<_> = data.iter().map(|s| cpal::Sample::to_f32(s)).collect();
-                    send(
-                        &buffer,
-                        sample_rate_0,
-                        sample_rate,
-                        channels,
-                        &mut encoder,
-                        &sp,
-                    );
+                    send(&buffer, &mut encoder, &sp);
                 },
                 err_fn,
             )?,
         };
         stream.play()?;
-        Ok((
-            Box::new(stream),
-            Arc::new(create_format_msg(sample_rate, channels)),
-        ))
+        Ok((Box::new(stream), format))
     }
 }
 
-fn create_format_msg(sample_rate: u32, channels: u16) -> Message {
+fn create_format_msg(
+    sample_rate: u32,
+    channels: u16,
+    profile: AudioProfile,
+    bitrate: i32,
+) -> Message {
     let format = AudioFormat {
         sample_rate,
         channels: channels as _,
+        profile: profile.into(),
+        bitrate,
         ..Default::default()
     };
     let mut misc = Misc::new();
@@ -300,17 +465,25 @@ fn create_format_msg(sample_rate: u32, channels: u16) -> Message {
 // every audio data length is set to 480
 // MAX_AUDIO_ZERO_COUNT=800 is similar as Gate Attack Time 3~5s(Linux) || 6~8s(Windows)
 const MAX_AUDIO_ZERO_COUNT: u16 = 800;
+// magnum-opus does not expose OPUS_SET_DTX, so the voice profile gets the same effect
+// by closing the gate after ~200ms of silence, which is the opus dtx hangover
+const MAX_AUDIO_ZERO_COUNT_DTX: u16 = 20;
 static mut AUDIO_ZERO_COUNT: u16 = 0;
 
-fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
+fn send_f32(data: &[f32], encoder: &mut AudioEncoder, sp: &GenericService) {
+    let max_zero_count = if encoder.dtx {
+        MAX_AUDIO_ZERO_COUNT_DTX
+    } else {
+        MAX_AUDIO_ZERO_COUNT
+    };
     if data.iter().filter(|x| **x != 0.).next().is_some() {
         unsafe {
             AUDIO_ZERO_COUNT = 0;
         }
     } else {
         unsafe {
-            if AUDIO_ZERO_COUNT > MAX_AUDIO_ZERO_COUNT {
-                if AUDIO_ZERO_COUNT == MAX_AUDIO_ZERO_COUNT + 1 {
+            if AUDIO_ZERO_COUNT > max_zero_count {
+                if AUDIO_ZERO_COUNT == max_zero_count + 1 {
                     log::debug!("Audio Zero Gate Attack");
                     AUDIO_ZERO_COUNT += 1;
                 }
@@ -319,11 +492,36 @@ fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
             AUDIO_ZERO_COUNT += 1;
         }
     }
-    match encoder.encode_vec_float(data, data.len() * 6) {
+    let channels0 = encoder.channels0.min(2);
+    let buffer;
+    let data = if encoder.channels < channels0 {
+        buffer = remix_channels(data, channels0, encoder.channels);
+        &buffer
+    } else {
+        data
+    };
+    let buffer;
+    let data = if encoder.sample_rate0 != encoder.sample_rate {
+        buffer = crate::common::resample_channels(
+            data,
+            encoder.sample_rate0,
+            encoder.sample_rate,
+            encoder.channels,
+        );
+        &buffer
+    } else {
+        data
+    };
+    // the data ends now, so it started one frame earlier
+    let ms = data.len() as i64 * 1000 / (encoder.channels as i64 * encoder.sample_rate as i64);
+    let timestamp = get_pts(std::time::Instant::now()) - ms;
+    let max_size = encoder.max_packet_size(data.len());
+    match encoder.encoder.encode_vec_float(data, max_size) {
         Ok(data) => {
             let mut msg_out = Message::new();
             msg_out.set_audio_frame(AudioFrame {
                 data,
+                timestamp,
                 ..Default::default()
             });
             sp.send(msg_out);
@@ -332,8 +530,39 @@ fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
     }
 }
 
+// downmix by averaging, or duplicate the first channel when upmixing
+fn remix_channels(data: &[f32], channels0: u16, channels: u16) -> Vec<f32> {
+    let channels0 = channels0 as usize;
+    let channels = channels as usize;
+    let mut out = Vec::with_capacity(data.len() / channels0 * channels);
+    for frame in data.chunks_exact(channels0) {
+        if channels < channels0 {
+            for i in 0..channels {
+                let n = channels0 / channels;
+                let sum: f32 = frame[i * n..(i + 1) * n].iter().sum();
+                out.push(sum / n as f32);
+            }
+        } else {
+            for i in 0..channels {
+                out.push(frame[i.min(channels0 - 1)]);
+            }
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn test_remix_channels() {
+        let stereo = [0.2, 0.4, -1., 1.];
+        assert_eq!(super::remix_channels(&stereo, 2, 1), vec![0.3, 0.]);
+        assert_eq!(
+            super::remix_channels(&[0.5, -0.5], 1, 2),
+            vec![0.5, 0.5, -0.5, -0.5]
+        );
+    }
+
     #[cfg(target_os = "linux")]
     #[test]
     fn test_pulse() {
@@ -1,10 +1,10 @@
 use super::*;
 pub use crate::common::{
-    check_clipboard, ClipboardContext, CLIPBOARD_INTERVAL as INTERVAL, CLIPBOARD_NAME as NAME,
-    CONTENT,
+    check_clipboard, ClipboardContent, ClipboardContext, CLIPBOARD_INTERVAL as INTERVAL,
+    CLIPBOARD_NAME as NAME, CONTENT,
 };
 use clipboard_master::{CallbackResult, ClipboardHandler, Master};
-use hbb_common::{anyhow, ResultType};
+use hbb_common::{anyhow, regex::Regex, ResultType};
 use std::{
     io, sync,
     sync::{
@@ -14,12 +14,140 @@ use std::{
     time::Duration,
 };
 
+pub const NAME_PRIMARY: &'static str = "primary_selection";
+
 pub fn new() -> GenericService {
     let sp = GenericService::new(NAME, true);
     sp.run::<_>(listen::run);
     sp
 }
 
+#[cfg(target_os = "linux")]
+pub fn new_primary() -> GenericService {
+    let sp = GenericService::new(NAME_PRIMARY, false);
+    sp.repeat::<primary::State, _>(INTERVAL, primary::run);
+    sp
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    HostToClient,
+    ClientToHost,
+}
+
+// the restrictions of the host on the clipboard, from the options:
+// clipboard-direction: "host-to-client", "client-to-host" or empty for both,
+// clipboard-max-size: in bytes, clipboard-formats: "text", "html" and "image" separated by commas,
+// clipboard-deny-regex: the text matching it is blocked
+pub struct Policy {
+    host_to_client: bool,
+    client_to_host: bool,
+    max_size: usize,
+    formats: Vec<ClipboardFormat>,
+    deny: Result<Option<Regex>, String>,
+}
+
+impl Policy {
+    pub fn get() -> Self {
+        let direction = Config::get_option("clipboard-direction");
+        let formats = Config::get_option("clipboard-formats")
+            .split(',')
+            .filter_map(|f| match f.trim() {
+                "text" => Some(ClipboardFormat::Text),
+                "html" => Some(ClipboardFormat::Html),
+                "image" => Some(ClipboardFormat::ImagePng),
+                _ => None,
+            })
+            .collect();
+        let deny = Config::get_option("clipboard-deny-regex");
+        let deny = if deny.is_empty() {
+            Ok(None)
+        } else {
+            Regex::new(&deny).map(Some).map_err(|err| err.to_string())
+        };
+        Self {
+            host_to_client: direction != "client-to-host",
+            client_to_host: direction != "host-to-client",
+            max_size: Config::get_option("clipboard-max-size")
+                .parse()
+                .unwrap_or(0),
+            formats,
+            deny,
+        }
+    }
+
+    pub fn allow(&self, direction: Direction, content: &ClipboardContent) -> bool {
+        match self.check(direction, content) {
+            Ok(()) => true,
+            Err(reason) => {
+                log::warn!(
+                    "{} {:?} of {} bytes ({:?}, {:?}) blocked: {}",
+                    NAME,
+                    direction,
+                    content.content.len(),
+                    content.format,
+                    content.selection,
+                    reason
+                );
+                false
+            }
+        }
+    }
+
+    // the direction and the size are known before the chunks of a large clipboard are received
+    pub fn allow_size(&self, direction: Direction, size: u64) -> bool {
+        match self.check_size(direction, size) {
+            Ok(()) => true,
+            Err(reason) => {
+                log::warn!(
+                    "{} {:?} of {} bytes blocked: {}",
+                    NAME,
+                    direction,
+                    size,
+                    reason
+                );
+                false
+            }
+        }
+    }
+
+    fn check_size(&self, direction: Direction, size: u64) -> Result<(), String> {
+        let allowed = match direction {
+            Direction::HostToClient => self.host_to_client,
+            Direction::ClientToHost => self.client_to_host,
+        };
+        if !allowed {
+            return Err("direction not allowed".to_owned());
+        }
+        if self.max_size > 0 && size > self.max_size as u64 {
+            return Err(format!("larger than {} bytes", self.max_size));
+        }
+        Ok(())
+    }
+
+    fn check(&self, direction: Direction, content: &ClipboardContent) -> Result<(), String> {
+        self.check_size(direction, content.content.len() as _)?;
+        if !self.formats.is_empty() && !self.formats.contains(&content.format) {
+            return Err("format not allowed".to_owned());
+        }
+        if content.format != ClipboardFormat::ImagePng {
+            // the deny-list fails closed
+            match &self.deny {
+                Ok(Some(deny)) => {
+                    if deny.is_match(&String::from_utf8_lossy(&content.content))
+                        || deny.is_match(&content.text)
+                    {
+                        return Err("denied content".to_owned());
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => return Err(format!("invalid deny-list: {}", err)),
+            }
+        }
+        Ok(())
+    }
+}
+
 mod listen {
     use super::*;
 
@@ -74,15 +202,17 @@ mod listen {
             let mut update = None;
             sp.snapshot(|sps| {
                 if sps.has_subscribes() {
-                    update = check_clipboard(&mut ctx, None);
+                    update = check(&mut ctx);
                 }
                 // if there is update, msg will be later together,
                 // otherwise it will be only sent to new subscriber,
                 // but old subscribers ignored
                 if update.is_none() {
-                    let txt = crate::CONTENT.lock().unwrap().clone();
-                    if !txt.is_empty() {
-                        let msg_out = crate::create_clipboard_msg(txt);
+                    let content = crate::CONTENT.lock().unwrap().clone();
+                    if !content.content.is_empty()
+                        && Policy::get().allow(Direction::HostToClient, &content)
+                    {
+                        let msg_out = crate::create_clipboard_msg(content);
                         sps.send_shared(Arc::new(msg_out));
                     }
                 }
@@ -93,7 +223,7 @@ mod listen {
             }
 
             if let Ok(_) = rx.recv_timeout(WAIT) {
-                if let Some(msg) = check_clipboard(&mut ctx, None) {
+                if let Some(msg) = check(&mut ctx) {
                     sp.send(msg);
                 }
             }
@@ -108,6 +238,16 @@ mod listen {
         Ok(())
     }
 
+    fn check(ctx: &mut ClipboardContext) -> Option<Message> {
+        let msg = check_clipboard(ctx, None)?;
+        let content = CONTENT.lock().unwrap().clone();
+        if Policy::get().allow(Direction::HostToClient, &content) {
+            Some(msg)
+        } else {
+            None
+        }
+    }
+
     fn trigger(ctx: &mut ClipboardContext) {
         let mut old_text = "".to_owned();
         let _ = match ctx.get_text() {
@@ -119,3 +259,37 @@ mod listen {
         ctx.set_text(old_text).ok();
     }
 }
+
+#[cfg(target_os = "linux")]
+mod primary {
+    use super::*;
+    use crate::common::{check_primary_selection, PRIMARY_CONTENT};
+
+    #[derive(Default)]
+    pub struct State {
+        ctx: Option<ClipboardContext>,
+        pending: Option<(u64, std::time::Instant)>,
+    }
+
+    impl super::super::service::Reset for State {
+        fn reset(&mut self) {
+            *self = Default::default();
+            *PRIMARY_CONTENT.lock().unwrap() = Default::default();
+        }
+    }
+
+    pub fn run(sp: GenericService, state: &mut State) -> ResultType<()> {
+        if state.ctx.is_none() {
+            state.ctx = Some(ClipboardContext::new()?);
+        }
+        if let Some(ctx) = state.ctx.as_mut() {
+            if let Some(msg) = check_primary_selection(ctx, &PRIMARY_CONTENT, &mut state.pending) {
+                let content = PRIMARY_CONTENT.lock().unwrap().clone();
+                if Policy::get().allow(Direction::HostToClient, &content) {
+                    sp.send(msg);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,16 @@
-use super::{input_service::*, *};
-#[cfg(windows)]
+use super::{
+    clipboard_service::{Direction, Policy},
+    input_service::*,
+    *,
+};
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 use crate::clipboard_file::*;
-use crate::{common::update_clipboard, ipc};
+use crate::{
+    common::{
+        get_clipboard_content, set_clipboard_content, ClipboardChunks, CLIPBOARD_CHUNK_INTERVAL,
+    },
+    ipc,
+};
 use hbb_common::{
     config::Config,
     fs,
@@ -22,6 +31,91 @@ pub type Sender = mpsc::UnboundedSender<(Instant, Arc<Message>)>;
 
 lazy_static::lazy_static! {
     static ref LOGIN_FAILURES: Arc::<Mutex<HashMap<String, (i32, i32, i32)>>> = Default::default();
+    static ref INPUT_CONTROL: Arc::<Mutex<InputControl>> = Default::default();
+}
+
+// only one remote control connection injects the input, the others are view only
+// until the control is handed over by its holder or the connection manager
+#[derive(Default)]
+struct InputControl {
+    holder: Option<i32>,
+    viewers: Vec<Viewer>, // in the order of logging in
+}
+
+struct Viewer {
+    inner: ConnInner,
+    tx_to_cm: mpsc::UnboundedSender<ipc::Data>,
+    peer_id: String,
+    name: String,
+    keyboard: bool,
+    requested: bool,
+}
+
+impl InputControl {
+    fn broadcast(&mut self) {
+        let (peer_id, name) = self
+            .viewers
+            .iter()
+            .find(|v| Some(v.inner.id()) == self.holder)
+            .map(|v| (v.peer_id.clone(), v.name.clone()))
+            .unwrap_or_default();
+        for v in self.viewers.iter_mut() {
+            let is_mine = Some(v.inner.id()) == self.holder;
+            let mut misc = Misc::new();
+            misc.set_control_info(ControlInfo {
+                peer_id: peer_id.clone(),
+                name: name.clone(),
+                is_mine,
+                ..Default::default()
+            });
+            let mut msg_out = Message::new();
+            msg_out.set_misc(misc);
+            v.inner.send(msg_out.into());
+            v.tx_to_cm
+                .send(ipc::Data::InputControl {
+                    has_control: is_mine,
+                    requested: v.requested,
+                })
+                .ok();
+        }
+    }
+
+    fn set_holder(&mut self, holder: Option<i32>) {
+        if let Some(old) = self.holder {
+            if Some(old) != holder {
+                cancel_type_text(old);
+                reset_wheel(old);
+                cancel_composition(old);
+                #[cfg(target_os = "linux")]
+                release_gamepads(old);
+            }
+        }
+        log::info!("Input control: {:?} -> {:?}", self.holder, holder);
+        self.holder = holder;
+        for v in self.viewers.iter_mut() {
+            if Some(v.inner.id()) == holder {
+                v.requested = false;
+            }
+        }
+        self.broadcast();
+    }
+
+    // the earliest request gets the control
+    fn release(&mut self, id: i32) {
+        if self.holder != Some(id) {
+            return;
+        }
+        let next = self
+            .viewers
+            .iter()
+            .find(|v| v.requested && v.keyboard && v.inner.id() != id)
+            .map(|v| v.inner.id());
+        self.set_holder(next);
+    }
+
+    fn get(&mut self, id: i32) -> Option<&mut Viewer> {
+        self.viewers.iter_mut().find(|v| v.inner.id() == id)
+    }
 }
 
 #[derive(Clone, Default)]
@@ -34,6 +128,14 @@ pub struct ConnInner {
 enum MessageInput {
     Mouse((MouseEvent, i32)),
     Key((KeyEvent, bool)),
+    LockKeys(LockKeys),
+    Ime((ImeInput, i32)),
+    #[cfg(target_os = "linux")]
+    Touch((TouchEvent, i32)),
+    #[cfg(target_os = "linux")]
+    Pen((PenEvent, i32)),
+    #[cfg(target_os = "linux")]
+    Gamepad((GamepadEvent, i32)),
     BlockOn,
     BlockOff,
     PrivacyOn,
@@ -62,11 +164,16 @@ pub struct Connection {
     show_remote_cursor: bool, // by peer
     privacy_mode: bool,
     ip: String,
+    peer_id: String,
+    peer_name: String,
     disable_clipboard: bool,                  // by peer
     disable_audio: bool,                      // by peer
+    audio_profile: AudioProfile,              // by peer
     enable_file_transfer: bool,               // by peer
+    sync_primary_selection: bool,             // by peer
     tx_input: std_mpsc::Sender<MessageInput>, // handle input messages
     video_ack_required: bool,
+    clipboard_chunks: ClipboardChunks,
 }
 
 impl Subscriber for ConnInner {
@@ -146,11 +253,16 @@ impl Connection {
             show_remote_cursor: false,
             privacy_mode: false,
             ip: "".to_owned(),
+            peer_id: "".to_owned(),
+            peer_name: "".to_owned(),
             disable_audio: false,
+            audio_profile: AudioProfile::Standard,
             enable_file_transfer: false,
+            sync_primary_selection: false,
             disable_clipboard: false,
             tx_input,
             video_ack_required: false,
+            clipboard_chunks: Default::default(),
         };
         tokio::spawn(async move {
             if let Err(err) = start_ipc(rx_to_cm, tx_from_cm).await {
@@ -175,6 +287,8 @@ impl Connection {
         let mut test_delay_timer =
             time::interval_at(Instant::now() + TEST_DELAY_TIMEOUT, TEST_DELAY_TIMEOUT);
         let mut last_recv_time = Instant::now();
+        let mut clipboard_chunk_timer =
+            time::interval(Duration::from_millis(CLIPBOARD_CHUNK_INTERVAL));
 
         conn.stream.set_send_timeout(
             if conn.file_transfer.is_some() || conn.port_forward_socket.is_some() {
@@ -221,12 +335,17 @@ impl Connection {
                             log::info!("Change permission {} -> {}", name, enabled);
                             if &name == "keyboard" {
                                 conn.keyboard = enabled;
+                                if !enabled {
+                                    cancel_type_text(conn.inner.id());
+                                }
+                                conn.update_viewer_keyboard();
                                 conn.send_permission(Permission::Keyboard, enabled).await;
                                 if let Some(s) = conn.server.upgrade() {
                                     s.write().unwrap().subscribe(
                                         NAME_CURSOR,
                                         conn.inner.clone(), enabled || conn.show_remote_cursor);
                                 }
+                                conn.subscribe_primary_selection();
                             } else if &name == "clipboard" {
                                 conn.clipboard = enabled;
                                 conn.send_permission(Permission::Clipboard, enabled).await;
@@ -235,6 +354,7 @@ impl Connection {
                                         super::clipboard_service::NAME,
                                         conn.inner.clone(), conn.clipboard_enabled() && conn.keyboard);
                                 }
+                                conn.subscribe_primary_selection();
                             } else if &name == "audio" {
                                 conn.audio = enabled;
                                 conn.send_permission(Permission::Audio, enabled).await;
@@ -247,6 +367,8 @@ impl Connection {
                                 conn.file = enabled;
                                 conn.send_permission(Permission::File, enabled).await;
                                 conn.send_to_cm(ipc::Data::ClipboardFileEnabled(conn.file_transfer_enabled()));
+                            } else if &name == "control" {
+                                conn.switch_control(enabled);
                             }
                         }
                         ipc::Data::RawMessage(bytes) => {
@@ -254,7 +376,7 @@ impl Connection {
                         }
                         ipc::Data::ClipbaordFile(_clip) => {
                             if conn.file_transfer_enabled() {
-                                #[cfg(windows)]
+                                #[cfg(any(target_os = "windows", target_os = "linux"))]
                                 allow_err!(conn.stream.send(&clip_2_msg(_clip)).await);
                             }
                         }
@@ -314,11 +436,22 @@ impl Connection {
                             _ => {}
                         }
                     }
+                    if conn.clipboard_chunks.push(msg) {
+                        continue;
+                    }
                     if let Err(err) = conn.stream.send(msg).await {
                         conn.on_close(&err.to_string(), false);
                         break;
                     }
                 },
+                _ = clipboard_chunk_timer.tick() => {
+                    if let Some((msg, _)) = conn.clipboard_chunks.next() {
+                        if let Err(err) = conn.stream.send(&msg).await {
+                            conn.on_close(&err.to_string(), false);
+                            break;
+                        }
+                    }
+                },
                 _ = test_delay_timer.tick() => {
                     if last_recv_time.elapsed() >= SEC30 {
                         conn.on_close("Timeout", true);
@@ -341,6 +474,15 @@ impl Connection {
         video_service::notify_video_frame_feched(id, None);
         super::video_service::update_test_latency(id, 0);
         super::video_service::update_image_quality(id, None);
+        super::audio_service::update_audio_profile(id, None);
+        cancel_type_text(id);
+        reset_wheel(id);
+        cancel_composition(id);
+        Self::remove_viewer(id);
+        #[cfg(target_os = "linux")]
+        release_gamepads(id);
+        #[cfg(target_os = "linux")]
+        super::audio_service::update_audio_source(id, None);
         if let Err(err) = conn.try_port_forward_loop(&mut rx_from_cm).await {
             conn.on_close(&err.to_string(), false);
         }
@@ -354,7 +496,12 @@ impl Connection {
 
         std::thread::spawn(|| Self::handle_blank(rx_blank));
 
+        // the lock keys of the host are reported once the client has sent its own
+        let mut report_lock_keys = false;
+        let mut last_lock_keys = None;
         loop {
+            // the host may change them too, checked when idle
+            let mut check_lock_keys = false;
             match receiver.recv_timeout(std::time::Duration::from_millis(500)) {
                 Ok(v) => match v {
                     MessageInput::Mouse((msg, id)) => {
@@ -369,6 +516,28 @@ impl Connection {
                             msg.down = false;
                             handle_key(&msg);
                         }
+                        check_lock_keys = true;
+                    }
+                    MessageInput::Ime((msg, id)) => {
+                        handle_ime_input(&msg, id);
+                    }
+                    MessageInput::LockKeys(msg) => {
+                        handle_lock_keys(&msg);
+                        report_lock_keys = true;
+                        last_lock_keys = None;
+                        check_lock_keys = true;
+                    }
+                    #[cfg(target_os = "linux")]
+                    MessageInput::Touch((msg, id)) => {
+                        handle_touch(&msg, id);
+                    }
+                    #[cfg(target_os = "linux")]
+                    MessageInput::Pen((msg, id)) => {
+                        handle_pen(&msg, id);
+                    }
+                    #[cfg(target_os = "linux")]
+                    MessageInput::Gamepad((msg, id)) => {
+                        handle_gamepad(&msg, id, &tx);
                     }
                     MessageInput::BlockOn => {
                         if crate::platform::block_input(true) {
@@ -398,6 +567,7 @@ impl Connection {
                     }
                 },
                 Err(err) => {
+                    check_lock_keys = true;
                     if block_input_mode {
                         let _ = crate::platform::block_input(true);
                     }
@@ -406,6 +576,17 @@ impl Connection {
                     }
                 }
             }
+            if report_lock_keys && check_lock_keys {
+                let lock_keys = get_lock_keys();
+                if last_lock_keys.as_ref() != Some(&lock_keys) {
+                    let mut misc = Misc::new();
+                    misc.set_lock_keys(lock_keys.clone());
+                    let mut msg_out = Message::new();
+                    msg_out.set_misc(misc);
+                    tx.send((Instant::now(), Arc::new(msg_out))).ok();
+                    last_lock_keys = Some(lock_keys);
+                }
+            }
         }
         log::info!("Input thread exited");
     }
@@ -578,6 +759,18 @@ impl Connection {
             platform: whoami::platform().to_string(),
             version: crate::VERSION.to_owned(),
             sas_enabled,
+            features: Some(Features {
+                relative_mouse: true,
+                touch: cfg!(target_os = "linux"),
+                pen: cfg!(target_os = "linux"),
+                physical_keyboard: cfg!(target_os = "linux"),
+                hires_scroll: true,
+                gamepad: cfg!(target_os = "linux"),
+                file_delta: true,
+                file_conflict: true,
+                ..Default::default()
+            })
+            .into(),
             ..Default::default()
         };
         let mut sub_service = false;
@@ -619,6 +812,9 @@ impl Connection {
                 if !self.clipboard_enabled() || !self.keyboard {
                     noperms.push(super::clipboard_service::NAME);
                 }
+                if !self.primary_selection_enabled() {
+                    noperms.push(super::clipboard_service::NAME_PRIMARY);
+                }
                 if !self.audio_enabled() {
                     noperms.push(super::audio_service::NAME);
                 }
@@ -626,6 +822,123 @@ impl Connection {
                     .unwrap()
                     .add_connection(self.inner.clone(), &noperms);
             }
+            self.add_viewer();
+        }
+    }
+
+    fn add_viewer(&self) {
+        let mut lock = INPUT_CONTROL.lock().unwrap();
+        lock.viewers.push(Viewer {
+            inner: self.inner.clone(),
+            tx_to_cm: self.tx_to_cm.clone(),
+            peer_id: self.peer_id.clone(),
+            name: self.peer_name.clone(),
+            keyboard: self.keyboard,
+            requested: false,
+        });
+        if lock.holder.is_none() && self.keyboard {
+            lock.set_holder(Some(self.inner.id()));
+        } else {
+            lock.broadcast();
+        }
+    }
+
+    fn remove_viewer(id: i32) {
+        let mut lock = INPUT_CONTROL.lock().unwrap();
+        lock.release(id);
+        lock.viewers.retain(|v| v.inner.id() != id);
+    }
+
+    fn update_viewer_keyboard(&self) {
+        let id = self.inner.id();
+        let mut lock = INPUT_CONTROL.lock().unwrap();
+        if let Some(v) = lock.get(id) {
+            v.keyboard = self.keyboard;
+            if !self.keyboard {
+                v.requested = false;
+            }
+        } else {
+            return;
+        }
+        if lock.holder == Some(id) {
+            if !self.keyboard {
+                lock.release(id);
+            }
+        } else if self.keyboard && lock.holder.is_none() {
+            lock.set_holder(Some(id));
+        } else {
+            lock.broadcast();
+        }
+    }
+
+    #[inline]
+    fn has_control(&self) -> bool {
+        self.keyboard && INPUT_CONTROL.lock().unwrap().holder == Some(self.inner.id())
+    }
+
+    // from the connection manager
+    fn switch_control(&self, enabled: bool) {
+        let id = self.inner.id();
+        let mut lock = INPUT_CONTROL.lock().unwrap();
+        if lock.get(id).is_none() {
+            return;
+        }
+        if !enabled {
+            lock.release(id);
+        } else if self.keyboard && lock.holder != Some(id) {
+            lock.set_holder(Some(id));
+        }
+    }
+
+    fn request_control(&self, request: bool) {
+        let id = self.inner.id();
+        let mut lock = INPUT_CONTROL.lock().unwrap();
+        if lock.holder == Some(id) {
+            if !request {
+                lock.release(id);
+            }
+            return;
+        }
+        if request && !self.keyboard {
+            return;
+        }
+        if request && lock.holder.is_none() {
+            lock.set_holder(Some(id));
+            return;
+        }
+        let req = match lock.get(id) {
+            Some(v) => {
+                v.requested = request;
+                ControlRequest {
+                    conn_id: id,
+                    peer_id: v.peer_id.clone(),
+                    name: v.name.clone(),
+                    ..Default::default()
+                }
+            }
+            None => return,
+        };
+        if request {
+            if let Some(holder) = lock.holder {
+                if let Some(v) = lock.get(holder) {
+                    let mut misc = Misc::new();
+                    misc.set_control_request(req);
+                    let mut msg_out = Message::new();
+                    msg_out.set_misc(misc);
+                    v.inner.send(msg_out.into());
+                }
+            }
+        }
+        lock.broadcast();
+    }
+
+    fn grant_control(&self, to: i32) {
+        let mut lock = INPUT_CONTROL.lock().unwrap();
+        if lock.holder != Some(self.inner.id()) {
+            return;
+        }
+        if lock.get(to).map(|v| v.keyboard) == Some(true) {
+            lock.set_holder(Some(to));
         }
     }
 
@@ -633,8 +946,22 @@ impl Connection {
         self.clipboard && !self.disable_clipboard
     }
 
+    fn primary_selection_enabled(&self) -> bool {
+        self.clipboard_enabled() && self.keyboard && self.sync_primary_selection
+    }
+
+    fn subscribe_primary_selection(&self) {
+        if let Some(s) = self.server.upgrade() {
+            s.write().unwrap().subscribe(
+                super::clipboard_service::NAME_PRIMARY,
+                self.inner.clone(),
+                self.primary_selection_enabled(),
+            );
+        }
+    }
+
     fn audio_enabled(&self) -> bool {
-        self.audio && !self.disable_audio
+        self.audio && !self.disable_audio && self.audio_profile != AudioProfile::Off
     }
 
     fn file_transfer_enabled(&self) -> bool {
@@ -642,6 +969,8 @@ impl Connection {
     }
 
     async fn try_start_cm(&mut self, peer_id: String, name: String, authorized: bool) {
+        self.peer_id = peer_id.clone();
+        self.peer_name = name.clone();
         self.send_to_cm(ipc::Data::Login {
             id: self.inner.id(),
             is_file_transfer: self.file_transfer.is_some(),
@@ -814,12 +1143,12 @@ impl Connection {
         } else if self.authorized {
             match msg.union {
                 Some(message::Union::mouse_event(me)) => {
-                    if self.keyboard {
+                    if self.has_control() {
                         self.input_mouse(me, self.inner.id());
                     }
                 }
                 Some(message::Union::key_event(me)) => {
-                    if self.keyboard {
+                    if self.has_control() {
                         // handle all down as press
                         // fix unexpected repeating key on remote linux, seems also fix abnormal alt/shift, which
                         // make sure all key are released
@@ -831,7 +1160,8 @@ impl Connection {
                         if is_press {
                             match me.union {
                                 Some(key_event::Union::unicode(_))
-                                | Some(key_event::Union::seq(_)) => {
+                                | Some(key_event::Union::seq(_))
+                                | Some(key_event::Union::usb_hid(_)) => {
                                     self.input_key(me, false);
                                 }
                                 _ => {
@@ -843,14 +1173,79 @@ impl Connection {
                         }
                     }
                 }
+                Some(message::Union::type_text(tt)) => {
+                    if self.has_control() {
+                        handle_type_text(tt, self.inner.id());
+                    }
+                }
+                Some(message::Union::play_macro(pm)) => {
+                    if self.has_control() {
+                        handle_play_macro(pm, self.inner.id());
+                    }
+                }
+                Some(message::Union::ime_input(ime)) => {
+                    if self.has_control() {
+                        self.tx_input
+                            .send(MessageInput::Ime((ime, self.inner.id())))
+                            .ok();
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                Some(message::Union::touch_event(te)) => {
+                    if self.has_control() {
+                        self.tx_input
+                            .send(MessageInput::Touch((te, self.inner.id())))
+                            .ok();
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                Some(message::Union::pen_event(pe)) => {
+                    if self.has_control() {
+                        self.tx_input
+                            .send(MessageInput::Pen((pe, self.inner.id())))
+                            .ok();
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                Some(message::Union::gamepad_event(ge)) => {
+                    if self.has_control() {
+                        self.tx_input
+                            .send(MessageInput::Gamepad((ge, self.inner.id())))
+                            .ok();
+                    }
+                }
                 Some(message::Union::clipboard(cb)) => {
                     if self.clipboard {
-                        update_clipboard(cb, None);
+                        self.update_clipboard(cb);
+                    }
+                }
+                Some(message::Union::clipboard_chunk(chunk)) => {
+                    // the compressed total is all the peers of old versions declare
+                    if self.clipboard
+                        && chunk.offset == 0
+                        && !chunk.reject
+                        && !chunk.abort
+                        && !Policy::get()
+                            .allow_size(Direction::ClientToHost, chunk.size.max(chunk.total))
+                    {
+                        let msg = self.clipboard_chunks.reject(&chunk);
+                        self.send(msg).await;
+                    } else if self.clipboard {
+                        match self.clipboard_chunks.receive(chunk) {
+                            Ok(Some(cb)) => self.update_clipboard(cb),
+                            Ok(None) => {}
+                            Err(err) => {
+                                log::error!("{}", err);
+                                if let Some(msg) = self.clipboard_chunks.reject_receiving() {
+                                    self.send(msg).await;
+                                }
+                            }
+                        }
                     }
                 }
                 Some(message::Union::cliprdr(_clip)) => {
                     if self.file_transfer_enabled() {
-                        #[cfg(windows)]
+                        #[cfg(any(target_os = "windows", target_os = "linux"))]
                         if let Some(clip) = msg_2_clip(_clip) {
                             self.send_to_cm(ipc::Data::ClipbaordFile(clip))
                         }
@@ -878,8 +1273,27 @@ impl Connection {
                                     Err(err) => {
                                         self.send(fs::new_error(id, err, 0)).await;
                                     }
-                                    Ok(job) => {
+                                    Ok(mut job) => {
+                                        // only the files changed when synchronizing
+                                        if !s.files.is_empty() {
+                                            let names: std::collections::HashSet<_> =
+                                                s.files.iter().collect();
+                                            let files = job
+                                                .files()
+                                                .iter()
+                                                .filter(|f| names.contains(&f.name))
+                                                .cloned()
+                                                .collect();
+                                            job.set_files(files);
+                                        }
                                         self.send(fs::new_dir(id, job.files().to_vec())).await;
+                                        job.set_offsets(s.offsets.to_vec());
+                                        if s.delta
+                                            || s.conflict_policy.enum_value()
+                                                != Ok(FileConflictPolicy::Overwrite)
+                                        {
+                                            job.wait_offsets();
+                                        }
                                         self.read_jobs.push(job);
                                         self.timer = time::interval(MILLI1);
                                     }
@@ -893,8 +1307,11 @@ impl Connection {
                                         .files
                                         .to_vec()
                                         .drain(..)
-                                        .map(|f| (f.name, f.modified_time))
+                                        .map(|f| (f.name, f.modified_time, f.size))
                                         .collect(),
+                                    resume: r.resume,
+                                    delta: r.delta,
+                                    conflict_policy: r.conflict_policy.value(),
                                 });
                             }
                             Some(file_action::Union::remove_dir(d)) => {
@@ -921,6 +1338,13 @@ impl Connection {
                                 self.send_fs(ipc::FS::CancelWrite { id: c.id });
                                 fs::remove_job(c.id, &mut self.read_jobs);
                             }
+                            Some(file_action::Union::offsets(o)) => {
+                                if let Some(job) = fs::get_job(o.id, &mut self.read_jobs) {
+                                    job.set_signatures(o.signatures.to_vec());
+                                    job.skip_files(&o.skipped);
+                                    job.set_offsets(o.offsets.to_vec());
+                                }
+                            }
                             _ => {}
                         }
                     }
@@ -932,6 +1356,9 @@ impl Connection {
                             file_num: block.file_num,
                             data: block.data,
                             compressed: block.compressed,
+                            offset: block.offset,
+                            sha256: block.sha256,
+                            block_refs: block.block_refs,
                         });
                     }
                     Some(file_response::Union::done(d)) => {
@@ -963,6 +1390,38 @@ impl Connection {
                             Some(Instant::now().into()),
                         );
                     }
+                    #[cfg(target_os = "linux")]
+                    Some(misc::Union::get_audio_sources(_)) => {
+                        if self.audio_enabled() {
+                            self.send_to_cm(ipc::Data::GetAudioSources);
+                        }
+                    }
+                    #[cfg(target_os = "linux")]
+                    Some(misc::Union::audio_source(s)) => {
+                        if self.audio_enabled() {
+                            if let Some(s) =
+                                super::audio_service::update_audio_source(self.inner.id, Some(s))
+                            {
+                                // chosen by another viewer, told to the client
+                                let mut misc = Misc::new();
+                                misc.set_audio_source(s);
+                                let mut msg_out = Message::new();
+                                msg_out.set_misc(misc);
+                                self.send(msg_out).await;
+                            }
+                        }
+                    }
+                    Some(misc::Union::request_control(r)) => {
+                        self.request_control(r);
+                    }
+                    Some(misc::Union::grant_control(to)) => {
+                        self.grant_control(to);
+                    }
+                    Some(misc::Union::lock_keys(lk)) => {
+                        if self.has_control() {
+                            self.tx_input.send(MessageInput::LockKeys(lk)).ok();
+                        }
+                    }
                     _ => {}
                 },
                 _ => {}
@@ -1016,7 +1475,20 @@ impl Connection {
                 }
             }
         }
-        #[cfg(windows)]
+        if let Ok(q) = o.audio_profile.enum_value() {
+            if q != AudioProfile::ProfileNotSet {
+                self.audio_profile = q;
+                super::audio_service::update_audio_profile(self.inner.id(), Some(q));
+                if let Some(s) = self.server.upgrade() {
+                    s.write().unwrap().subscribe(
+                        super::audio_service::NAME,
+                        self.inner.clone(),
+                        self.audio_enabled(),
+                    );
+                }
+            }
+        }
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
         if let Ok(q) = o.enable_file_transfer.enum_value() {
             if q != BoolOption::NotSet {
                 self.enable_file_transfer = q == BoolOption::Yes;
@@ -1033,6 +1505,13 @@ impl Connection {
                         self.clipboard_enabled() && self.keyboard,
                     );
                 }
+                self.subscribe_primary_selection();
+            }
+        }
+        if let Ok(q) = o.sync_primary_selection.enum_value() {
+            if q != BoolOption::NotSet {
+                self.sync_primary_selection = q == BoolOption::Yes;
+                self.subscribe_primary_selection();
             }
         }
         if let Ok(q) = o.privacy_mode.enum_value() {
@@ -1085,6 +1564,16 @@ impl Connection {
         });
     }
 
+    fn update_clipboard(&self, cb: Clipboard) {
+        if let Some(content) = get_clipboard_content(cb) {
+            let opted_in =
+                content.selection != ClipboardSelection::Primary || self.sync_primary_selection;
+            if opted_in && Policy::get().allow(Direction::ClientToHost, &content) {
+                set_clipboard_content(content, None);
+            }
+        }
+    }
+
     #[inline]
     async fn send(&mut self, msg: Message) {
         allow_err!(self.stream.send(&msg).await);
@@ -1159,8 +1648,11 @@ async fn start_ipc(
                         if let Data::FS(ipc::FS::WriteBlock{id,
                             file_num,
                             data,
-                            compressed}) = data {
-                                stream.send(&Data::FS(ipc::FS::WriteBlock{id, file_num, data: Vec::new(), compressed})).await?;
+                            compressed,
+                            offset,
+                            sha256,
+                            block_refs}) = data {
+                                stream.send(&Data::FS(ipc::FS::WriteBlock{id, file_num, data: Vec::new(), compressed, offset, sha256, block_refs})).await?;
                                 stream.send_raw(data).await?;
                         } else {
                             stream.send(&data).await?;
@@ -1,12 +1,17 @@
 use super::*;
 #[cfg(target_os = "macos")]
 use dispatch::Queue;
-use enigo::{Enigo, Key, KeyboardControllable, MouseButton, MouseControllable};
+use enigo::{
+    dsl::{self, Token},
+    Enigo, Key, KeyboardControllable, MouseButton, MouseControllable,
+};
+#[cfg(target_os = "linux")]
+use enigo::{GamepadState, PenState, TouchContact, VirtualGamepad, VirtualPen, VirtualTouchscreen};
 use hbb_common::{config::COMPRESS_LEVEL, protobuf::ProtobufEnumOrUnknown};
 use std::{
     convert::TryFrom,
     sync::atomic::{AtomicBool, Ordering},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 #[derive(Default)]
@@ -14,6 +19,9 @@ struct StateCursor {
     hcursor: u64,
     cursor_data: Arc<Message>,
     cached_cursor_data: HashMap<u64, Arc<Message>>,
+    #[cfg(target_os = "linux")]
+    hidden_cursors: std::collections::HashSet<u64>,
+    pointer_state: (bool, bool),
 }
 
 impl super::service::Reset for StateCursor {
@@ -23,6 +31,8 @@ impl super::service::Reset for StateCursor {
         fix_key_down_timeout(true);
         #[cfg(target_os = "linux")]
         ENIGO.lock().unwrap().reset();
+        #[cfg(target_os = "linux")]
+        reset_touch();
     }
 }
 
@@ -44,6 +54,11 @@ struct Input {
 }
 
 const KEY_CHAR_START: u64 = 9999;
+const TYPE_TEXT_CHUNK: usize = 16;
+// let the slow consoles drain their input between the chunks
+const TYPE_TEXT_CHUNK_PAUSE: Duration = Duration::from_millis(100);
+const MAX_TYPE_TEXT_DELAY: u32 = 1000;
+const KEY_HID_START: u64 = 1 << 32;
 
 #[derive(Clone, Default)]
 pub struct MouseCursorSub {
@@ -145,6 +160,11 @@ fn run_cursor(sp: MouseCursorService, state: &mut StateCursor) -> ResultType<()>
                 msg = cached.clone();
             } else {
                 let mut data = crate::get_cursor_data(hcursor)?;
+                // a fully transparent image is how the apps hide the cursor on X11
+                #[cfg(target_os = "linux")]
+                if data.colors.chunks(4).all(|c| c.len() == 4 && c[3] == 0) {
+                    state.hidden_cursors.insert(hcursor);
+                }
                 data.colors = hbb_common::compress::compress(&data.colors[..], COMPRESS_LEVEL);
                 let mut tmp = Message::new();
                 tmp.set_cursor_data(data);
@@ -155,30 +175,74 @@ fn run_cursor(sp: MouseCursorService, state: &mut StateCursor) -> ResultType<()>
             state.hcursor = hcursor;
             sp.send_shared(msg.clone());
             state.cursor_data = msg;
+            #[cfg(target_os = "linux")]
+            {
+                let pointer_state = (state.hidden_cursors.contains(&hcursor), false);
+                if pointer_state != state.pointer_state {
+                    state.pointer_state = pointer_state;
+                    sp.send(create_pointer_state_msg(pointer_state));
+                }
+            }
+        }
+    }
+    // the clip rect is not tied to the cursor handle, CGCursorIsVisible is always true on macOS
+    #[cfg(windows)]
+    {
+        let pointer_state = crate::platform::get_pointer_state();
+        if pointer_state != state.pointer_state {
+            state.pointer_state = pointer_state;
+            sp.send(create_pointer_state_msg(pointer_state));
         }
     }
     sp.snapshot(|sps| {
         sps.send_shared(state.cursor_data.clone());
+        if state.pointer_state != (false, false) {
+            sps.send(create_pointer_state_msg(state.pointer_state));
+        }
         Ok(())
     })?;
     Ok(())
 }
 
+fn create_pointer_state_msg((hidden, confined): (bool, bool)) -> Message {
+    let mut misc = Misc::new();
+    misc.set_pointer_state(PointerState {
+        hidden,
+        confined,
+        ..Default::default()
+    });
+    let mut msg_out = Message::new();
+    msg_out.set_misc(misc);
+    msg_out
+}
+
 lazy_static::lazy_static! {
-    static ref ENIGO: Arc<Mutex<Enigo>> = {
-        #[cfg(target_os = "linux")]
-        {
-            if crate::platform::is_root() {
-                std::env::set_var("PYNPUT_USERNAME", crate::platform::linux::get_active_username());
-                std::env::set_var("PYNPUT_USERID", crate::platform::linux::get_active_userid());
-            }
-        }
-        Arc::new(Mutex::new(Enigo::new()))
-    };
+    static ref ENIGO: Arc<Mutex<Enigo>> = Arc::new(Mutex::new(Enigo::new()));
     static ref KEYS_DOWN: Arc<Mutex<HashMap<u64, Instant>>> = Default::default();
     static ref LATEST_INPUT: Arc<Mutex<Input>> = Default::default();
+    // the connection typing text and the flag to stop it
+    static ref TYPING: Arc<Mutex<Option<(i32, Arc<AtomicBool>)>>> = Default::default();
+    // the high-resolution wheel not emitted yet by connection, in 1/120 of a notch
+    static ref WHEEL: Arc<Mutex<HashMap<i32, (f32, f32)>>> = Default::default();
+    // the composition of the input method typed so far by connection
+    static ref COMPOSITION: Arc<Mutex<HashMap<i32, String>>> = Default::default();
 }
 static EXITING: AtomicBool = AtomicBool::new(false);
+const WHEEL_DELTA: i32 = 120;
+
+// virtual devices sized to the desktop, recreated when the desktop size changes
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    static ref TOUCHSCREEN: Arc<Mutex<Option<((i32, i32), VirtualTouchscreen)>>> = Default::default();
+    static ref PEN: Arc<Mutex<Option<((i32, i32), VirtualPen)>>> = Default::default();
+    static ref DESKTOP_SIZE: Arc<Mutex<Option<(i32, i32)>>> = Default::default();
+    // the virtual game controllers by connection and index, with the way back for the rumble
+    static ref GAMEPADS: Arc<Mutex<HashMap<(i32, u32), (VirtualGamepad, super::connection::Sender)>>> = Default::default();
+}
+#[cfg(target_os = "linux")]
+static GAMEPAD_POLLING: AtomicBool = AtomicBool::new(false);
+#[cfg(target_os = "linux")]
+const MAX_GAMEPADS: usize = 4;
 
 // mac key input must be run in main thread, otherwise crash on >= osx 10.15
 #[cfg(target_os = "macos")]
@@ -264,6 +328,35 @@ fn get_layout(key: u32) -> Key {
     Key::Layout(std::char::from_u32(key).unwrap_or('\0'))
 }
 
+// physical mode, the usb hid usage injected as the platform keycode
+#[cfg(target_os = "linux")]
+fn get_physical_key(usage: u32) -> Option<Key> {
+    crate::hid::hid_to_x11_keycode(usage).map(Key::Raw)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_physical_key(_usage: u32) -> Option<Key> {
+    None
+}
+
+fn handle_physical_key(usage: u32, down: bool, en: &mut Enigo) {
+    if let Some(key) = get_physical_key(usage) {
+        if down {
+            allow_err!(en.key_down(key));
+            KEYS_DOWN
+                .lock()
+                .unwrap()
+                .insert(usage as u64 + KEY_HID_START, Instant::now());
+        } else {
+            en.key_up(key);
+            KEYS_DOWN
+                .lock()
+                .unwrap()
+                .remove(&(usage as u64 + KEY_HID_START));
+        }
+    }
+}
+
 fn fix_key_down_timeout(force: bool) {
     if KEYS_DOWN.lock().unwrap().is_empty() {
         return;
@@ -278,6 +371,8 @@ fn fix_key_down_timeout(force: bool) {
                 } else {
                     None
                 }
+            } else if key >= KEY_HID_START {
+                get_physical_key((key - KEY_HID_START) as _)
             } else {
                 Some(get_layout((key - KEY_CHAR_START) as _))
             };
@@ -350,7 +445,7 @@ fn handle_mouse_(evt: &MouseEvent, conn: i32) {
     crate::platform::windows::try_change_desktop();
     let buttons = evt.mask >> 3;
     let evt_type = evt.mask & 0x7;
-    if evt_type == 0 {
+    if evt_type == 0 || evt_type == 4 {
         let time = crate::get_time();
         *LATEST_INPUT.lock().unwrap() = Input { time, conn };
     }
@@ -380,6 +475,9 @@ fn handle_mouse_(evt: &MouseEvent, conn: i32) {
         0 => {
             en.mouse_move_to(evt.x, evt.y);
         }
+        4 => {
+            en.mouse_move_relative(evt.x, evt.y);
+        }
         1 => match buttons {
             1 => {
                 allow_err!(en.mouse_down(MouseButton::Left));
@@ -405,20 +503,10 @@ fn handle_mouse_(evt: &MouseEvent, conn: i32) {
             _ => {}
         },
         3 => {
-            #[allow(unused_mut)]
-            let mut x = evt.x;
-            #[allow(unused_mut)]
-            let mut y = evt.y;
-            #[cfg(not(windows))]
-            {
-                x = -x;
-                y = -y;
-            }
-            if x != 0 {
-                en.mouse_scroll_x(x);
-            }
-            if y != 0 {
-                en.mouse_scroll_y(y);
+            if evt.wheel_x != 0. || evt.wheel_y != 0. {
+                handle_wheel(&mut en, evt.wheel_x, evt.wheel_y, conn);
+            } else {
+                scroll_notches(&mut en, evt.x, evt.y);
             }
         }
         _ => {}
@@ -429,6 +517,441 @@ fn handle_mouse_(evt: &MouseEvent, conn: i32) {
     }
 }
 
+// x and y in notches, positive is right and up
+fn handle_wheel(en: &mut Enigo, x: f32, y: f32, conn: i32) {
+    let mut lock = WHEEL.lock().unwrap();
+    let rest = lock.entry(conn).or_default();
+    rest.0 += x * WHEEL_DELTA as f32;
+    rest.1 += y * WHEEL_DELTA as f32;
+    let dx = rest.0.trunc() as i32;
+    let dy = rest.1.trunc() as i32;
+    if dx == 0 && dy == 0 {
+        return;
+    }
+    if en.mouse_scroll_smooth(dx, dy) {
+        rest.0 -= dx as f32;
+        rest.1 -= dy as f32;
+        return;
+    }
+    // emulated with whole notches, the fractions are kept for the next events
+    let nx = dx / WHEEL_DELTA;
+    let ny = dy / WHEEL_DELTA;
+    rest.0 -= (nx * WHEEL_DELTA) as f32;
+    rest.1 -= (ny * WHEEL_DELTA) as f32;
+    scroll_notches(en, nx, ny);
+}
+
+// drops the fractions left by the connection, so they do not add to the next scroll
+pub fn reset_wheel(conn: i32) {
+    WHEEL.lock().unwrap().remove(&conn);
+}
+
+fn scroll_notches(en: &mut Enigo, x: i32, y: i32) {
+    #[allow(unused_mut)]
+    let mut x = x;
+    #[allow(unused_mut)]
+    let mut y = y;
+    #[cfg(not(windows))]
+    {
+        x = -x;
+        y = -y;
+    }
+    if x != 0 {
+        en.mouse_scroll_x(x);
+    }
+    if y != 0 {
+        en.mouse_scroll_y(y);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get_desktop_size() -> ResultType<(i32, i32)> {
+    let mut lock = DESKTOP_SIZE.lock().unwrap();
+    if let Some(size) = *lock {
+        return Ok(size);
+    }
+    let (_, displays) = super::video_service::get_displays()?;
+    let w = displays.iter().map(|d| d.x + d.width).max().unwrap_or(0);
+    let h = displays.iter().map(|d| d.y + d.height).max().unwrap_or(0);
+    if w <= 0 || h <= 0 {
+        bail!("No displays");
+    }
+    *lock = Some((w, h));
+    Ok((w, h))
+}
+
+// the video service restarts on the displays changed
+#[cfg(target_os = "linux")]
+pub fn reset_desktop_size() {
+    *DESKTOP_SIZE.lock().unwrap() = None;
+}
+
+#[cfg(target_os = "linux")]
+fn reset_touch() {
+    if let Some((_, dev)) = TOUCHSCREEN.lock().unwrap().as_mut() {
+        allow_err!(dev.update(&[]));
+    }
+    if let Some((_, dev)) = PEN.lock().unwrap().as_mut() {
+        allow_err!(dev.update(&Default::default()));
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn handle_touch(evt: &TouchEvent, conn: i32) {
+    if EXITING.load(Ordering::SeqCst) {
+        return;
+    }
+    *LATEST_INPUT.lock().unwrap() = Input {
+        time: crate::get_time(),
+        conn,
+    };
+    let size = match get_desktop_size() {
+        Ok(size) => size,
+        Err(err) => {
+            log::error!("Failed to get desktop size: {}", err);
+            return;
+        }
+    };
+    let mut lock = TOUCHSCREEN.lock().unwrap();
+    if lock.as_ref().map(|x| x.0) != Some(size) {
+        *lock = None;
+        match VirtualTouchscreen::new(size.0, size.1) {
+            Ok(dev) => *lock = Some((size, dev)),
+            Err(err) => {
+                log::error!("Failed to create virtual touchscreen: {}", err);
+                return;
+            }
+        }
+    }
+    if let Some((_, dev)) = lock.as_mut() {
+        let contacts: Vec<TouchContact> = evt
+            .points
+            .iter()
+            .map(|p| TouchContact {
+                id: p.id,
+                x: p.x.max(0).min(size.0 - 1),
+                y: p.y.max(0).min(size.1 - 1),
+                pressure: p.pressure,
+            })
+            .collect();
+        allow_err!(dev.update(&contacts));
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn handle_pen(evt: &PenEvent, conn: i32) {
+    if EXITING.load(Ordering::SeqCst) {
+        return;
+    }
+    *LATEST_INPUT.lock().unwrap() = Input {
+        time: crate::get_time(),
+        conn,
+    };
+    let size = match get_desktop_size() {
+        Ok(size) => size,
+        Err(err) => {
+            log::error!("Failed to get desktop size: {}", err);
+            return;
+        }
+    };
+    let mut lock = PEN.lock().unwrap();
+    if lock.as_ref().map(|x| x.0) != Some(size) {
+        *lock = None;
+        match VirtualPen::new(size.0, size.1) {
+            Ok(dev) => *lock = Some((size, dev)),
+            Err(err) => {
+                log::error!("Failed to create virtual pen: {}", err);
+                return;
+            }
+        }
+    }
+    if let Some((_, dev)) = lock.as_mut() {
+        allow_err!(dev.update(&PenState {
+            x: evt.x.max(0).min(size.0 - 1),
+            y: evt.y.max(0).min(size.1 - 1),
+            pressure: evt.pressure,
+            tilt_x: evt.tilt_x,
+            tilt_y: evt.tilt_y,
+            eraser: evt.eraser,
+            touching: evt.touching,
+            in_range: evt.in_range,
+            buttons: evt.buttons,
+        }));
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn handle_gamepad(evt: &GamepadEvent, conn: i32, tx: &super::connection::Sender) {
+    if EXITING.load(Ordering::SeqCst) {
+        return;
+    }
+    *LATEST_INPUT.lock().unwrap() = Input {
+        time: crate::get_time(),
+        conn,
+    };
+    let key = (conn, evt.index);
+    let mut lock = GAMEPADS.lock().unwrap();
+    if !evt.connected {
+        lock.remove(&key);
+        return;
+    }
+    if !lock.contains_key(&key) {
+        if lock.keys().filter(|k| k.0 == conn).count() >= MAX_GAMEPADS {
+            return;
+        }
+        match VirtualGamepad::new() {
+            Ok(dev) => {
+                lock.insert(key, (dev, tx.clone()));
+            }
+            Err(err) => {
+                log::error!("Failed to create virtual gamepad: {}", err);
+                return;
+            }
+        }
+        if !GAMEPAD_POLLING.swap(true, Ordering::SeqCst) {
+            std::thread::spawn(poll_gamepads);
+        }
+    }
+    if let Some((dev, _)) = lock.get_mut(&key) {
+        allow_err!(dev.update(&GamepadState {
+            buttons: evt.buttons,
+            left_x: evt.left_x,
+            left_y: evt.left_y,
+            right_x: evt.right_x,
+            right_y: evt.right_y,
+            left_trigger: evt.left_trigger as _,
+            right_trigger: evt.right_trigger as _,
+        }));
+    }
+}
+
+// unplugs the controllers of the connection
+#[cfg(target_os = "linux")]
+pub fn release_gamepads(conn: i32) {
+    GAMEPADS.lock().unwrap().retain(|k, _| k.0 != conn);
+}
+
+// the games wait for their force feedback requests to be answered,
+// runs as long as there is a controller
+#[cfg(target_os = "linux")]
+fn poll_gamepads() {
+    loop {
+        std::thread::sleep(Duration::from_millis(10));
+        let mut lock = GAMEPADS.lock().unwrap();
+        if lock.is_empty() {
+            GAMEPAD_POLLING.store(false, Ordering::SeqCst);
+            break;
+        }
+        for ((_, index), (dev, tx)) in lock.iter_mut() {
+            match dev.poll_rumble() {
+                Ok(Some(rumble)) => {
+                    let mut misc = Misc::new();
+                    misc.set_gamepad_rumble(GamepadRumble {
+                        index: *index,
+                        strong: rumble.strong as _,
+                        weak: rumble.weak as _,
+                        duration: rumble.duration as _,
+                        ..Default::default()
+                    });
+                    let mut msg_out = Message::new();
+                    msg_out.set_misc(misc);
+                    tx.send((tokio::time::Instant::now(), Arc::new(msg_out)))
+                        .ok();
+                }
+                Ok(None) => {}
+                Err(err) => log::error!("Failed to poll virtual gamepad: {}", err),
+            }
+        }
+    }
+}
+
+// stops the text or macro being typed, only if started by the connection
+pub fn cancel_type_text(conn: i32) {
+    let mut lock = TYPING.lock().unwrap();
+    if lock.as_ref().map(|x| x.0) == Some(conn) {
+        if let Some((_, stop)) = lock.take() {
+            stop.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+// only one text or macro is typed at a time, the new one stops the previous one
+fn replace_typing(conn: i32) -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some((_, old)) = TYPING.lock().unwrap().replace((conn, stop.clone())) {
+        old.store(true, Ordering::SeqCst);
+    }
+    stop
+}
+
+pub fn handle_type_text(evt: TypeText, conn: i32) {
+    if evt.cancel || evt.text.is_empty() {
+        cancel_type_text(conn);
+        return;
+    }
+    let stop = replace_typing(conn);
+    let delay = Duration::from_millis(evt.delay.min(MAX_TYPE_TEXT_DELAY) as _);
+    let chars: Vec<char> = evt.text.chars().filter(|c| *c != '\r').collect();
+    log::info!("Type {} characters, delay: {:?}", chars.len(), delay);
+    std::thread::spawn(move || {
+        for chunk in chars.chunks(TYPE_TEXT_CHUNK) {
+            for chr in chunk {
+                if stop.load(Ordering::SeqCst) || EXITING.load(Ordering::SeqCst) {
+                    log::info!("Typing stopped");
+                    return;
+                }
+                *LATEST_INPUT.lock().unwrap() = Input {
+                    time: crate::get_time(),
+                    conn,
+                };
+                let chr = *chr;
+                #[cfg(target_os = "macos")]
+                if !*IS_SERVER {
+                    QUEUE.exec_sync(move || type_char(chr));
+                } else {
+                    type_char(chr);
+                }
+                #[cfg(not(target_os = "macos"))]
+                type_char(chr);
+                std::thread::sleep(delay);
+            }
+            std::thread::sleep(TYPE_TEXT_CHUNK_PAUSE);
+        }
+    });
+}
+
+fn type_char(chr: char) {
+    #[cfg(windows)]
+    crate::platform::windows::try_change_desktop();
+    let mut en = ENIGO.lock().unwrap();
+    match chr {
+        '\n' => en.key_click(Key::Return),
+        '\t' => en.key_click(Key::Tab),
+        _ => en.key_sequence(&chr.to_string()),
+    }
+}
+
+pub fn handle_ime_input(evt: &ImeInput, conn: i32) {
+    #[cfg(target_os = "macos")]
+    if !*IS_SERVER {
+        let evt = evt.clone();
+        QUEUE.exec_async(move || handle_ime_input_(&evt, conn));
+        return;
+    }
+    handle_ime_input_(evt, conn);
+}
+
+// the composition is typed inline and replaced as it changes, until committed
+fn handle_ime_input_(evt: &ImeInput, conn: i32) {
+    if EXITING.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut lock = COMPOSITION.lock().unwrap();
+    match evt.union {
+        Some(ime_input::Union::composition(ref text)) => {
+            let composition = lock.entry(conn).or_default();
+            replace_text(composition, text);
+            *composition = text.clone();
+        }
+        Some(ime_input::Union::commit(ref text)) => {
+            replace_text(&lock.remove(&conn).unwrap_or_default(), text);
+        }
+        None => {}
+    }
+}
+
+// forgets the composition of the connection, what is typed of it is kept as is
+pub fn cancel_composition(conn: i32) {
+    COMPOSITION.lock().unwrap().remove(&conn);
+}
+
+// erases what differs from the new text and types the rest
+fn replace_text(old: &str, new: &str) {
+    let same = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let erase = old.chars().count() - same;
+    if erase > 0 {
+        #[cfg(windows)]
+        crate::platform::windows::try_change_desktop();
+        let mut en = ENIGO.lock().unwrap();
+        for _ in 0..erase {
+            en.key_click(Key::Backspace);
+        }
+    }
+    for chr in new.chars().skip(same).filter(|c| *c != '\r') {
+        type_char(chr);
+    }
+}
+
+pub fn handle_play_macro(evt: PlayMacro, conn: i32) {
+    let tokens = match dsl::tokenize(&evt.dsl) {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            log::error!("Failed to parse macro: {}", err);
+            return;
+        }
+    };
+    let stop = replace_typing(conn);
+    log::info!("Play macro of {} tokens", tokens.len());
+    std::thread::spawn(move || {
+        let mut pressed = Vec::new();
+        for token in tokens {
+            if stop.load(Ordering::SeqCst) || EXITING.load(Ordering::SeqCst) {
+                log::info!("Macro stopped");
+                break;
+            }
+            match token {
+                Token::Delay(ms) => {
+                    let deadline = Instant::now() + Duration::from_millis(ms);
+                    while !stop.load(Ordering::SeqCst) && Instant::now() < deadline {
+                        std::thread::sleep(
+                            deadline
+                                .saturating_duration_since(Instant::now())
+                                .min(TYPE_TEXT_CHUNK_PAUSE),
+                        );
+                    }
+                    continue;
+                }
+                Token::KeyDown(_) | Token::MouseDown(_) => pressed.push(token.clone()),
+                Token::KeyUp(key) => pressed.retain(|x| x != &Token::KeyDown(key)),
+                Token::MouseUp(button) => pressed.retain(|x| x != &Token::MouseDown(button)),
+                _ => {}
+            }
+            *LATEST_INPUT.lock().unwrap() = Input {
+                time: crate::get_time(),
+                conn,
+            };
+            play_token(token);
+        }
+        // do not leave the keys or buttons of an interrupted macro down
+        for token in pressed.into_iter().rev() {
+            match token {
+                Token::KeyDown(key) => play_token(Token::KeyUp(key)),
+                Token::MouseDown(button) => play_token(Token::MouseUp(button)),
+                _ => {}
+            }
+        }
+    });
+}
+
+fn play_token(token: Token) {
+    #[cfg(target_os = "macos")]
+    if !*IS_SERVER {
+        QUEUE.exec_sync(move || play_token_(&token));
+        return;
+    }
+    play_token_(&token);
+}
+
+fn play_token_(token: &Token) {
+    #[cfg(windows)]
+    crate::platform::windows::try_change_desktop();
+    dsl::eval_token(&mut *ENIGO.lock().unwrap(), token);
+}
+
 pub fn is_enter(evt: &KeyEvent) -> bool {
     if let Some(key_event::Union::control_key(ck)) = evt.union {
         if ck.value() == ControlKey::Return.value() || ck.value() == ControlKey::NumpadEnter.value()
@@ -567,6 +1090,12 @@ lazy_static::lazy_static! {
     ].iter().map(|(a, b)| (a.value(), b.clone())).collect();
 }
 
+// used by the client to record the control keys in macros
+#[inline]
+pub fn control_key_to_key(ck: i32) -> Option<Key> {
+    KEY_MAP.get(&ck).cloned()
+}
+
 pub fn handle_key(evt: &KeyEvent) {
     #[cfg(target_os = "macos")]
     if !*IS_SERVER {
@@ -578,6 +1107,55 @@ pub fn handle_key(evt: &KeyEvent) {
     handle_key_(evt);
 }
 
+pub fn handle_lock_keys(evt: &LockKeys) {
+    #[cfg(target_os = "macos")]
+    if !*IS_SERVER {
+        let evt = evt.clone();
+        QUEUE.exec_async(move || handle_lock_keys_(&evt));
+        return;
+    }
+    handle_lock_keys_(evt);
+}
+
+// there is no Num Lock and Scroll Lock on macOS
+fn lock_keys(evt: &LockKeys) -> Vec<(Key, bool)> {
+    if cfg!(target_os = "macos") {
+        vec![(Key::CapsLock, evt.caps_lock)]
+    } else {
+        vec![
+            (Key::CapsLock, evt.caps_lock),
+            (Key::NumLock, evt.num_lock),
+            (Key::Scroll, evt.scroll_lock),
+        ]
+    }
+}
+
+fn handle_lock_keys_(evt: &LockKeys) {
+    if EXITING.load(Ordering::SeqCst) {
+        return;
+    }
+    #[cfg(windows)]
+    crate::platform::windows::try_change_desktop();
+    let mut en = ENIGO.lock().unwrap();
+    for (key, on) in lock_keys(evt) {
+        if en.get_key_state(key.clone()) != on {
+            en.key_down(key.clone()).ok();
+            en.key_up(key);
+        }
+    }
+}
+
+pub fn get_lock_keys() -> LockKeys {
+    let mut en = ENIGO.lock().unwrap();
+    let mut evt = LockKeys::new();
+    evt.caps_lock = en.get_key_state(Key::CapsLock);
+    if cfg!(not(target_os = "macos")) {
+        evt.num_lock = en.get_key_state(Key::NumLock);
+        evt.scroll_lock = en.get_key_state(Key::Scroll);
+    }
+    evt
+}
+
 fn handle_key_(evt: &KeyEvent) {
     if EXITING.load(Ordering::SeqCst) {
         return;
@@ -585,6 +1163,11 @@ fn handle_key_(evt: &KeyEvent) {
     #[cfg(windows)]
     crate::platform::windows::try_change_desktop();
     let mut en = ENIGO.lock().unwrap();
+    // the modifiers and lock keys come as physical keys too, no need to sync them
+    if let Some(key_event::Union::usb_hid(usage)) = evt.union {
+        handle_physical_key(usage, evt.down, &mut en);
+        return;
+    }
     // disable numlock if press home etc when numlock is on,
     // because we will get numpad value (7,8,9 etc) if not
     #[cfg(windows)]
@@ -174,6 +174,8 @@ fn run(sp: GenericService) -> ResultType<()> {
     let wait = 1000 / fps;
     let spf = time::Duration::from_secs_f32(1. / (fps as f32));
     let (ndisplay, current, display) = get_current_display()?;
+    #[cfg(target_os = "linux")]
+    super::input_service::reset_desktop_size();
     let (origin, width, height) = (display.origin(), display.width(), display.height());
     log::debug!(
         "#displays={}, current={}, origin: {:?}, width={}, height={}",
@@ -224,7 +226,6 @@ fn run(sp: GenericService) -> ResultType<()> {
 
     let mut frame_controller = VideoFrameController::new();
 
-    let start = time::Instant::now();
     let mut last_check_displays = time::Instant::now();
     #[cfg(windows)]
     let mut try_gdi = 1;
@@ -262,8 +263,7 @@ fn run(sp: GenericService) -> ResultType<()> {
 
         match c.frame(wait as _) {
             Ok(frame) => {
-                let time = now - start;
-                let ms = (time.as_secs() * 1000 + time.subsec_millis() as u64) as i64;
+                let ms = super::get_pts(now);
                 let send_conn_ids = handle_one_frame(&sp, &frame, ms, &mut vpx)?;
                 frame_controller.set_send(now, send_conn_ids);
                 #[cfg(windows)]
@@ -315,6 +315,13 @@ impl UI {
         hbb_common::socket_client::test_if_valid_server(&host)
     }
 
+    fn test_if_valid_regex(&self, regex: String) -> String {
+        match hbb_common::regex::Regex::new(&regex) {
+            Ok(_) => "".to_owned(),
+            Err(err) => err.to_string(),
+        }
+    }
+
     fn get_sound_inputs(&self) -> Value {
         let mut a = Value::array(0);
         #[cfg(windows)]
@@ -706,6 +713,7 @@ impl sciter::EventHandler for UI {
         fn forget_password(String);
         fn set_peer_option(String, String, String);
         fn test_if_valid_server(String);
+        fn test_if_valid_regex(String);
         fn get_sound_inputs();
         fn set_options(Value);
         fn set_option(String, String);
@@ -1,5 +1,5 @@
 use crate::ipc::{self, new_listener, Connection, Data};
-#[cfg(windows)]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 use clipboard::{
     create_cliprdr_context, empty_clipboard, get_rx_clip_client, server_clip_file, set_conn_enabled,
 };
@@ -8,7 +8,7 @@ use hbb_common::{
     config::{Config, ICON},
     fs, log,
     message_proto::*,
-    protobuf::Message as _,
+    protobuf::{Message as _, ProtobufEnum},
     tokio::{self, sync::mpsc, task::spawn_blocking},
 };
 use sciter::{make_args, Element, Value, HELEMENT};
@@ -118,6 +118,16 @@ impl ConnectionManager {
             Data::ChatMessage { text } => {
                 self.call("newMessage", &make_args!(id, text));
             }
+            Data::InputControl {
+                has_control,
+                requested,
+            } => {
+                self.call("updateControl", &make_args!(id, has_control, requested));
+            }
+            #[cfg(target_os = "linux")]
+            Data::GetAudioSources => {
+                Self::send_audio_sources(conn).await;
+            }
             Data::FS(v) => match v {
                 ipc::FS::ReadDir {
                     dir,
@@ -142,19 +152,50 @@ impl ConnectionManager {
                     path,
                     id,
                     mut files,
+                    resume,
+                    delta,
+                    conflict_policy,
                 } => {
-                    write_jobs.push(fs::TransferJob::new_write(
-                        id,
-                        path,
-                        files
-                            .drain(..)
-                            .map(|f| FileEntry {
-                                name: f.0,
-                                modified_time: f.1,
-                                ..Default::default()
-                            })
-                            .collect(),
-                    ));
+                    let files: Vec<FileEntry> = files
+                        .drain(..)
+                        .map(|f| FileEntry {
+                            name: f.0,
+                            modified_time: f.1,
+                            size: f.2,
+                            ..Default::default()
+                        })
+                        .collect();
+                    let mut job = fs::TransferJob::new_write(id, path.clone(), files.clone());
+                    job.set_conflict_policy(
+                        FileConflictPolicy::from_i32(conflict_policy).unwrap_or_default(),
+                    );
+                    let overwrite = job.conflict_policy() == FileConflictPolicy::Overwrite;
+                    if resume || delta || !overwrite {
+                        let offsets = if resume {
+                            fs::get_resume_offsets(&path, &files)
+                        } else {
+                            Vec::new()
+                        };
+                        let (skipped, conflicts) = job.resolve_conflicts();
+                        let signatures = if delta {
+                            let files: Vec<_> = files
+                                .into_iter()
+                                .filter(|f| !skipped.contains(&f.name))
+                                .collect();
+                            spawn_blocking(move || fs::get_signatures(&path, &files))
+                                .await
+                                .unwrap_or_default()
+                        } else {
+                            Vec::new()
+                        };
+                        job.set_signatures(signatures.clone());
+                        Self::send(
+                            fs::new_offsets(id, offsets, signatures, skipped, conflicts),
+                            conn,
+                        )
+                        .await;
+                    }
+                    write_jobs.push(job);
                 }
                 ipc::FS::CancelWrite { id } => {
                     if let Some(job) = fs::get_job(id, write_jobs) {
@@ -174,6 +215,9 @@ impl ConnectionManager {
                     file_num,
                     data,
                     compressed,
+                    offset,
+                    sha256,
+                    block_refs,
                 } => {
                     let raw = if let Ok(bytes) = conn.next_raw().await {
                         Some(bytes)
@@ -188,6 +232,9 @@ impl ConnectionManager {
                                     file_num,
                                     data,
                                     compressed,
+                                    offset,
+                                    sha256,
+                                    block_refs,
                                     ..Default::default()
                                 },
                                 raw.as_ref().map(|x| &x[..]),
@@ -199,13 +246,13 @@ impl ConnectionManager {
                     }
                 }
             },
-            #[cfg(windows)]
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
             Data::ClipbaordFile(_clip) => {
                 _tx_clip_file
                     .send(ClipboardFileData::Clip((id, _clip)))
                     .ok();
             }
-            #[cfg(windows)]
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
             Data::ClipboardFileEnabled(enabled) => {
                 _tx_clip_file
                     .send(ClipboardFileData::Enable((id, enabled)))
@@ -289,6 +336,37 @@ impl ConnectionManager {
         .await;
     }
 
+    // cm runs in the user session which owns the pulseaudio daemon
+    #[cfg(target_os = "linux")]
+    async fn send_audio_sources(conn: &mut Connection) {
+        use audio_source::SourceType;
+        let mut sources: Vec<AudioSource> = Vec::new();
+        for (name, description) in crate::platform::linux::get_pa_sinks() {
+            sources.push(AudioSource {
+                source_type: SourceType::Sink.into(),
+                name,
+                description,
+                ..Default::default()
+            });
+        }
+        for (index, description, _) in crate::platform::linux::get_pa_apps() {
+            sources.push(AudioSource {
+                source_type: SourceType::Application.into(),
+                name: index,
+                description,
+                ..Default::default()
+            });
+        }
+        let mut misc = Misc::new();
+        misc.set_audio_sources(AudioSources {
+            sources: sources.into(),
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        Self::send(msg_out, conn).await;
+    }
+
     async fn send(msg: Message, conn: &mut Connection) {
         match msg.write_to_bytes() {
             Ok(bytes) => allow_err!(conn.send(&Data::RawMessage(bytes)).await),
@@ -350,7 +428,7 @@ impl sciter::EventHandler for ConnectionManager {
 }
 
 enum ClipboardFileData {
-    #[cfg(windows)]
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
     Clip((i32, ipc::ClipbaordFile)),
     Enable((i32, bool)),
 }
@@ -358,9 +436,9 @@ enum ClipboardFileData {
 #[tokio::main(flavor = "current_thread")]
 async fn start_ipc(cm: ConnectionManager) {
     let (tx_file, _rx_file) = mpsc::unbounded_channel::<ClipboardFileData>();
-    #[cfg(windows)]
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
     let cm_clip = cm.clone();
-    #[cfg(windows)]
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
     std::thread::spawn(move || start_clipboard_file(cm_clip, _rx_file));
 
     match new_listener("_cm").await {
@@ -437,55 +515,89 @@ async fn start_pa() {
                     match result {
                         Ok(stream) => {
                             let mut stream = Connection::new(stream);
-                            let mut device: String = "".to_owned();
+                            let mut audio_input: String = "".to_owned();
                             if let Some(Ok(Some(Data::Config((_, Some(x)))))) =
                                 stream.next_timeout2(1000).await
                             {
-                                device = x;
-                            }
-                            if !device.is_empty() {
-                                device = crate::platform::linux::get_pa_source_name(&device);
-                            }
-                            if device.is_empty() {
-                                device = crate::platform::linux::get_pa_monitor();
-                            }
-                            if device.is_empty() {
-                                continue;
+                                audio_input = x;
                             }
-                            let spec = pulse::sample::Spec {
-                                format: pulse::sample::Format::F32le,
-                                channels: 2,
-                                rate: crate::platform::linux::PA_SAMPLE_RATE,
-                            };
-                            log::info!("pa monitor: {:?}", device);
-                            // systemctl --user status pulseaudio.service
-                            let mut buf: Vec<u8> = vec![0; AUDIO_DATA_SIZE_U8];
-                            match psimple::Simple::new(
-                                None,                             // Use the default server
-                                APP_NAME,                         // Our application’s name
-                                pulse::stream::Direction::Record, // We want a record stream
-                                Some(&device),                    // Use the default device
-                                "record",                         // Description of our stream
-                                &spec,                            // Our sample format
-                                None,                             // Use default channel map
-                                None, // Use default buffering attributes
-                            ) {
-                                Ok(s) => loop {
-                                    if let Ok(_) = s.read(&mut buf) {
-                                        let out =
-                                            if buf.iter().filter(|x| **x != 0).next().is_none() {
-                                                vec![]
-                                            } else {
-                                                buf.clone()
-                                            };
-                                        if let Err(err) = stream.send_raw(out).await {
-                                            log::error!("Failed to send audio data:{}", err);
-                                            break;
+                            // chosen by the client, "sink:<name>" or "app:<index>"
+                            let mut source: String = "".to_owned();
+                            'capture: loop {
+                                let mut device: String = "".to_owned();
+                                let mut _app_capture = None;
+                                if let Some(index) = source.strip_prefix("app:") {
+                                    match crate::platform::linux::PaAppCapture::new(index) {
+                                        Ok(c) => {
+                                            device = c.monitor();
+                                            _app_capture = Some(c);
+                                        }
+                                        Err(err) => {
+                                            log::error!("Failed to capture {}: {}", source, err);
                                         }
                                     }
-                                },
-                                Err(err) => {
-                                    log::error!("Could not create simple pulse: {}", err);
+                                } else if let Some(sink) = source.strip_prefix("sink:") {
+                                    device = format!("{}.monitor", sink);
+                                }
+                                if device.is_empty() && !audio_input.is_empty() {
+                                    device =
+                                        crate::platform::linux::get_pa_source_name(&audio_input);
+                                }
+                                if device.is_empty() {
+                                    device = crate::platform::linux::get_pa_monitor();
+                                }
+                                if device.is_empty() {
+                                    break;
+                                }
+                                let spec = pulse::sample::Spec {
+                                    format: pulse::sample::Format::F32le,
+                                    channels: 2,
+                                    rate: crate::platform::linux::PA_SAMPLE_RATE,
+                                };
+                                log::info!("pa monitor: {:?}", device);
+                                // systemctl --user status pulseaudio.service
+                                let mut buf: Vec<u8> = vec![0; AUDIO_DATA_SIZE_U8];
+                                match psimple::Simple::new(
+                                    None,                             // Use the default server
+                                    APP_NAME,                         // Our application’s name
+                                    pulse::stream::Direction::Record, // We want a record stream
+                                    Some(&device),                    // Use the default device
+                                    "record",                         // Description of our stream
+                                    &spec,                            // Our sample format
+                                    None,                             // Use default channel map
+                                    None, // Use default buffering attributes
+                                ) {
+                                    Ok(s) => loop {
+                                        if let Ok(_) = s.read(&mut buf) {
+                                            let out =
+                                                if buf.iter().filter(|x| **x != 0).next().is_none()
+                                                {
+                                                    vec![]
+                                                } else {
+                                                    buf.clone()
+                                                };
+                                            if let Err(err) = stream.send_raw(out).await {
+                                                log::error!("Failed to send audio data:{}", err);
+                                                break 'capture;
+                                            }
+                                        }
+                                        // switch live without waiting for a new connection
+                                        match stream.next_timeout2(0).await {
+                                            Some(Ok(Some(Data::Config((name, Some(x))))))
+                                                if name == "audio-source" && x != source =>
+                                            {
+                                                log::info!("pa source: {:?}", x);
+                                                source = x;
+                                                continue 'capture;
+                                            }
+                                            Some(Err(_)) => break 'capture,
+                                            _ => {}
+                                        }
+                                    },
+                                    Err(err) => {
+                                        log::error!("Could not create simple pulse: {}", err);
+                                        break;
+                                    }
                                 }
                             }
                         }
@@ -502,7 +614,7 @@ async fn start_pa() {
     }
 }
 
-#[cfg(windows)]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 #[tokio::main(flavor = "current_thread")]
 async fn start_clipboard_file(
     cm: ConnectionManager,
@@ -562,7 +674,7 @@ async fn start_clipboard_file(
     }
 }
 
-#[cfg(windows)]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 fn cmd_inner_send(cm: &ConnectionManager, id: i32, data: Data) {
     let lock = cm.read().unwrap();
     if id != 0 {
@@ -1,15 +1,22 @@
-#[cfg(windows)]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 use crate::clipboard_file::*;
+#[cfg(target_os = "linux")]
+use crate::common::check_primary_selection;
 use crate::{
     client::*,
-    common::{self, check_clipboard, update_clipboard, ClipboardContext, CLIPBOARD_INTERVAL},
+    common::{
+        self, check_clipboard, update_clipboard, ClipboardChunks, ClipboardContent,
+        ClipboardContext, CLIPBOARD_CHUNK_INTERVAL, CLIPBOARD_INTERVAL,
+    },
+    gamepad,
+    keymap::{self, Keymap},
 };
-#[cfg(windows)]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 use clipboard::{
-    cliprdr::CliprdrClientContext, create_cliprdr_context as create_clipboard_file_context,
-    get_rx_clip_client, server_clip_file,
+    create_cliprdr_context as create_clipboard_file_context, get_rx_clip_client, server_clip_file,
+    CliprdrClientContext,
 };
-use enigo::{self, Enigo, KeyboardControllable};
+use enigo::{self, dsl, Enigo, KeyboardControllable, MouseControllable};
 use hbb_common::{
     allow_err,
     config::{self, Config, PeerConfig},
@@ -21,6 +28,7 @@ use hbb_common::{
     tokio::{
         self,
         sync::mpsc,
+        task::spawn_blocking,
         time::{self, Duration, Instant, Interval},
     },
     Stream,
@@ -35,7 +43,7 @@ use sciter::{
     Value,
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ops::Deref,
     sync::{Arc, Mutex, RwLock},
 };
@@ -52,11 +60,17 @@ fn get_key_state(key: enigo::Key) -> bool {
     if key == enigo::Key::NumLock {
         return true;
     }
+    #[cfg(target_os = "macos")]
+    if key == enigo::Key::Scroll {
+        return false;
+    }
     ENIGO.lock().unwrap().get_key_state(key)
 }
 
 static mut IS_IN: bool = false;
 static mut KEYBOARD_HOOKED: bool = false;
+// the keys come through the view, after the local input method, instead of the hook
+static mut LOCAL_IME: bool = false;
 static mut SERVER_KEYBOARD_ENABLED: bool = true;
 static mut SERVER_FILE_TRANSFER_ENABLED: bool = true;
 static mut SERVER_CLIPBOARD_ENABLED: bool = true;
@@ -78,6 +92,11 @@ pub struct Handler {
     id: String,
     args: Vec<String>,
     lc: Arc<RwLock<LoginConfigHandler>>,
+    recorder: Arc<Mutex<Option<dsl::Recorder>>>,
+    keymap: Arc<Mutex<Keymap>>,
+    // the rumble for the thread of the game controllers
+    gamepad: Arc<Mutex<Option<std::sync::mpsc::Sender<GamepadRumble>>>>,
+    audio_stats: Arc<Mutex<AudioStats>>,
 }
 
 impl Deref for Handler {
@@ -168,12 +187,36 @@ impl sciter::EventHandler for Handler {
         fn login(String, bool);
         fn new_rdp();
         fn send_mouse(i32, i32, i32, bool, bool, bool, bool);
+        fn send_wheel(f64, f64, bool, bool, bool, bool);
+        fn move_local_cursor(i32, i32);
+        fn send_touch(Value);
+        fn send_pen(i32, i32, f64, i32, i32, bool, bool, bool, i32);
         fn enter();
+        fn set_local_ime(bool);
+        fn send_key_event(i32, String, i32, bool, bool, bool, bool);
+        fn send_ime_input(String, bool);
         fn leave();
         fn ctrl_alt_del();
         fn transfer_file();
         fn tunnel();
         fn lock_screen();
+        fn type_clipboard(i32);
+        fn cancel_type_text();
+        fn cancel_clipboard();
+        fn start_macro_recording();
+        fn stop_macro_recording();
+        fn get_macros();
+        fn get_macro(String);
+        fn save_macro(String, String);
+        fn play_macro(String);
+        fn request_control(bool);
+        fn grant_control(i32);
+        fn get_keymap();
+        fn save_keymap(String);
+        fn get_keymap_presets();
+        fn get_keymap_preset(String);
+        fn import_keymap(String);
+        fn export_keymap(String);
         fn reconnect();
         fn get_chatbox();
         fn get_icon();
@@ -189,7 +232,12 @@ impl sciter::EventHandler for Handler {
         fn confirm_delete_files(i32, i32);
         fn set_no_confirm(i32);
         fn cancel_job(i32);
-        fn send_files(i32, String, String, bool, bool);
+        fn send_files(i32, String, String, bool, bool, String);
+        fn get_transfer_jobs();
+        fn resume_job(i32, String, String, bool, bool, String);
+        fn sync_files(i32, String, String, bool, bool, bool, bool);
+        fn resolve_conflict(i32, bool, bool);
+        fn clear_transfer_jobs();
         fn get_platform(bool);
         fn get_path_sep(bool);
         fn get_icon_path(i32, String);
@@ -206,6 +254,11 @@ impl sciter::EventHandler for Handler {
         fn save_view_style(String);
         fn save_image_quality(String);
         fn save_custom_image_quality(i32, i32);
+        fn get_audio_profile();
+        fn save_audio_profile(String);
+        fn get_audio_sources();
+        fn get_audio_stats();
+        fn set_audio_source(i32, String);
         fn refresh_video();
         fn get_toggle_option(String);
         fn toggle_option(String);
@@ -226,9 +279,37 @@ impl Handler {
             .write()
             .unwrap()
             .initialize(id, me.is_file_transfer(), me.is_port_forward());
+        *me.keymap.lock().unwrap() = Keymap::new(&me.lc.read().unwrap().keymap);
         me
     }
 
+    fn start_gamepad(&self) {
+        if self.is_port_forward() || self.is_file_transfer() {
+            return;
+        }
+        let mut gamepad = self.gamepad.lock().unwrap();
+        if gamepad.is_some() || !self.lc.read().unwrap().features.gamepad {
+            return;
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        *gamepad = Some(tx);
+        let me = self.clone();
+        let lc = self.lc.clone();
+        let active =
+            move || unsafe { SERVER_KEYBOARD_ENABLED } && lc.read().unwrap().is_gamepad_forwarded();
+        std::thread::spawn(move || {
+            gamepad::run(rx, active, |evt| {
+                let mut msg_out = Message::new();
+                msg_out.set_gamepad_event(evt);
+                match me.read().unwrap().sender {
+                    Some(ref sender) => sender.send(Data::Message(msg_out)).is_ok(),
+                    None => false,
+                }
+            });
+            log::info!("gamepad forwarding stopped");
+        });
+    }
+
     fn start_keyboard_hook(&self) {
         if self.is_port_forward() || self.is_file_transfer() {
             return;
@@ -248,7 +329,7 @@ impl Handler {
             std::env::set_var("KEYBOARD_ONLY", "y"); // pass to rdev
             use rdev::{EventType::*, *};
             let func = move |evt: Event| {
-                if unsafe { !IS_IN || !SERVER_KEYBOARD_ENABLED } {
+                if unsafe { !IS_IN || !SERVER_KEYBOARD_ENABLED || LOCAL_IME } {
                     return;
                 }
                 let (key, down) = match evt.event_type {
@@ -277,6 +358,23 @@ impl Handler {
                 let ctrl = get_key_state(enigo::Key::Control);
                 let shift = get_key_state(enigo::Key::Shift);
                 let command = get_key_state(enigo::Key::Meta);
+                if me.lc.read().unwrap().is_physical_keyboard() {
+                    // the fake VK_LCONTROL sent with AltGr, see below
+                    #[cfg(windows)]
+                    if key == Key::ControlLeft && evt.scan_code & 0x200 != 0 {
+                        return;
+                    }
+                    #[cfg(windows)]
+                    let scan_code = evt.scan_code;
+                    #[cfg(not(windows))]
+                    let scan_code = 0;
+                    if let Some(usage) = get_hid_usage(key, scan_code) {
+                        let mut key_event = KeyEvent::new();
+                        key_event.set_usb_hid(usage);
+                        me.key_down_or_up(down, key_event, alt, ctrl, shift, command);
+                    }
+                    return;
+                }
                 let control_key = match key {
                     Key::Alt => Some(ControlKey::Alt),
                     Key::AltGr => Some(ControlKey::RAlt),
@@ -358,6 +456,10 @@ impl Handler {
                     Key::KpDecimal => Some(ControlKey::Decimal),
                     Key::KpMinus => Some(ControlKey::Subtract),
                     Key::KpPlus => Some(ControlKey::Add),
+                    // the lock states are sent along with the keys, unless Caps Lock is remapped
+                    Key::CapsLock if me.keymap.lock().unwrap().caps_lock().is_some() => {
+                        Some(ControlKey::CapsLock)
+                    }
                     Key::CapsLock | Key::NumLock | Key::ScrollLock => {
                         return;
                     }
@@ -522,6 +624,55 @@ impl Handler {
         }
     }
 
+    fn get_audio_profile(&mut self) -> String {
+        self.lc.read().unwrap().audio_profile.clone()
+    }
+
+    fn save_audio_profile(&mut self, value: String) {
+        let msg = self.lc.write().unwrap().save_audio_profile(value);
+        if let Some(msg) = msg {
+            self.send(Data::Message(msg));
+        }
+    }
+
+    // jitter buffer, output latency and drift in ms, and the packets dropped
+    fn get_audio_stats(&mut self) -> Value {
+        let stats = *self.audio_stats.lock().unwrap();
+        let mut v = Value::array(0);
+        v.push(stats.buffer_depth as i32);
+        v.push(stats.output_latency as i32);
+        v.push(stats.drift as i32);
+        v.push(stats.dropped as i32);
+        v
+    }
+
+    fn get_audio_sources(&mut self) {
+        let mut misc = Misc::new();
+        misc.set_get_audio_sources(true);
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
+    // only for this session, empty name to go back to the host setting
+    fn set_audio_source(&mut self, source_type: i32, name: String) {
+        let mut misc = Misc::new();
+        use audio_source::SourceType;
+        misc.set_audio_source(AudioSource {
+            source_type: if source_type == SourceType::Application as i32 {
+                SourceType::Application
+            } else {
+                SourceType::Sink
+            }
+            .into(),
+            name,
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
     fn get_remember(&mut self) -> bool {
         self.lc.read().unwrap().remember
     }
@@ -693,6 +844,69 @@ impl Handler {
         self.send(Data::CancelJob(id));
     }
 
+    fn get_transfer_jobs(&mut self) -> Value {
+        let jobs = self.lc.read().unwrap().get_transfer_jobs();
+        let mut v = Value::array(0);
+        for job in jobs {
+            let mut v2 = Value::array(0);
+            v2.push(job.path);
+            v2.push(job.to);
+            v2.push(job.include_hidden);
+            v2.push(job.is_remote);
+            v.push(v2);
+        }
+        v
+    }
+
+    fn resume_job(
+        &mut self,
+        id: i32,
+        path: String,
+        to: String,
+        include_hidden: bool,
+        is_remote: bool,
+        policy: String,
+    ) {
+        self.send(Data::ResumeJob((
+            id,
+            path,
+            to,
+            include_hidden,
+            is_remote,
+            policy,
+        )));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn sync_files(
+        &mut self,
+        id: i32,
+        path: String,
+        to: String,
+        include_hidden: bool,
+        is_remote: bool,
+        delete_extras: bool,
+        dry_run: bool,
+    ) {
+        self.send(Data::SyncFiles((
+            id,
+            path,
+            to,
+            include_hidden,
+            is_remote,
+            delete_extras,
+            dry_run,
+        )));
+    }
+
+    fn resolve_conflict(&mut self, id: i32, overwrite: bool, apply_all: bool) {
+        self.send(Data::ResolveConflict((id, overwrite, apply_all)));
+    }
+
+    fn clear_transfer_jobs(&mut self) {
+        self.lc.write().unwrap().clear_transfer_jobs();
+    }
+
     fn read_remote_dir(&mut self, path: String, include_hidden: bool) {
         let mut msg_out = Message::new();
         let mut file_action = FileAction::new();
@@ -762,8 +976,16 @@ impl Handler {
         to: String,
         include_hidden: bool,
         is_remote: bool,
+        policy: String,
     ) {
-        self.send(Data::SendFiles((id, path, to, include_hidden, is_remote)));
+        self.send(Data::SendFiles((
+            id,
+            path,
+            to,
+            include_hidden,
+            is_remote,
+            policy,
+        )));
     }
 
     fn is_file_transfer(&self) -> bool {
@@ -878,6 +1100,7 @@ impl Handler {
         unsafe {
             IS_IN = true;
         }
+        self.send_lock_keys();
     }
 
     fn leave(&mut self) {
@@ -886,6 +1109,69 @@ impl Handler {
         }
     }
 
+    fn set_local_ime(&mut self, v: bool) {
+        unsafe {
+            LOCAL_IME = v;
+        }
+    }
+
+    // the keys of the view in the local input method mode, except those taken by the input method
+    fn send_key_event(
+        &mut self,
+        down_or_up: i32,
+        name: String,
+        code: i32,
+        alt: bool,
+        ctrl: bool,
+        shift: bool,
+        command: bool,
+    ) {
+        // VK_PROCESSKEY, the key is taken by the input method
+        if cfg!(windows) && code == 0xE5 {
+            return;
+        }
+        if let Some(key_event) = self.get_key_event(down_or_up, &name, code) {
+            self.key_down_or_up(down_or_up, key_event, alt, ctrl, shift, command);
+        }
+    }
+
+    // the text of the local input method, replaced on the peer as it changes until committed
+    fn send_ime_input(&mut self, text: String, commit: bool) {
+        let mut ime = ImeInput::new();
+        if commit {
+            ime.set_commit(text);
+        } else {
+            ime.set_composition(text);
+        }
+        let mut msg_out = Message::new();
+        msg_out.set_ime_input(ime);
+        self.send(Data::Message(msg_out));
+    }
+
+    // the peer aligns its lock keys to ours, on the session start and when we get back the focus
+    fn send_lock_keys(&mut self) {
+        if self.is_file_transfer() || self.is_port_forward() || !unsafe { SERVER_KEYBOARD_ENABLED }
+        {
+            return;
+        }
+        let caps_lock = self.keymap.lock().unwrap().caps_lock();
+        let mut misc = Misc::new();
+        misc.set_lock_keys(LockKeys {
+            caps_lock: caps_lock.unwrap_or_else(|| get_key_state(enigo::Key::CapsLock)),
+            num_lock: get_key_state(enigo::Key::NumLock),
+            scroll_lock: get_key_state(enigo::Key::Scroll),
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
+    // pointer lock for the relative mouse mode, the cursor is put back after each move
+    fn move_local_cursor(&mut self, x: i32, y: i32) {
+        ENIGO.lock().unwrap().mouse_move_to(x, y);
+    }
+
     fn send_mouse(
         &mut self,
         mask: i32,
@@ -917,6 +1203,7 @@ impl Handler {
         }
         msg_out.set_mouse_event(mouse_event);
         self.send(Data::Message(msg_out));
+        self.record_mouse(mask, x, y);
         // on macos, ctrl + left = right, up wont emit, so we need to
         // emit up myself if peer is not macos
         // to-do: how about ctrl + left from win to macos
@@ -929,6 +1216,76 @@ impl Handler {
         }
     }
 
+    // dx and dy in notches, fractional for the high-resolution wheels and touchpads
+    fn send_wheel(&mut self, dx: f64, dy: f64, alt: bool, ctrl: bool, shift: bool, command: bool) {
+        let mut mouse_event = MouseEvent {
+            mask: 3,
+            wheel_x: dx as _,
+            wheel_y: dy as _,
+            ..Default::default()
+        };
+        for (on, key) in [
+            (alt, ControlKey::Alt),
+            (shift, ControlKey::Shift),
+            (ctrl, ControlKey::Control),
+            (command, ControlKey::Meta),
+        ] {
+            if on {
+                mouse_event.modifiers.push(key.into());
+            }
+        }
+        let mut msg_out = Message::new();
+        msg_out.set_mouse_event(mouse_event);
+        self.send(Data::Message(msg_out));
+    }
+
+    // points: [[id, x, y, pressure], ...], all the contacts currently down
+    fn send_touch(&mut self, points: Value) {
+        let mut touch_event = TouchEvent::new();
+        for i in 0..points.len() {
+            let p = points.get(i);
+            touch_event.points.push(TouchPoint {
+                id: p.get(0).to_int().unwrap_or(0),
+                x: p.get(1).to_int().unwrap_or(0),
+                y: p.get(2).to_int().unwrap_or(0),
+                pressure: p.get(3).to_float().unwrap_or(1.) as _,
+                ..Default::default()
+            });
+        }
+        let mut msg_out = Message::new();
+        msg_out.set_touch_event(touch_event);
+        self.send(Data::Message(msg_out));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn send_pen(
+        &mut self,
+        x: i32,
+        y: i32,
+        pressure: f64,
+        tilt_x: i32,
+        tilt_y: i32,
+        eraser: bool,
+        touching: bool,
+        in_range: bool,
+        buttons: i32,
+    ) {
+        let mut msg_out = Message::new();
+        msg_out.set_pen_event(PenEvent {
+            x,
+            y,
+            pressure: pressure as _,
+            tilt_x,
+            tilt_y,
+            eraser,
+            touching,
+            in_range,
+            buttons: buttons as _,
+            ..Default::default()
+        });
+        self.send(Data::Message(msg_out));
+    }
+
     fn set_cursor_data(&mut self, cd: CursorData) {
         let mut colors = hbb_common::compress::decompress(&cd.colors);
         if colors.iter().filter(|x| **x != 0).next().is_none() {
@@ -1089,6 +1446,191 @@ impl Handler {
         self.key_down_or_up(1, key_event, false, false, false, false);
     }
 
+    // for the consoles not accepting paste
+    fn type_clipboard(&mut self, delay: i32) -> bool {
+        let text = match ClipboardContext::new().and_then(|mut ctx| ctx.get_text()) {
+            Ok(text) => text,
+            Err(err) => {
+                log::error!("Failed to get clipboard text: {}", err);
+                return false;
+            }
+        };
+        if text.is_empty() {
+            return false;
+        }
+        self.set_option("type-text-delay".to_owned(), delay.to_string());
+        let mut msg_out = Message::new();
+        msg_out.set_type_text(TypeText {
+            text,
+            delay: delay.max(0) as _,
+            ..Default::default()
+        });
+        self.send(Data::Message(msg_out));
+        true
+    }
+
+    fn cancel_type_text(&mut self) {
+        let mut msg_out = Message::new();
+        msg_out.set_type_text(TypeText {
+            cancel: true,
+            ..Default::default()
+        });
+        self.send(Data::Message(msg_out));
+    }
+
+    fn cancel_clipboard(&mut self) {
+        self.send(Data::CancelClipboard);
+    }
+
+    fn start_macro_recording(&mut self) {
+        *self.recorder.lock().unwrap() = Some(dsl::Recorder::new());
+    }
+
+    // the recorded dsl, empty if nothing recorded
+    fn stop_macro_recording(&mut self) -> String {
+        match self.recorder.lock().unwrap().take() {
+            Some(recorder) => dsl::format(recorder.tokens()),
+            None => String::new(),
+        }
+    }
+
+    fn get_macros(&mut self) -> Value {
+        let mut names: Vec<String> = self.lc.read().unwrap().macros.keys().cloned().collect();
+        names.sort();
+        let mut v = Value::array(0);
+        for name in names {
+            v.push(name);
+        }
+        v
+    }
+
+    fn get_macro(&mut self, name: String) -> String {
+        self.lc
+            .read()
+            .unwrap()
+            .macros
+            .get(&name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // empty dsl removes the macro, returns the parse error
+    fn save_macro(&mut self, name: String, text: String) -> String {
+        let mut config = self.load_config();
+        if text.is_empty() {
+            config.macros.remove(&name);
+        } else if let Err(err) = dsl::tokenize(&text) {
+            return err.to_string();
+        } else {
+            config.macros.insert(name, text);
+        }
+        self.save_config(config);
+        "".to_owned()
+    }
+
+    fn get_keymap(&mut self) -> String {
+        keymap::format(&self.lc.read().unwrap().keymap)
+    }
+
+    // returns the parse error
+    fn save_keymap(&mut self, text: String) -> String {
+        let rules = match keymap::parse(&text) {
+            Ok(rules) => rules,
+            Err(err) => return err.to_string(),
+        };
+        *self.keymap.lock().unwrap() = Keymap::new(&rules);
+        let mut config = self.load_config();
+        config.keymap = rules;
+        self.save_config(config);
+        "".to_owned()
+    }
+
+    fn get_keymap_presets(&mut self) -> Value {
+        let mut v = Value::array(0);
+        for (name, _) in keymap::PRESETS {
+            v.push(*name);
+        }
+        v
+    }
+
+    fn get_keymap_preset(&mut self, name: String) -> String {
+        keymap::PRESETS
+            .iter()
+            .find(|x| x.0 == name)
+            .map(|x| x.1.to_owned())
+            .unwrap_or_default()
+    }
+
+    fn import_keymap(&mut self, path: String) -> String {
+        match std::fs::read_to_string(&path) {
+            Ok(text) => self.save_keymap(text),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn export_keymap(&mut self, path: String) -> String {
+        match std::fs::write(&path, self.get_keymap() + "\n") {
+            Ok(_) => "".to_owned(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn play_macro(&mut self, name: String) -> bool {
+        let text = self.get_macro(name);
+        if text.is_empty() {
+            return false;
+        }
+        let mut msg_out = Message::new();
+        msg_out.set_play_macro(PlayMacro {
+            dsl: text,
+            ..Default::default()
+        });
+        self.send(Data::Message(msg_out));
+        true
+    }
+
+    // false to release the control or withdraw the request
+    fn request_control(&mut self, request: bool) {
+        let mut misc = Misc::new();
+        misc.set_request_control(request);
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
+    fn grant_control(&mut self, conn_id: i32) {
+        let mut misc = Misc::new();
+        misc.set_grant_control(conn_id);
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
+    fn record_mouse(&self, mask: i32, x: i32, y: i32) {
+        let mut lock = self.recorder.lock().unwrap();
+        let recorder = match lock.as_mut() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let button = match mask >> 3 {
+            1 => Some(enigo::MouseButton::Left),
+            2 => Some(enigo::MouseButton::Right),
+            4 => Some(enigo::MouseButton::Middle),
+            _ => None,
+        };
+        let token = match (mask & 0x7, button) {
+            (0, _) => dsl::Token::MouseMove(x, y),
+            (4, _) => dsl::Token::MouseMoveRelative(x, y),
+            (1, Some(button)) => dsl::Token::MouseDown(button),
+            (2, Some(button)) => dsl::Token::MouseUp(button),
+            // the peer flips the wheel except on Windows
+            (3, _) if self.peer_platform() == "Windows" => dsl::Token::Scroll(x, y),
+            (3, _) => dsl::Token::Scroll(-x, -y),
+            _ => return,
+        };
+        recorder.push(token);
+    }
+
     fn transfer_file(&mut self) {
         let id = self.get_id();
         let args = vec!["--file-transfer", &id];
@@ -1140,7 +1682,18 @@ impl Handler {
         {
             key_event.modifiers.push(ControlKey::Meta.into());
         }
-        if get_key_state(enigo::Key::CapsLock) {
+        if down_or_up == 1 {
+            key_event.down = true;
+        } else if down_or_up == 3 {
+            key_event.press = true;
+        }
+        let mut keymap = self.keymap.lock().unwrap();
+        keymap.apply(&mut key_event);
+        let caps_lock = keymap
+            .caps_lock()
+            .unwrap_or_else(|| get_key_state(enigo::Key::CapsLock));
+        drop(keymap);
+        if caps_lock {
             key_event.modifiers.push(ControlKey::CapsLock.into());
         }
         if self.peer_platform() != "Mac OS" {
@@ -1148,10 +1701,10 @@ impl Handler {
                 key_event.modifiers.push(ControlKey::NumLock.into());
             }
         }
-        if down_or_up == 1 {
-            key_event.down = true;
-        } else if down_or_up == 3 {
-            key_event.press = true;
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+            if let Some(token) = get_key_token(&key_event) {
+                recorder.push(token);
+            }
         }
         let mut msg_out = Message::new();
         msg_out.set_key_event(key_event);
@@ -1272,13 +1825,16 @@ async fn io_loop(handler: Handler) {
         }
         return;
     }
-    let (video_sender, audio_sender) = start_video_audio_threads(|data: &[u8]| {
-        VIDEO
-            .lock()
-            .unwrap()
-            .as_mut()
-            .map(|v| v.render_frame(data).ok());
-    });
+    let (video_sender, audio_sender) = start_video_audio_threads(
+        |data: &[u8]| {
+            VIDEO
+                .lock()
+                .unwrap()
+                .as_mut()
+                .map(|v| v.render_frame(data).ok());
+        },
+        handler.audio_stats.clone(),
+    );
 
     let mut remote = Remote {
         handler,
@@ -1287,13 +1843,19 @@ async fn io_loop(handler: Handler) {
         receiver,
         sender,
         old_clipboard: Default::default(),
+        old_primary: Default::default(),
         read_jobs: Vec::new(),
         write_jobs: Vec::new(),
         remove_jobs: Default::default(),
+        sync_jobs: Default::default(),
+        conflict_jobs: Default::default(),
         timer: time::interval(SEC30),
         last_update_jobs_status: (Instant::now(), Default::default()),
         first_frame: false,
-        #[cfg(windows)]
+        clipboard_chunks: Default::default(),
+        clipboard_progress: (false, -1),
+        failed_jobs: Default::default(),
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
         clipboard_file_context: None,
     };
     remote.io_loop().await;
@@ -1321,20 +1883,48 @@ impl RemoveJob {
     }
 }
 
+// a folder compared with the one at the other side, only the files changed are transferred
+struct SyncJob {
+    path: String,
+    to: String,
+    include_hidden: bool,
+    is_remote: bool,
+    delete_extras: bool,
+    dry_run: bool,
+    local: Vec<FileEntry>,
+    files: Vec<FileEntry>, // to be transferred once compared
+    removing: usize,       // the extra files at the peer not removed yet
+}
+
+// the files existing at the destination, asked one by one before the transfer starts
+struct ConflictJob {
+    names: Vec<String>,   // not answered yet
+    skipped: Vec<String>, // by the policy or the answers
+    offsets: Vec<FileTransferOffset>,
+    signatures: Vec<FileSignature>,
+    is_remote: bool,
+}
+
 struct Remote {
     handler: Handler,
     video_sender: MediaSender,
     audio_sender: MediaSender,
     receiver: mpsc::UnboundedReceiver<Data>,
     sender: mpsc::UnboundedSender<Data>,
-    old_clipboard: Arc<Mutex<String>>,
+    old_clipboard: Arc<Mutex<ClipboardContent>>,
+    old_primary: Arc<Mutex<ClipboardContent>>,
     read_jobs: Vec<fs::TransferJob>,
     write_jobs: Vec<fs::TransferJob>,
     remove_jobs: HashMap<i32, RemoveJob>,
+    sync_jobs: HashMap<i32, SyncJob>,
+    conflict_jobs: HashMap<i32, ConflictJob>,
     timer: Interval,
     last_update_jobs_status: (Instant, HashMap<i32, u64>),
     first_frame: bool,
-    #[cfg(windows)]
+    clipboard_chunks: ClipboardChunks,
+    clipboard_progress: (bool, i32), // (incoming, percent) last shown
+    failed_jobs: HashSet<i32>,       // the transfer jobs to be retried once done
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
     clipboard_file_context: Option<Box<CliprdrClientContext>>,
 }
 
@@ -1358,10 +1948,12 @@ impl Remote {
                     .call("setConnectionType", &make_args!(peer.is_secured(), direct));
 
                 // just build for now
-                #[cfg(not(windows))]
+                #[cfg(not(any(target_os = "windows", target_os = "linux")))]
                 let (_tx_holder, mut rx_clip_client) = mpsc::unbounded_channel::<i32>();
-                #[cfg(windows)]
+                #[cfg(any(target_os = "windows", target_os = "linux"))]
                 let mut rx_clip_client = get_rx_clip_client().lock().await;
+                let mut clipboard_chunk_timer =
+                    time::interval(Duration::from_millis(CLIPBOARD_CHUNK_INTERVAL));
 
                 loop {
                     tokio::select! {
@@ -1394,7 +1986,7 @@ impl Remote {
                             }
                         }
                         _msg = rx_clip_client.recv() => {
-                            #[cfg(windows)]
+                            #[cfg(any(target_os = "windows", target_os = "linux"))]
                             match _msg {
                                 Some((_, clip)) => {
                                     allow_err!(peer.send(&clip_2_msg(clip)).await);
@@ -1404,6 +1996,12 @@ impl Remote {
                                 }
                             }
                         }
+                        _ = clipboard_chunk_timer.tick() => {
+                            if let Some((msg, progress)) = self.clipboard_chunks.next() {
+                                allow_err!(peer.send(&msg).await);
+                                self.update_clipboard_progress(false, progress);
+                            }
+                        }
                         _ = self.timer.tick() => {
                             if last_recv_time.elapsed() >= SEC30 {
                                 self.handler.msgbox("error", "Connection Error", "Timeout");
@@ -1421,6 +2019,7 @@ impl Remote {
                         }
                     }
                 }
+                self.save_transfer_progress();
                 log::debug!("Exit io_loop of id={}", self.handler.id);
             }
             Err(err) => {
@@ -1472,12 +2071,16 @@ impl Remote {
         }
         let (tx, rx) = std::sync::mpsc::channel();
         let old_clipboard = self.old_clipboard.clone();
+        #[cfg(target_os = "linux")]
+        let old_primary = self.old_primary.clone();
         let tx_protobuf = self.sender.clone();
         let lc = self.handler.lc.clone();
         match ClipboardContext::new() {
             Ok(mut ctx) => {
                 // ignore clipboard update before service start
                 check_clipboard(&mut ctx, Some(&old_clipboard));
+                #[cfg(target_os = "linux")]
+                let mut pending_primary = None;
                 std::thread::spawn(move || loop {
                     std::thread::sleep(Duration::from_millis(CLIPBOARD_INTERVAL));
                     match rx.try_recv() {
@@ -1496,6 +2099,18 @@ impl Remote {
                     if let Some(msg) = check_clipboard(&mut ctx, Some(&old_clipboard)) {
                         tx_protobuf.send(Data::Message(msg)).ok();
                     }
+                    #[cfg(target_os = "linux")]
+                    if lc
+                        .read()
+                        .unwrap()
+                        .get_toggle_option("sync-primary-selection")
+                    {
+                        if let Some(msg) =
+                            check_primary_selection(&mut ctx, &old_primary, &mut pending_primary)
+                        {
+                            tx_protobuf.send(Data::Message(msg)).ok();
+                        }
+                    }
                 });
             }
             Err(err) => {
@@ -1519,20 +2134,48 @@ impl Remote {
                 self.check_clipboard_file_context();
             }
             Data::Message(msg) => {
-                allow_err!(peer.send(&msg).await);
+                if self.clipboard_chunks.push(&msg) {
+                    self.update_clipboard_progress(false, 0);
+                } else {
+                    allow_err!(peer.send(&msg).await);
+                }
             }
-            Data::SendFiles((id, path, to, include_hidden, is_remote)) => {
+            Data::CancelClipboard => {
+                if let Some(msg) = self.clipboard_chunks.abort_sending() {
+                    allow_err!(peer.send(&msg).await);
+                }
+                if let Some(msg) = self.clipboard_chunks.reject_receiving() {
+                    allow_err!(peer.send(&msg).await);
+                }
+                self.update_clipboard_progress(self.clipboard_progress.0, -1);
+            }
+            Data::SendFiles((id, path, to, include_hidden, is_remote, policy)) => {
                 if is_remote {
                     log::debug!("New job {}, write to {} from remote {}", id, to, path);
-                    self.write_jobs
-                        .push(fs::TransferJob::new_write(id, to, Vec::new()));
-                    allow_err!(peer.send(&fs::new_send(id, path, include_hidden)).await);
+                    self.save_transfer_job(id, &path, &to, include_hidden, is_remote, &[]);
+                    let policy = self.handler.lc.read().unwrap().get_conflict_policy(&policy);
+                    let mut job = fs::TransferJob::new_write(id, to, Vec::new());
+                    job.set_conflict_policy(policy);
+                    self.write_jobs.push(job);
+                    let delta = self.handler.lc.read().unwrap().features.file_delta;
+                    allow_err!(
+                        peer.send(&fs::new_send(
+                            id,
+                            path,
+                            include_hidden,
+                            Vec::new(),
+                            delta,
+                            Vec::new(),
+                            policy
+                        ))
+                        .await
+                    );
                 } else {
                     match fs::TransferJob::new_read(id, path.clone(), include_hidden) {
                         Err(err) => {
                             self.handle_job_status(id, -1, Some(err.to_string()));
                         }
-                        Ok(job) => {
+                        Ok(mut job) => {
                             log::debug!(
                                 "New job {}, read {} to remote {}, {} files",
                                 id,
@@ -1543,13 +2186,143 @@ impl Remote {
                             let m = make_fd(job.id(), job.files(), true);
                             self.handler.call("updateFolderFiles", &make_args!(m));
                             let files = job.files().clone();
+                            self.save_transfer_job(
+                                id,
+                                &path,
+                                &to,
+                                include_hidden,
+                                is_remote,
+                                &files,
+                            );
+                            let delta = self.handler.lc.read().unwrap().features.file_delta;
+                            let policy =
+                                self.handler.lc.read().unwrap().get_conflict_policy(&policy);
+                            if delta || policy != FileConflictPolicy::Overwrite {
+                                job.wait_offsets();
+                            }
                             self.read_jobs.push(job);
                             self.timer = time::interval(MILLI1);
-                            allow_err!(peer.send(&fs::new_receive(id, to, files)).await);
+                            allow_err!(
+                                peer.send(&fs::new_receive(id, to, files, false, delta, policy))
+                                    .await
+                            );
                         }
                     }
                 }
             }
+            Data::ResumeJob((id, path, to, include_hidden, is_remote, policy)) => {
+                let meta = self
+                    .handler
+                    .lc
+                    .read()
+                    .unwrap()
+                    .get_transfer_jobs()
+                    .into_iter()
+                    .find(|j| j.is_same_job(is_remote, &path, &to));
+                let mut meta = match meta {
+                    Some(meta) => meta,
+                    None => {
+                        self.handle_job_status(id, -1, Some("Not found".to_owned()));
+                        return true;
+                    }
+                };
+                meta.id = id;
+                self.handler
+                    .lc
+                    .write()
+                    .unwrap()
+                    .save_transfer_job(meta.clone());
+                if is_remote {
+                    log::debug!("Resume job {}, write to {} from remote {}", id, to, path);
+                    let offsets = fs::get_resume_offsets(&to, &meta.files());
+                    let policy = self.handler.lc.read().unwrap().get_conflict_policy(&policy);
+                    let mut job = fs::TransferJob::new_write(id, to, Vec::new());
+                    job.set_conflict_policy(policy);
+                    self.write_jobs.push(job);
+                    let delta = self.handler.lc.read().unwrap().features.file_delta;
+                    allow_err!(
+                        peer.send(&fs::new_send(
+                            id,
+                            path,
+                            include_hidden,
+                            offsets,
+                            delta,
+                            Vec::new(),
+                            policy
+                        ))
+                        .await
+                    );
+                } else {
+                    match fs::TransferJob::new_read(id, path.clone(), include_hidden) {
+                        Err(err) => {
+                            self.handle_job_status(id, -1, Some(err.to_string()));
+                        }
+                        Ok(mut job) => {
+                            log::debug!("Resume job {}, read {} to remote {}", id, path, to);
+                            let m = make_fd(job.id(), job.files(), true);
+                            self.handler.call("updateFolderFiles", &make_args!(m));
+                            let files = job.files().clone();
+                            job.wait_offsets();
+                            self.read_jobs.push(job);
+                            self.timer = time::interval(MILLI1);
+                            let delta = self.handler.lc.read().unwrap().features.file_delta;
+                            let policy =
+                                self.handler.lc.read().unwrap().get_conflict_policy(&policy);
+                            allow_err!(
+                                peer.send(&fs::new_receive(id, to, files, true, delta, policy))
+                                    .await
+                            );
+                        }
+                    }
+                }
+            }
+            Data::SyncFiles((id, path, to, include_hidden, is_remote, delete_extras, dry_run)) => {
+                log::debug!(
+                    "New sync job {}, {} to {}, remote: {}",
+                    id,
+                    path,
+                    to,
+                    is_remote
+                );
+                let (local_path, remote_path) = if is_remote {
+                    (to.clone(), path.clone())
+                } else {
+                    (path.clone(), to.clone())
+                };
+                let local = match fs::get_recursive_files(&local_path, include_hidden) {
+                    Ok(files) => files,
+                    // nothing at the destination yet
+                    Err(_) if is_remote => Vec::new(),
+                    Err(err) => {
+                        self.handle_job_status(id, -1, Some(err.to_string()));
+                        return true;
+                    }
+                };
+                let mut msg_out = Message::new();
+                let mut file_action = FileAction::new();
+                file_action.set_all_files(ReadAllFiles {
+                    id,
+                    path: remote_path,
+                    include_hidden,
+                    ..Default::default()
+                });
+                msg_out.set_file_action(file_action);
+                allow_err!(peer.send(&msg_out).await);
+                self.sync_jobs.insert(
+                    id,
+                    SyncJob {
+                        path,
+                        to,
+                        include_hidden,
+                        is_remote,
+                        delete_extras,
+                        dry_run,
+                        local,
+                        files: Vec::new(),
+                        removing: 0,
+                    },
+                );
+            }
             Data::SetNoConfirm(id) => {
                 if let Some(job) = self.remove_jobs.get_mut(&id) {
                     job.no_confirm = true;
@@ -1610,6 +2383,34 @@ impl Remote {
                 }
                 fs::remove_job(id, &mut self.read_jobs);
                 self.remove_jobs.remove(&id);
+                self.sync_jobs.remove(&id);
+                self.conflict_jobs.remove(&id);
+                self.handler.lc.write().unwrap().remove_transfer_job(id);
+            }
+            Data::ResolveConflict((id, overwrite, apply_all)) => {
+                let mut job = match self.conflict_jobs.remove(&id) {
+                    Some(job) => job,
+                    None => return true,
+                };
+                let n = if apply_all { job.names.len() } else { 1 };
+                let names: Vec<_> = job.names.drain(..n.min(job.names.len())).collect();
+                if !overwrite {
+                    job.skipped.extend(names);
+                }
+                if !job.names.is_empty() {
+                    self.ask_conflict(id, job);
+                } else if job.is_remote {
+                    if let Some(write_job) = fs::get_job(id, &mut self.write_jobs) {
+                        write_job.skip_files(&job.skipped);
+                        allow_err!(
+                            peer.send(&fs::new_offsets_action(id, job.signatures, job.skipped))
+                                .await
+                        );
+                    }
+                } else if let Some(read_job) = fs::get_job(id, &mut self.read_jobs) {
+                    read_job.skip_files(&job.skipped);
+                    read_job.set_offsets(job.offsets);
+                }
             }
             Data::RemoveDir((id, path)) => {
                 let mut msg_out = Message::new();
@@ -1723,6 +2524,241 @@ impl Remote {
         }
     }
 
+    fn handle_transfer_error(&mut self, id: i32, file_num: i32, err: String) {
+        let lc = self.handler.lc.read().unwrap();
+        if file_num >= 0 && lc.transfer_jobs.iter().any(|j| j.id == id) {
+            self.failed_jobs.insert(id);
+        }
+        drop(lc);
+        self.handle_job_status(id, file_num, Some(err));
+    }
+
+    fn save_transfer_job(
+        &mut self,
+        id: i32,
+        path: &str,
+        to: &str,
+        include_hidden: bool,
+        is_remote: bool,
+        files: &[FileEntry],
+    ) {
+        let mut job = fs::TransferJobMeta {
+            id,
+            path: path.to_owned(),
+            to: to.to_owned(),
+            include_hidden,
+            is_remote,
+            ..Default::default()
+        };
+        job.set_files(files);
+        self.handler.lc.write().unwrap().save_transfer_job(job);
+    }
+
+    // the next file of the job existing at the destination, asked to be overwritten or skipped
+    fn ask_conflict(&mut self, id: i32, job: ConflictJob) {
+        if let Some(name) = job.names.first() {
+            let name = if name.is_empty() {
+                // the single file transferred
+                self.read_jobs
+                    .iter()
+                    .chain(self.write_jobs.iter())
+                    .find(|j| j.id() == id)
+                    .map(|j| fs::get_file_name(&fs::get_path(&j.path())))
+                    .unwrap_or_default()
+            } else {
+                name.clone()
+            };
+            self.handler.call(
+                "confirmOverwrite",
+                &make_args!(id, name, job.names.len() as i32),
+            );
+        }
+        self.conflict_jobs.insert(id, job);
+    }
+
+    // the files at the peer listed, compared with the local ones
+    async fn handle_sync_listed(
+        &mut self,
+        id: i32,
+        remote: Result<Vec<FileEntry>, String>,
+        peer: &mut Stream,
+    ) {
+        let mut job = match self.sync_jobs.remove(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        let remote = match remote {
+            Ok(files) => files,
+            // nothing at the destination yet
+            Err(_) if !job.is_remote => Vec::new(),
+            Err(err) => {
+                self.handle_job_status(id, -1, Some(err));
+                return;
+            }
+        };
+        let local = std::mem::take(&mut job.local);
+        let (src, dst) = if job.is_remote {
+            (remote, local)
+        } else {
+            (local, remote)
+        };
+        let (files, mut extras) = fs::get_sync_files(&src, &dst);
+        if !job.delete_extras {
+            extras.clear();
+        }
+        if job.dry_run {
+            let names = |files: &Vec<FileEntry>| {
+                let mut a = Value::array(0);
+                for f in files {
+                    a.push(f.name.clone());
+                }
+                a
+            };
+            self.handler.call(
+                "showSyncFiles",
+                &make_args!(id, names(&files), names(&extras)),
+            );
+            return;
+        }
+        job.files = files;
+        if job.is_remote {
+            for f in extras.iter() {
+                let path = fs::get_path(&job.to).join(&f.name);
+                allow_err!(fs::remove_file(&fs::get_string(&path)));
+            }
+        } else if !extras.is_empty() {
+            let sep = self.handler.get_path_sep(true);
+            for (i, f) in extras.iter().enumerate() {
+                let mut msg_out = Message::new();
+                let mut file_action = FileAction::new();
+                file_action.set_remove_file(FileRemoveFile {
+                    id,
+                    path: format!("{}{}{}", job.to, sep, f.name),
+                    file_num: i as _,
+                    ..Default::default()
+                });
+                msg_out.set_file_action(file_action);
+                allow_err!(peer.send(&msg_out).await);
+            }
+            // the transfer starts once removed
+            job.removing = extras.len();
+            self.sync_jobs.insert(id, job);
+            return;
+        }
+        self.start_sync(id, job, peer).await;
+    }
+
+    async fn handle_sync_removed(&mut self, id: i32, err: Option<String>, peer: &mut Stream) {
+        if let Some(err) = err {
+            log::error!(
+                "Failed to remove the extra file of sync job {}: {}",
+                id,
+                err
+            );
+        }
+        if let Some(job) = self.sync_jobs.get_mut(&id) {
+            job.removing = job.removing.saturating_sub(1);
+            if job.removing == 0 {
+                if let Some(job) = self.sync_jobs.remove(&id) {
+                    self.start_sync(id, job, peer).await;
+                }
+            }
+        }
+    }
+
+    // saved as the other transfers to be resumed
+    async fn start_sync(&mut self, id: i32, job: SyncJob, peer: &mut Stream) {
+        if job.files.is_empty() {
+            let m = make_fd(id, &job.files, true);
+            self.handler.call("updateFolderFiles", &make_args!(m));
+            return;
+        }
+        let delta = self.handler.lc.read().unwrap().features.file_delta;
+        if job.is_remote {
+            self.save_transfer_job(id, &job.path, &job.to, job.include_hidden, true, &[]);
+            self.write_jobs
+                .push(fs::TransferJob::new_write(id, job.to, Vec::new()));
+            let files = job.files.into_iter().map(|f| f.name).collect();
+            allow_err!(
+                peer.send(&fs::new_send(
+                    id,
+                    job.path,
+                    job.include_hidden,
+                    Vec::new(),
+                    delta,
+                    files,
+                    FileConflictPolicy::Overwrite
+                ))
+                .await
+            );
+        } else {
+            match fs::TransferJob::new_read(id, job.path.clone(), job.include_hidden) {
+                Err(err) => {
+                    self.handle_job_status(id, -1, Some(err.to_string()));
+                }
+                Ok(mut read_job) => {
+                    read_job.set_files(job.files);
+                    let m = make_fd(id, read_job.files(), true);
+                    self.handler.call("updateFolderFiles", &make_args!(m));
+                    let files = read_job.files().clone();
+                    self.save_transfer_job(
+                        id,
+                        &job.path,
+                        &job.to,
+                        job.include_hidden,
+                        false,
+                        &files,
+                    );
+                    if delta {
+                        read_job.wait_offsets();
+                    }
+                    self.read_jobs.push(read_job);
+                    self.timer = time::interval(MILLI1);
+                    allow_err!(
+                        peer.send(&fs::new_receive(
+                            id,
+                            job.to,
+                            files,
+                            false,
+                            delta,
+                            FileConflictPolicy::Overwrite
+                        ))
+                        .await
+                    );
+                }
+            }
+        }
+    }
+
+    // the progress of the unfinished jobs, to resume them after reconnecting
+    fn save_transfer_progress(&mut self) {
+        let mut lc = self.handler.lc.write().unwrap();
+        for job in self.read_jobs.iter().chain(self.write_jobs.iter()) {
+            lc.update_transfer_job(job.id(), |j| {
+                j.file_num = job.file_num();
+                j.offset = job.offset();
+            });
+        }
+    }
+
+    fn update_clipboard(&self, cb: Clipboard) {
+        let old = if cb.selection.enum_value() == Ok(ClipboardSelection::Primary) {
+            &self.old_primary
+        } else {
+            &self.old_clipboard
+        };
+        update_clipboard(cb, Some(old));
+    }
+
+    // the percent is negative once done or cancelled
+    fn update_clipboard_progress(&mut self, incoming: bool, percent: i32) {
+        if self.clipboard_progress != (incoming, percent) {
+            self.clipboard_progress = (incoming, percent);
+            self.handler
+                .call("updateClipboardProgress", &make_args!(incoming, percent));
+        }
+    }
+
     async fn handle_msg_from_peer(&mut self, data: &[u8], peer: &mut Stream) -> bool {
         if let Ok(msg_in) = Message::parse_from_bytes(&data) {
             match msg_in.union {
@@ -1746,15 +2782,26 @@ impl Remote {
                     Some(login_response::Union::peer_info(pi)) => {
                         self.handler.handle_peer_info(pi);
                         self.check_clipboard_file_context();
+                        if self.handler.is_file_transfer()
+                            && !self
+                                .handler
+                                .lc
+                                .read()
+                                .unwrap()
+                                .get_transfer_jobs()
+                                .is_empty()
+                        {
+                            self.handler.call("showTransferJobs", &make_args!(false));
+                        }
                         if !(self.handler.is_file_transfer()
                             || self.handler.is_port_forward()
                             || !unsafe { SERVER_CLIPBOARD_ENABLED }
                             || !unsafe { SERVER_KEYBOARD_ENABLED }
                             || self.handler.lc.read().unwrap().disable_clipboard)
                         {
-                            let txt = self.old_clipboard.lock().unwrap().clone();
-                            if !txt.is_empty() {
-                                let msg_out = crate::create_clipboard_msg(txt);
+                            let content = self.old_clipboard.lock().unwrap().clone();
+                            if !content.content.is_empty() {
+                                let msg_out = crate::create_clipboard_msg(content);
                                 let sender = self.sender.clone();
                                 tokio::spawn(async move {
                                     // due to clipboard service interval time
@@ -1777,10 +2824,31 @@ impl Remote {
                 }
                 Some(message::Union::clipboard(cb)) => {
                     if !self.handler.lc.read().unwrap().disable_clipboard {
-                        update_clipboard(cb, Some(&self.old_clipboard));
+                        self.update_clipboard(cb);
                     }
                 }
-                #[cfg(windows)]
+                Some(message::Union::clipboard_chunk(chunk)) => {
+                    if !self.handler.lc.read().unwrap().disable_clipboard {
+                        let reject = chunk.reject;
+                        match self.clipboard_chunks.receive(chunk) {
+                            Ok(Some(cb)) => self.update_clipboard(cb),
+                            Ok(None) => {}
+                            Err(err) => {
+                                log::error!("{}", err);
+                                if let Some(msg) = self.clipboard_chunks.reject_receiving() {
+                                    allow_err!(peer.send(&msg).await);
+                                }
+                            }
+                        }
+                        if reject {
+                            self.update_clipboard_progress(false, -1);
+                        } else {
+                            let progress = self.clipboard_chunks.receiving_progress();
+                            self.update_clipboard_progress(true, progress.unwrap_or(-1));
+                        }
+                    }
+                }
+                #[cfg(any(target_os = "windows", target_os = "linux"))]
                 Some(message::Union::cliprdr(clip)) => {
                     if !self.handler.lc.read().unwrap().disable_clipboard {
                         if let Some(context) = &mut self.clipboard_file_context {
@@ -1791,6 +2859,10 @@ impl Remote {
                     }
                 }
                 Some(message::Union::file_response(fr)) => match fr.union {
+                    Some(file_response::Union::dir(fd)) if self.sync_jobs.contains_key(&fd.id) => {
+                        self.handle_sync_listed(fd.id, Ok(fd.entries.to_vec()), peer)
+                            .await;
+                    }
                     Some(file_response::Union::dir(fd)) => {
                         let entries = fd.entries.to_vec();
                         let mut m = make_fd(fd.id, &entries, fd.id > 0);
@@ -1799,28 +2871,136 @@ impl Remote {
                         }
                         self.handler.call("updateFolderFiles", &make_args!(m));
                         if let Some(job) = fs::get_job(fd.id, &mut self.write_jobs) {
+                            // the files first read are kept when resumed
+                            self.handler
+                                .lc
+                                .write()
+                                .unwrap()
+                                .update_transfer_job(fd.id, |j| {
+                                    if j.files.is_empty() {
+                                        j.set_files(&entries);
+                                    }
+                                });
                             job.set_files(entries);
+                            // the sender waits for the signatures and the files skipped
+                            // if asked in the request
+                            let delta = self.handler.lc.read().unwrap().features.file_delta;
+                            if delta || job.conflict_policy() != FileConflictPolicy::Overwrite {
+                                let (skipped, conflicts) = job.resolve_conflicts();
+                                let signatures = if delta {
+                                    let path = job.path();
+                                    let files: Vec<_> = job
+                                        .files()
+                                        .iter()
+                                        .filter(|f| !skipped.contains(&f.name))
+                                        .cloned()
+                                        .collect();
+                                    spawn_blocking(move || fs::get_signatures(&path, &files))
+                                        .await
+                                        .unwrap_or_default()
+                                } else {
+                                    Vec::new()
+                                };
+                                job.set_signatures(signatures.clone());
+                                if conflicts.is_empty() {
+                                    allow_err!(
+                                        peer.send(&fs::new_offsets_action(
+                                            fd.id, signatures, skipped
+                                        ))
+                                        .await
+                                    );
+                                } else {
+                                    self.ask_conflict(
+                                        fd.id,
+                                        ConflictJob {
+                                            names: conflicts,
+                                            skipped,
+                                            offsets: Vec::new(),
+                                            signatures,
+                                            is_remote: true,
+                                        },
+                                    );
+                                }
+                            }
                         } else if let Some(job) = self.remove_jobs.get_mut(&fd.id) {
                             job.files = entries;
                         }
                     }
                     Some(file_response::Union::block(block)) => {
                         if let Some(job) = fs::get_job(block.id, &mut self.write_jobs) {
-                            if let Err(_err) = job.write(block, None).await {
-                                // to-do: add "skip" for writing job
+                            let (id, file_num) = (block.id, block.file_num);
+                            if let Err(err) = job.write(block, None).await {
+                                self.handle_transfer_error(id, file_num, err.to_string());
                             }
                             self.update_jobs_status();
                         }
                     }
+                    Some(file_response::Union::done(d)) if self.sync_jobs.contains_key(&d.id) => {
+                        self.handle_sync_removed(d.id, None, peer).await;
+                    }
                     Some(file_response::Union::done(d)) => {
                         if let Some(job) = fs::get_job(d.id, &mut self.write_jobs) {
                             job.modify_time();
                             fs::remove_job(d.id, &mut self.write_jobs);
                         }
+                        if self.failed_jobs.remove(&d.id) {
+                            // all sent, the files verified are skipped when retried
+                            self.handler
+                                .lc
+                                .write()
+                                .unwrap()
+                                .update_transfer_job(d.id, |j| {
+                                    j.id = 0;
+                                    j.file_num = j.files.len() as _;
+                                    j.offset = 0;
+                                });
+                            self.handler.call("showTransferJobs", &make_args!(true));
+                        } else {
+                            self.handler.lc.write().unwrap().remove_transfer_job(d.id);
+                        }
                         self.handle_job_status(d.id, d.file_num, None);
                     }
+                    Some(file_response::Union::offsets(o)) => {
+                        if let Some(job) = fs::get_job(o.id, &mut self.read_jobs) {
+                            let meta = self
+                                .handler
+                                .lc
+                                .read()
+                                .unwrap()
+                                .transfer_jobs
+                                .iter()
+                                .find(|j| j.id == o.id)
+                                .cloned();
+                            let offsets = meta
+                                .map(|meta| meta.check_offsets(o.offsets.to_vec()))
+                                .unwrap_or_default();
+                            job.set_signatures(o.signatures.to_vec());
+                            job.skip_files(&o.skipped);
+                            if o.conflicts.is_empty() {
+                                job.set_offsets(offsets);
+                            } else {
+                                self.ask_conflict(
+                                    o.id,
+                                    ConflictJob {
+                                        names: o.conflicts.to_vec(),
+                                        skipped: Vec::new(),
+                                        offsets,
+                                        signatures: Vec::new(),
+                                        is_remote: false,
+                                    },
+                                );
+                            }
+                        }
+                    }
+                    Some(file_response::Union::error(e)) if self.sync_jobs.contains_key(&e.id) => {
+                        if self.sync_jobs[&e.id].removing > 0 {
+                            self.handle_sync_removed(e.id, Some(e.error), peer).await;
+                        } else {
+                            self.handle_sync_listed(e.id, Err(e.error), peer).await;
+                        }
+                    }
                     Some(file_response::Union::error(e)) => {
-                        self.handle_job_status(e.id, e.file_num, Some(e.error));
+                        self.handle_transfer_error(e.id, e.file_num, e.error);
                     }
                     _ => {}
                 },
@@ -1831,6 +3011,26 @@ impl Remote {
                     Some(misc::Union::chat_message(c)) => {
                         self.handler.call("newMessage", &make_args!(c.text));
                     }
+                    Some(misc::Union::pointer_state(s)) => {
+                        self.handler
+                            .call("setPointerState", &make_args!(s.hidden, s.confined));
+                    }
+                    // the audio is shared, the source chosen by another viewer is kept
+                    Some(misc::Union::audio_source(s)) => {
+                        self.handler
+                            .call("setAudioSource", &make_args!(s.source_type.value(), s.name));
+                    }
+                    Some(misc::Union::audio_sources(sources)) => {
+                        let mut a = Value::array(0);
+                        for s in sources.sources.iter() {
+                            let mut e = Value::map();
+                            e.set_item("type", s.source_type.value());
+                            e.set_item("name", s.name.clone());
+                            e.set_item("description", s.description.clone());
+                            a.push(e);
+                        }
+                        self.handler.call("updateAudioSources", &make_args!(a));
+                    }
                     Some(misc::Union::permission_info(p)) => {
                         log::info!("Change permission {:?} -> {}", p.permission, p.enabled);
                         match p.permission.enum_value_or_default() {
@@ -1865,6 +3065,27 @@ impl Remote {
                             }
                         }
                     }
+                    Some(misc::Union::control_info(c)) => {
+                        self.handler
+                            .call2("setControl", &make_args!(c.peer_id, c.name, c.is_mine));
+                    }
+                    Some(misc::Union::lock_keys(lk)) => {
+                        self.handler.call(
+                            "setLockKeys",
+                            &make_args!(lk.caps_lock, lk.num_lock, lk.scroll_lock),
+                        );
+                    }
+                    Some(misc::Union::gamepad_rumble(r)) => {
+                        if let Some(tx) = self.handler.gamepad.lock().unwrap().as_ref() {
+                            tx.send(r).ok();
+                        }
+                    }
+                    Some(misc::Union::control_request(r)) => {
+                        self.handler.call2(
+                            "controlRequested",
+                            &make_args!(r.conn_id, r.peer_id, r.name),
+                        );
+                    }
                     Some(misc::Union::switch_display(s)) => {
                         self.handler.call("switchDisplay", &make_args!(s.display));
                         self.video_sender.send(MediaData::Reset).ok();
@@ -1906,7 +3127,7 @@ impl Remote {
     }
 
     fn check_clipboard_file_context(&mut self) {
-        #[cfg(windows)]
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
         {
             let enabled = unsafe { SERVER_FILE_TRANSFER_ENABLED }
                 && self.handler.lc.read().unwrap().enable_file_transfer;
@@ -1961,6 +3182,156 @@ fn make_fd(id: i32, entries: &Vec<FileEntry>, only_count: bool) -> Value {
     m
 }
 
+// the physical keys are not recorded, their codes depend on the peer platform
+fn get_key_token(evt: &KeyEvent) -> Option<dsl::Token> {
+    let key = match evt.union {
+        Some(key_event::Union::control_key(ck)) => {
+            crate::server::input_service::control_key_to_key(ck.value())?
+        }
+        Some(key_event::Union::chr(chr)) => enigo::Key::Layout(std::char::from_u32(chr)?),
+        Some(key_event::Union::unicode(chr)) if evt.down || evt.press => {
+            return Some(dsl::Token::Unicode(std::char::from_u32(chr)?.to_string()));
+        }
+        Some(key_event::Union::seq(ref seq)) if evt.down || evt.press => {
+            return Some(dsl::Token::Unicode(seq.clone()));
+        }
+        _ => return None,
+    };
+    Some(if evt.press {
+        match key {
+            enigo::Key::Layout(chr) => dsl::Token::Sequence(chr.to_string()),
+            _ => dsl::Token::KeyClick(key),
+        }
+    } else if evt.down {
+        dsl::Token::KeyDown(key)
+    } else {
+        dsl::Token::KeyUp(key)
+    })
+}
+
+fn get_hid_usage(key: rdev::Key, _scan_code: u32) -> Option<u32> {
+    use rdev::Key::*;
+    let usage = match key {
+        KeyA => 0x04,
+        KeyB => 0x05,
+        KeyC => 0x06,
+        KeyD => 0x07,
+        KeyE => 0x08,
+        KeyF => 0x09,
+        KeyG => 0x0a,
+        KeyH => 0x0b,
+        KeyI => 0x0c,
+        KeyJ => 0x0d,
+        KeyK => 0x0e,
+        KeyL => 0x0f,
+        KeyM => 0x10,
+        KeyN => 0x11,
+        KeyO => 0x12,
+        KeyP => 0x13,
+        KeyQ => 0x14,
+        KeyR => 0x15,
+        KeyS => 0x16,
+        KeyT => 0x17,
+        KeyU => 0x18,
+        KeyV => 0x19,
+        KeyW => 0x1a,
+        KeyX => 0x1b,
+        KeyY => 0x1c,
+        KeyZ => 0x1d,
+        Num1 => 0x1e,
+        Num2 => 0x1f,
+        Num3 => 0x20,
+        Num4 => 0x21,
+        Num5 => 0x22,
+        Num6 => 0x23,
+        Num7 => 0x24,
+        Num8 => 0x25,
+        Num9 => 0x26,
+        Num0 => 0x27,
+        Return => 0x28,
+        Escape => 0x29,
+        Backspace => 0x2a,
+        Tab => 0x2b,
+        Space => 0x2c,
+        Minus => 0x2d,
+        Equal => 0x2e,
+        LeftBracket => 0x2f,
+        RightBracket => 0x30,
+        BackSlash => 0x31,
+        SemiColon => 0x33,
+        Quote => 0x34,
+        BackQuote => 0x35,
+        Comma => 0x36,
+        Dot => 0x37,
+        Slash => 0x38,
+        CapsLock => 0x39,
+        F1 => 0x3a,
+        F2 => 0x3b,
+        F3 => 0x3c,
+        F4 => 0x3d,
+        F5 => 0x3e,
+        F6 => 0x3f,
+        F7 => 0x40,
+        F8 => 0x41,
+        F9 => 0x42,
+        F10 => 0x43,
+        F11 => 0x44,
+        F12 => 0x45,
+        PrintScreen => 0x46,
+        ScrollLock => 0x47,
+        Pause => 0x48,
+        Insert => 0x49,
+        Home => 0x4a,
+        PageUp => 0x4b,
+        Delete => 0x4c,
+        End => 0x4d,
+        PageDown => 0x4e,
+        RightArrow => 0x4f,
+        LeftArrow => 0x50,
+        DownArrow => 0x51,
+        UpArrow => 0x52,
+        NumLock => 0x53,
+        KpDivide => 0x54,
+        KpMultiply => 0x55,
+        KpMinus => 0x56,
+        KpPlus => 0x57,
+        KpReturn => 0x58,
+        Kp1 => 0x59,
+        Kp2 => 0x5a,
+        Kp3 => 0x5b,
+        Kp4 => 0x5c,
+        Kp5 => 0x5d,
+        Kp6 => 0x5e,
+        Kp7 => 0x5f,
+        Kp8 => 0x60,
+        Kp9 => 0x61,
+        Kp0 => 0x62,
+        KpDecimal => 0x63,
+        IntlBackslash => 0x64,
+        Apps => 0x65,
+        Hangul => 0x90,
+        Hanja => 0x91,
+        ControlLeft => 0xe0,
+        ShiftLeft => 0xe1,
+        Alt => 0xe2,
+        MetaLeft => 0xe3,
+        ControlRight => 0xe4,
+        ShiftRight => 0xe5,
+        AltGr => 0xe6,
+        MetaRight => 0xe7,
+        _ => return None,
+    };
+    // the virtual key codes of the character keys follow the layout on Windows,
+    // the scan code tells the position
+    #[cfg(windows)]
+    if crate::hid::is_layout_key(usage) {
+        if let Some(usage) = crate::hid::evdev_to_hid((_scan_code & 0xff) as _) {
+            return Some(usage);
+        }
+    }
+    Some(usage)
+}
+
 #[async_trait]
 impl Interface for Handler {
     fn msgbox(&self, msgtype: &str, title: &str, text: &str) {
@@ -1979,6 +3350,38 @@ impl Interface for Handler {
         pi_sciter.set_item("hostname", pi.hostname.clone());
         pi_sciter.set_item("platform", pi.platform.clone());
         pi_sciter.set_item("sas_enabled", pi.sas_enabled);
+        let mut features = Value::map();
+        features.set_item(
+            "relative_mouse",
+            pi.features
+                .as_ref()
+                .map(|x| x.relative_mouse)
+                .unwrap_or(false),
+        );
+        features.set_item(
+            "physical_keyboard",
+            pi.features
+                .as_ref()
+                .map(|x| x.physical_keyboard)
+                .unwrap_or(false),
+        );
+        features.set_item(
+            "touch",
+            pi.features.as_ref().map(|x| x.touch).unwrap_or(false),
+        );
+        features.set_item("pen", pi.features.as_ref().map(|x| x.pen).unwrap_or(false));
+        features.set_item(
+            "hires_scroll",
+            pi.features
+                .as_ref()
+                .map(|x| x.hires_scroll)
+                .unwrap_or(false),
+        );
+        features.set_item(
+            "gamepad",
+            pi.features.as_ref().map(|x| x.gamepad).unwrap_or(false),
+        );
+        pi_sciter.set_item("features", features);
         if self.is_file_transfer() {
             if pi.username.is_empty() {
                 self.on_error("No active console user logged on, please connect and logon first.");
@@ -2026,6 +3429,7 @@ impl Interface for Handler {
             self.call2("closeSuccess", &make_args!());
         } else if !self.is_port_forward() {
             self.msgbox("success", "Successful", "Connected, waiting for image...");
+            self.send_lock_keys();
         }
         #[cfg(windows)]
         {
@@ -2038,6 +3442,7 @@ impl Interface for Handler {
             }
         }
         self.start_keyboard_hook();
+        self.start_gamepad();
     }
 
     async fn handle_hash(&mut self, hash: Hash, peer: &mut Stream) {